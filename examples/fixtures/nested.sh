@@ -0,0 +1,14 @@
+set -e
+
+# Nested directories with duplicates at different depths. Useful for
+# testing the `--max-depth` option of the find command e.g.
+#
+#   --max-depth 0 => no duplicates
+#   --max-depth 1 => 1.txt, foo/1.txt
+#   --max-depth 2 => 1.txt, foo/1.txt, foo/bar/1.txt
+
+mkdir -p foo/bar/cat
+echo "ONE" > 1.txt
+cp 1.txt foo/
+cp 1.txt foo/bar/
+cp 1.txt foo/bar/cat/
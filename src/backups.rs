@@ -0,0 +1,210 @@
+use crate::error::AppError;
+use chrono::{Local, NaiveDateTime};
+use log::{debug, info};
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Format of the names of the (timestamped) backup dirs
+pub const DIRNAME_FORMAT: &str = "%Y%m%d%H%M%S";
+
+/// Backup dir created by the tool, named as per `DIRNAME_FORMAT`
+#[derive(Debug, PartialEq)]
+pub struct BackupDir {
+    pub path: PathBuf,
+    pub created_at: NaiveDateTime,
+}
+
+impl BackupDir {
+    /// Returns the backup dir if the path is a dir (and not a
+    /// symlink) whose name is a timestamp in `DIRNAME_FORMAT`
+    fn parse(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        // Timestamp parsing alone would accept fields that are not
+        // zero-padded
+        if name.len() != 14 || !name.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let created_at = NaiveDateTime::parse_from_str(name, DIRNAME_FORMAT).ok()?;
+        let metadata = path.symlink_metadata().ok()?;
+        if !metadata.is_dir() {
+            return None;
+        }
+        Some(Self { path, created_at })
+    }
+}
+
+/// Lists the backup dirs under `backup_root`, the latest first.
+///
+/// Entries that are not recognizable as backup dirs created by the
+/// tool are left out.
+pub fn list(backup_root: &Path) -> io::Result<Vec<BackupDir>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(backup_root)? {
+        let path = entry?.path();
+        match BackupDir::parse(path.clone()) {
+            Some(d) => dirs.push(d),
+            None => debug!("Skipping unrecognized entry: {}", path.display()),
+        }
+    }
+    dirs.sort_by_key(|d| Reverse(d.created_at));
+    Ok(dirs)
+}
+
+/// Returns the backup dirs (sorted latest first) that are to be
+/// removed as per the retention policy
+///
+/// The latest `keep` dirs are always retained. Of the rest, only the
+/// ones created more than `older_than` before `now` are removed. A
+/// `None` value means that the respective criterion doesn't apply.
+fn plan(
+    dirs: &[BackupDir],
+    keep: Option<usize>,
+    older_than: Option<Duration>,
+    now: NaiveDateTime,
+) -> Vec<&BackupDir> {
+    let cutoff = older_than.map(|d| {
+        chrono::Duration::from_std(d)
+            .ok()
+            .and_then(|d| now.checked_sub_signed(d))
+            .unwrap_or(NaiveDateTime::MIN)
+    });
+    dirs.iter()
+        .skip(keep.unwrap_or(0))
+        .filter(|d| cutoff.is_none_or(|c| d.created_at < c))
+        .collect()
+}
+
+pub fn prune(
+    backup_root: &Path,
+    keep: Option<usize>,
+    older_than: Option<Duration>,
+    dry_run: &bool,
+    confirm: impl FnOnce() -> bool,
+) -> Result<(), AppError> {
+    if keep.is_none() && older_than.is_none() {
+        return Err(AppError::Cmd(
+            "Either '--keep' or '--older-than' must be specified".to_owned(),
+        ));
+    }
+    if !backup_root.is_dir() {
+        return Err(AppError::Cmd(format!(
+            "Backup root dir doesn't exist: {}",
+            backup_root.display()
+        )));
+    }
+    let dirs = list(backup_root).map_err(AppError::Io)?;
+    let to_remove = plan(&dirs, keep, older_than, Local::now().naive_local());
+    info!(
+        "Removing {} of {} backup dir(s) with dry_run={}",
+        to_remove.len(),
+        dirs.len(),
+        dry_run
+    );
+    if *dry_run {
+        for dir in to_remove.iter() {
+            eprintln!("[DRY RUN] Backup dir to be removed: {}", dir.path.display());
+        }
+        eprintln!(
+            "[DRY RUN] {} backup dir(s) will be removed",
+            to_remove.len()
+        );
+    } else if to_remove.is_empty() {
+        eprintln!("No backup dirs to be removed");
+    } else if confirm() {
+        for dir in to_remove.iter() {
+            fs::remove_dir_all(&dir.path).map_err(AppError::Io)?;
+            info!("Removed backup dir: {}", dir.path.display());
+        }
+        eprintln!("{} backup dir(s) have been removed", to_remove.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
+
+    #[test]
+    #[serial]
+    fn test_prune() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let root = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        for name in [
+            "20240101100000",
+            "20240201100000",
+            "20240301100000",
+            "20240401100000",
+            // Not recognizable as backup dirs
+            "2024040110000",
+            "20241301100000",
+            "photos",
+        ] {
+            fs::create_dir(root.join(name)).unwrap();
+        }
+        fs::write(root.join("20240501100000"), "not a dir").unwrap();
+
+        let dirs = list(&root).unwrap();
+        let names = |ds: &[&BackupDir]| {
+            ds.iter()
+                .map(|d| d.path.file_name().unwrap().to_str().unwrap().to_owned())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            vec![
+                "20240401100000",
+                "20240301100000",
+                "20240201100000",
+                "20240101100000"
+            ],
+            names(&dirs.iter().collect::<Vec<&BackupDir>>())
+        );
+
+        let now = NaiveDateTime::parse_from_str("20240415100000", DIRNAME_FORMAT).unwrap();
+        let days = |n: u64| Duration::from_secs(n * 24 * 60 * 60);
+        assert_eq!(
+            vec!["20240201100000", "20240101100000"],
+            names(&plan(&dirs, Some(2), None, now))
+        );
+        assert_eq!(
+            vec!["20240201100000", "20240101100000"],
+            names(&plan(&dirs, None, Some(days(60)), now))
+        );
+        // The latest ones are retained even if they are old
+        assert_eq!(
+            vec!["20240101100000"],
+            names(&plan(&dirs, Some(3), Some(days(30)), now))
+        );
+        assert!(plan(&dirs, Some(5), None, now).is_empty());
+
+        // Nothing is removed in dry run mode or without confirmation
+        prune(&root, Some(1), None, &true, || true).unwrap();
+        prune(&root, Some(1), None, &false, || false).unwrap();
+        assert_eq!(4, list(&root).unwrap().len());
+
+        prune(&root, Some(1), None, &false, || true).unwrap();
+        let dirs = list(&root).unwrap();
+        assert_eq!(
+            vec!["20240401100000"],
+            names(&dirs.iter().collect::<Vec<&BackupDir>>())
+        );
+        // Unrecognized entries are left untouched
+        assert!(root.join("photos").is_dir());
+        assert!(root.join("20241301100000").is_dir());
+        assert!(root.join("20240501100000").is_file());
+
+        match prune(&root, None, None, &false, || true) {
+            Err(AppError::Cmd(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+use crate::hash::VerificationHash;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// Hashes computed for a file along with the file metadata at the
+/// time of computing them
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Entry {
+    size: u64,
+    mtime_ns: u128,
+    xxh3: Option<u64>,
+    sha256: Option<String>,
+    blake3: Option<String>,
+}
+
+impl Entry {
+    fn new(size: u64, mtime_ns: u128) -> Self {
+        Self {
+            size,
+            mtime_ns,
+            ..Default::default()
+        }
+    }
+
+    fn verification_hash(&self, alg: VerificationHash) -> Option<&String> {
+        match alg {
+            VerificationHash::Sha256 => self.sha256.as_ref(),
+            VerificationHash::Blake3 => self.blake3.as_ref(),
+        }
+    }
+
+    fn set_verification_hash(&mut self, alg: VerificationHash, value: String) {
+        match alg {
+            VerificationHash::Sha256 => self.sha256 = Some(value),
+            VerificationHash::Blake3 => self.blake3 = Some(value),
+        }
+    }
+
+    // Encodes the entry as a line of tab separated fields. The path
+    // is the last field so that it may contain tabs.
+    fn encode(&self, path: &Path) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.size,
+            self.mtime_ns,
+            self.xxh3.map_or("-".to_owned(), |h| h.to_string()),
+            self.sha256.as_deref().unwrap_or("-"),
+            self.blake3.as_deref().unwrap_or("-"),
+            path.display()
+        )
+    }
+
+    fn decode(line: &str) -> Option<(PathBuf, Self)> {
+        let parts = line.splitn(6, '\t').collect::<Vec<&str>>();
+        if parts.len() != 6 {
+            return None;
+        }
+        let opt = |s: &str| {
+            if s == "-" {
+                None
+            } else {
+                Some(s.to_owned())
+            }
+        };
+        let xxh3 = match parts[2] {
+            "-" => None,
+            s => Some(s.parse::<u64>().ok()?),
+        };
+        let entry = Self {
+            size: parts[0].parse().ok()?,
+            mtime_ns: parts[1].parse().ok()?,
+            xxh3,
+            sha256: opt(parts[3]),
+            blake3: opt(parts[4]),
+        };
+        Some((PathBuf::from(parts[5]), entry))
+    }
+}
+
+struct State {
+    entries: HashMap<PathBuf, Entry>,
+    is_dirty: bool,
+}
+
+/// On-disk cache of file hashes keyed by the absolute path of the
+/// file.
+///
+/// Every entry also stores the size and modification time of the
+/// file at the time of hashing. If any of these change, the entry is
+/// considered stale and the hashes are recomputed. The cache may be
+/// accessed from multiple threads concurrently.
+pub struct HashCache {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+/// Returns size and modification time (in nanoseconds since epoch) of
+/// the file
+fn file_stat(path: &Path) -> io::Result<(u64, u128)> {
+    let metadata = path.metadata()?;
+    let mtime_ns = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    Ok((metadata.len(), mtime_ns))
+}
+
+impl HashCache {
+    /// Loads the cache from the file at `path`. If the file doesn't
+    /// exist, an empty cache is returned. Lines that can't be parsed
+    /// are ignored.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut entries = HashMap::new();
+        match fs::File::open(path) {
+            Ok(f) => {
+                for line in io::BufReader::new(f).lines() {
+                    match Entry::decode(&line?) {
+                        Some((p, entry)) => {
+                            entries.insert(p, entry);
+                        }
+                        None => warn!("Ignoring malformed entry in hash cache"),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        debug!("Loaded {} entries from hash cache", entries.len());
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(State {
+                entries,
+                is_dirty: false,
+            }),
+        })
+    }
+
+    /// Writes the cache to disk if any entries have been updated
+    /// since it was loaded
+    pub fn save(&self) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        if !state.is_dirty {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a tmp file first and then rename it, so that the
+        // cache file doesn't get corrupted if interrupted midway
+        let tmp_path = self.path.with_extension("tmp");
+        let mut f = io::BufWriter::new(fs::File::create(&tmp_path)?);
+        for (path, entry) in state.entries.iter() {
+            let line = entry.encode(path);
+            // Paths with newlines can't be stored in the line based
+            // format, so such entries are not cached
+            if !line.contains('\n') {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        f.flush()?;
+        fs::rename(&tmp_path, &self.path)?;
+        debug!("Saved {} entries to hash cache", state.entries.len());
+        Ok(())
+    }
+
+    // Returns the value from the (fresh) cache entry for the `path`
+    // if found, otherwise calls `compute` and stores the result in
+    // the cache
+    fn get_or_compute<T, G, S, F>(&self, path: &Path, get: G, set: S, compute: F) -> io::Result<T>
+    where
+        G: Fn(&Entry) -> Option<T>,
+        S: Fn(&mut Entry, T),
+        F: FnOnce() -> io::Result<T>,
+        T: Clone,
+    {
+        let (size, mtime_ns) = file_stat(path)?;
+        {
+            let state = self.state.lock().unwrap();
+            if let Some(entry) = state.entries.get(path) {
+                if entry.size == size && entry.mtime_ns == mtime_ns {
+                    if let Some(value) = get(entry) {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+        // The lock is not held while computing the hash so that
+        // other threads can access the cache in the meanwhile
+        let value = compute()?;
+        let mut state = self.state.lock().unwrap();
+        let entry = state
+            .entries
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Entry::new(size, mtime_ns));
+        if entry.size != size || entry.mtime_ns != mtime_ns {
+            *entry = Entry::new(size, mtime_ns);
+        }
+        set(entry, value.clone());
+        state.is_dirty = true;
+        Ok(value)
+    }
+
+    /// Returns the xxh3 hash of the file from the cache, or computes
+    /// it by calling `compute` if not found or stale.
+    pub fn xxh3<F>(&self, path: &Path, compute: F) -> io::Result<u64>
+    where
+        F: FnOnce() -> io::Result<u64>,
+    {
+        self.get_or_compute(path, |e| e.xxh3, |e, v| e.xxh3 = Some(v), compute)
+    }
+
+    /// Returns the size and xxh3 hash recorded in the cache for the
+    /// `path`, without checking whether the entry is fresh.
+    ///
+    /// This is useful for finding out the contents of a file that
+    /// doesn't exist anymore.
+    pub fn lookup(&self, path: &Path) -> Option<(u64, u64)> {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .get(path)
+            .and_then(|e| e.xxh3.map(|h| (e.size, h)))
+    }
+
+    /// Returns the verification hash of the file from the cache, or
+    /// computes it by calling `compute` if not found or stale.
+    pub fn verification_hash<F>(
+        &self,
+        path: &Path,
+        alg: VerificationHash,
+        compute: F,
+    ) -> io::Result<String>
+    where
+        F: FnOnce() -> io::Result<String>,
+    {
+        self.get_or_compute(
+            path,
+            |e| e.verification_hash(alg).cloned(),
+            |e, v| e.set_verification_hash(alg, v),
+            compute,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::cell::Cell;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
+
+    #[test]
+    fn test_entry_encode_decode() {
+        let entry = Entry {
+            size: 10,
+            mtime_ns: 1700000000123456789,
+            xxh3: Some(937219074347857651),
+            sha256: None,
+            blake3: Some("abcd".to_owned()),
+        };
+        let line = entry.encode(Path::new("/foo/bar\t1.txt"));
+        assert_eq!(
+            "10\t1700000000123456789\t937219074347857651\t-\tabcd\t/foo/bar\t1.txt",
+            line
+        );
+        assert_eq!(
+            Some((PathBuf::from("/foo/bar\t1.txt"), entry)),
+            Entry::decode(&line)
+        );
+        assert!(Entry::decode("10\t12\tfoo\t-\t-\t/foo/1.txt").is_none());
+        assert!(Entry::decode("10\t12").is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn test_cache_hit_and_invalidation() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let cache_path = data_dir.join("cache/hashes.txt");
+        let file = data_dir.join("1.txt");
+        fs::write(&file, "ONE").unwrap();
+
+        let num_computed = Cell::new(0);
+        let compute = || {
+            num_computed.set(num_computed.get() + 1);
+            Ok(42)
+        };
+
+        // First scan computes the hash
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(42, cache.xxh3(&file, compute).unwrap());
+        assert_eq!(1, num_computed.get());
+        cache.save().unwrap();
+
+        // Second scan with the file unchanged reads from the cache
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(42, cache.xxh3(&file, compute).unwrap());
+        assert_eq!(1, num_computed.get());
+
+        // Hash is recomputed once the file size changes
+        fs::write(&file, "ONE MORE").unwrap();
+        assert_eq!(42, cache.xxh3(&file, compute).unwrap());
+        assert_eq!(2, num_computed.get());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+}
@@ -4,6 +4,8 @@ use std::io;
 #[derive(Debug)]
 pub enum AppError {
     SnapshotParsing,
+    // Required metadata is missing or invalid in the snapshot
+    SnapshotMetadata(String),
     SnapshotValidation(validation::Error),
     Cmd(String),
     Io(io::Error),
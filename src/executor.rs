@@ -1,11 +1,60 @@
 use crate::error::AppError;
 use crate::fileutil::{
-    delete_file, normalize_path, normalize_symlink_src_path, replace_with_symlink,
+    delete_file, encode_path, files_equal, move_file, normalize_path, normalize_symlink_src_path,
+    replace_with_hardlink, replace_with_symlink, trash_file, Backup, SymlinkStyle,
 };
-use log::info;
+use crate::hash::Checksum;
+use clap::ValueEnum;
+use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use size::Size;
-use std::io;
-use std::path::Path;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Name of the file inside the backup dir that records the backed
+/// up files
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// Name of the sub dir of the backup dir under which the files are
+/// backed up (preserving the directory structure). Keeping them
+/// separate means a backed up file can't collide with the manifest.
+pub const BACKUP_FILES_DIR: &str = "files";
+
+/// Format in which the actions are printed in dry-run mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DryRunFormat {
+    // Human readable messages printed to stderr
+    Human,
+    // Tab separated values printed to stdout
+    Tsv,
+    // JSON array of the planned actions printed to stdout, along with
+    // the human readable messages printed to stderr
+    Json,
+}
+
+/// Projection of an action for the dry-run output
+///
+/// Paths are encoded using `fileutil::encode_path` as they may not be
+/// valid UTF-8 (and may contain tabs, which would break the TSV
+/// output).
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PlannedAction {
+    pub action: &'static str,
+    pub path: String,
+    // Relative to the rootdir
+    pub relative_path: String,
+    // Symlink/hardlink source or the destination in case of move
+    pub source: Option<String>,
+    pub is_no_op: bool,
+}
 
 #[derive(Debug)]
 pub enum Action<'a> {
@@ -16,40 +65,125 @@ pub enum Action<'a> {
         is_explicit: bool,
         is_no_op: bool,
     },
+    Hardlink {
+        path: &'a Path,
+        // Absolute path of the hardlink source
+        source: PathBuf,
+        is_no_op: bool,
+    },
+    Move {
+        path: &'a Path,
+        // Absolute destination path
+        dest: PathBuf,
+        is_no_op: bool,
+    },
     Delete {
         path: &'a Path,
         is_no_op: bool,
     },
+    Trash {
+        path: &'a Path,
+        is_no_op: bool,
+    },
 }
 
 impl<'a> Action<'a> {
-    fn freeable_space(&self) -> io::Result<u64> {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Keep(_) => "keep",
+            Self::Symlink { .. } => "symlink",
+            Self::Hardlink { .. } => "hardlink",
+            Self::Move { .. } => "move",
+            Self::Delete { .. } => "delete",
+            Self::Trash { .. } => "trash",
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Self::Keep(path)
+            | Self::Symlink { path, .. }
+            | Self::Hardlink { path, .. }
+            | Self::Move { path, .. }
+            | Self::Delete { path, .. }
+            | Self::Trash { path, .. } => path,
+        }
+    }
+
+    fn is_no_op(&self) -> bool {
+        match self {
+            Self::Keep(_) => false,
+            Self::Symlink { is_no_op, .. }
+            | Self::Hardlink { is_no_op, .. }
+            | Self::Move { is_no_op, .. }
+            | Self::Delete { is_no_op, .. }
+            | Self::Trash { is_no_op, .. } => *is_no_op,
+        }
+    }
+
+    /// Returns the size of the backup taken (in a backup dir) when
+    /// executing the action. Trashed files are not backed up.
+    fn backup_size(&self) -> io::Result<u64> {
+        match self {
+            Self::Keep(_) | Self::Trash { .. } => Ok(0),
+            _ if self.is_no_op() => Ok(0),
+            Self::Symlink { path, .. }
+            | Self::Hardlink { path, .. }
+            | Self::Move { path, .. }
+            | Self::Delete { path, .. } => Ok(path.metadata()?.len()),
+        }
+    }
+
+    fn freeable_space(&self, style: SymlinkStyle) -> Result<u64, AppError> {
         let size = match self {
             Self::Keep(_) => 0_u64,
             Self::Symlink {
+                is_no_op,
+                path,
+                source,
+                is_explicit,
+            } => {
+                if *is_no_op {
+                    0_u64
+                } else {
+                    // The size of a symlink is the length of the path
+                    // that it points to
+                    let src_path = normalize_symlink_src_path(path, source, *is_explicit, style)?;
+                    let symlink_size = src_path.as_os_str().len() as u64;
+                    path.metadata()
+                        .map_err(AppError::Io)?
+                        .len()
+                        .saturating_sub(symlink_size)
+                }
+            }
+            Self::Hardlink {
                 is_no_op,
                 path,
                 source: _,
-                is_explicit: _,
             } => {
                 if *is_no_op {
                     0_u64
                 } else {
-                    path.metadata()?.len()
+                    path.metadata().map_err(AppError::Io)?.len()
                 }
             }
-            Self::Delete { is_no_op, path } => {
+            // Moving a file doesn't free up any space per se (unless
+            // the dest is on another filesystem)
+            Self::Move { .. } => 0_u64,
+            // Space of the trashed files will be actually freed up
+            // only after the trash is emptied
+            Self::Delete { is_no_op, path } | Self::Trash { is_no_op, path } => {
                 if *is_no_op {
                     0_u64
                 } else {
-                    path.metadata()?.len()
+                    path.metadata().map_err(AppError::Io)?.len()
                 }
             }
         };
         Ok(size)
     }
 
-    fn dry_run(&self, rootdir: &Path) {
+    fn dry_run(&self, rootdir: &Path, style: SymlinkStyle) -> Result<(), AppError> {
         match self {
             Self::Keep(_) => {}
             Self::Symlink {
@@ -64,10 +198,10 @@ impl<'a> Action<'a> {
                     res.push_str("[NO-OP]");
                 }
 
-                let src_path = normalize_symlink_src_path(path, source, *is_explicit).unwrap();
+                let src_path = normalize_symlink_src_path(path, source, *is_explicit, style)?;
 
                 // Use relative path in dry-run output
-                let rel_path = normalize_path(path, true, rootdir).unwrap();
+                let rel_path = normalize_path(path, true, rootdir)?;
                 res.push_str(
                     format!(
                         " File to be replaced with symlink: {} -> {}",
@@ -78,6 +212,51 @@ impl<'a> Action<'a> {
                 );
                 eprintln!("{}", res)
             }
+            Self::Hardlink {
+                path,
+                source,
+                is_no_op,
+            } => {
+                let mut res = String::from("");
+                res.push_str("[DRY RUN]");
+                if *is_no_op {
+                    res.push_str("[NO-OP]");
+                }
+                // Use relative paths in dry-run output
+                let rel_path = normalize_path(path, true, rootdir)?;
+                let rel_src_path = normalize_path(source, true, rootdir).unwrap_or(source.clone());
+                res.push_str(
+                    format!(
+                        " File to be replaced with hardlink: {} -> {}",
+                        rel_path.display(),
+                        rel_src_path.display(),
+                    )
+                    .as_str(),
+                );
+                eprintln!("{}", res)
+            }
+            Self::Move {
+                path,
+                dest,
+                is_no_op,
+            } => {
+                let mut res = String::from("");
+                res.push_str("[DRY RUN]");
+                if *is_no_op {
+                    res.push_str("[NO-OP]");
+                }
+                // Use relative path in dry-run output
+                let rel_path = normalize_path(path, true, rootdir)?;
+                res.push_str(
+                    format!(
+                        " File to be moved: {} -> {}",
+                        rel_path.display(),
+                        dest.display(),
+                    )
+                    .as_str(),
+                );
+                eprintln!("{}", res)
+            }
             Self::Delete { path, is_no_op } => {
                 let mut res = String::from("");
                 res.push_str("[DRY RUN]");
@@ -85,53 +264,267 @@ impl<'a> Action<'a> {
                     res.push_str("[NO-OP]");
                 }
                 // Use relative path in dry-run output
-                let rel_path = normalize_path(path, true, rootdir).unwrap();
+                let rel_path = normalize_path(path, true, rootdir)?;
                 res.push_str(format!(" File to be deleted: {}", rel_path.display()).as_str());
                 eprintln!("{}", res)
             }
+            Self::Trash { path, is_no_op } => {
+                let mut res = String::from("");
+                res.push_str("[DRY RUN]");
+                if *is_no_op {
+                    res.push_str("[NO-OP]");
+                }
+                // Use relative path in dry-run output
+                let rel_path = normalize_path(path, true, rootdir)?;
+                res.push_str(format!(" File to be trashed: {}", rel_path.display()).as_str());
+                eprintln!("{}", res)
+            }
         }
+        Ok(())
     }
 
-    fn execute(&self, backup_dir: Option<&Path>, rootdir: &Path) -> Result<(), AppError> {
+    /// Returns the projection of the action for the dry-run output,
+    /// with the symlink source path as it will be created
+    fn planned(&self, rootdir: &Path, style: SymlinkStyle) -> Result<PlannedAction, AppError> {
+        let rel_path = normalize_path(self.path(), true, rootdir)?;
+        let (source, is_no_op) = match self {
+            Self::Keep(_) => (None, false),
+            Self::Symlink {
+                path,
+                source,
+                is_explicit,
+                is_no_op,
+            } => (
+                Some(normalize_symlink_src_path(
+                    path,
+                    source,
+                    *is_explicit,
+                    style,
+                )?),
+                *is_no_op,
+            ),
+            Self::Hardlink {
+                source, is_no_op, ..
+            } => (
+                Some(normalize_path(source, true, rootdir).unwrap_or(source.clone())),
+                *is_no_op,
+            ),
+            Self::Move { dest, is_no_op, .. } => (Some(dest.clone()), *is_no_op),
+            Self::Delete { is_no_op, .. } | Self::Trash { is_no_op, .. } => (None, *is_no_op),
+        };
+        Ok(PlannedAction {
+            action: self.name(),
+            path: encode_path(self.path()),
+            relative_path: encode_path(&rel_path),
+            source: source.as_deref().map(encode_path),
+            is_no_op,
+        })
+    }
+
+    /// Returns a tab separated line with the columns `action`,
+    /// `relative_path`, `source` (or dest in case of move) and
+    /// `no_op` for dry-run output in TSV format
+    fn dry_run_tsv(&self, rootdir: &Path, style: SymlinkStyle) -> Result<String, AppError> {
+        let planned = self.planned(rootdir, style)?;
+        Ok(format!(
+            "{}\t{}\t{}\t{}",
+            planned.action,
+            planned.relative_path,
+            planned.source.unwrap_or_default(),
+            if planned.is_no_op { "no-op" } else { "" }
+        ))
+    }
+
+    /// Executes the action, returning the path where the file is
+    /// backed up, if applicable
+    fn execute(
+        &self,
+        backup: &Backup,
+        style: SymlinkStyle,
+        rootdir: &Path,
+        retries: u32,
+    ) -> Result<Option<PathBuf>, AppError> {
         match self {
-            Self::Keep(_) => Ok(()),
+            Self::Keep(_) => Ok(None),
             Self::Symlink {
                 path,
                 source,
                 is_explicit,
                 is_no_op,
             } => {
-                let src_path = normalize_symlink_src_path(path, source, *is_explicit).unwrap();
+                let src_path = normalize_symlink_src_path(path, source, *is_explicit, style)?;
 
                 // Show relative path in log messages
-                let rel_path = normalize_path(path, true, rootdir).unwrap();
+                let rel_path = normalize_path(path, true, rootdir)?;
                 if !is_no_op {
                     info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
                         "Replacing file with symlink: {} -> {}",
                         rel_path.display(),
                         src_path.display()
                     );
-                    replace_with_symlink(path, &src_path, backup_dir, rootdir)
+                    with_retries(retries, path, || {
+                        replace_with_symlink(path, &src_path, backup, rootdir)
+                    })
                 } else {
                     info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
                         "Intended symlink already exists (no-op): {} -> {}",
                         rel_path.display(),
                         src_path.display()
                     );
-                    Ok(())
+                    Ok(None)
+                }
+            }
+            Self::Hardlink {
+                path,
+                source,
+                is_no_op,
+            } => {
+                // Show relative path in log messages
+                let rel_path = normalize_path(path, true, rootdir)?;
+                if !is_no_op {
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "Replacing file with hardlink: {} -> {}",
+                        rel_path.display(),
+                        source.display()
+                    );
+                    with_retries(retries, path, || {
+                        replace_with_hardlink(path, source, backup, rootdir)
+                    })
+                } else {
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "Intended hardlink already exists (no-op): {} -> {}",
+                        rel_path.display(),
+                        source.display()
+                    );
+                    Ok(None)
+                }
+            }
+            Self::Move {
+                path,
+                dest,
+                is_no_op,
+            } => {
+                // Show relative path in log messages
+                let rel_path = normalize_path(path, true, rootdir)?;
+                if !is_no_op {
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "Moving file: {} -> {}",
+                        rel_path.display(),
+                        dest.display()
+                    );
+                    with_retries(retries, path, || move_file(path, dest, backup, rootdir))
+                } else {
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "File already moved: {} -> {}",
+                        rel_path.display(),
+                        dest.display()
+                    );
+                    Ok(None)
                 }
             }
             Self::Delete { path, is_no_op } => {
                 // Show relative path in log messages
-                let rel_path = normalize_path(path, true, rootdir).unwrap();
+                let rel_path = normalize_path(path, true, rootdir)?;
                 if !is_no_op {
-                    info!("Deleting file: {}", rel_path.display());
-                    delete_file(path, backup_dir, rootdir)
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "Deleting file: {}",
+                        rel_path.display()
+                    );
+                    with_retries(retries, path, || delete_file(path, backup, rootdir))
                 } else {
-                    info!("File already deleted: {}", rel_path.display());
-                    Ok(())
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "File already deleted: {}",
+                        rel_path.display()
+                    );
+                    Ok(None)
                 }
             }
+            Self::Trash { path, is_no_op } => {
+                // Show relative path in log messages
+                let rel_path = normalize_path(path, true, rootdir)?;
+                if !is_no_op {
+                    // Backup is not taken as trashed files can be
+                    // restored
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "Moving file to trash: {}",
+                        rel_path.display()
+                    );
+                    with_retries(retries, path, || trash_file(path))?;
+                    Ok(None)
+                } else {
+                    info!(
+                        action = self.name(),
+                        path = rel_path.to_string_lossy().as_ref();
+                        "File already deleted: {}",
+                        rel_path.display()
+                    );
+                    Ok(None)
+                }
+            }
+        }
+    }
+}
+
+/// Initial delay before retrying a filesystem op that failed due to a
+/// transient error. It's doubled after every retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Returns whether the error is likely to be transient i.e. the op
+/// that resulted in it may succeed if retried. Other errors (e.g.
+/// permission denied) are not worth retrying.
+fn is_transient(e: &AppError) -> bool {
+    match e {
+        AppError::Io(e) => matches!(
+            e.kind(),
+            io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+        ),
+        _ => false,
+    }
+}
+
+/// Runs the filesystem `op` on the `path`, retrying it upto `retries`
+/// times (with backoff) in case it fails due to a transient error
+fn with_retries<T>(
+    retries: u32,
+    path: &Path,
+    mut op: impl FnMut() -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let mut attempt = 0;
+    let mut delay = RETRY_BACKOFF;
+    loop {
+        match op() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                warn!(
+                    "Retrying ({} of {}) in {:?} after transient error for {}: {:?}",
+                    attempt,
+                    retries,
+                    delay,
+                    path.display(),
+                    e
+                );
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
         }
     }
 }
@@ -141,31 +534,316 @@ pub fn pending_actions<'a>(actions: &'a [Action], include_no_op: bool) -> Vec<&'
         .iter()
         .filter(|action| match action {
             Action::Keep(_) => false,
-            Action::Symlink {
-                is_no_op,
-                path: _,
-                source: _,
-                is_explicit: _,
-            } => include_no_op || !is_no_op,
-            Action::Delete { is_no_op, path: _ } => include_no_op || !is_no_op,
+            _ => include_no_op || !action.is_no_op(),
         })
         .collect::<Vec<&Action>>()
 }
 
-pub fn total_freeable_space(actions: &[Action]) -> io::Result<Size> {
+pub fn total_freeable_space(actions: &[Action], style: SymlinkStyle) -> Result<Size, AppError> {
     let mut total = 0_u64;
     for action in actions {
-        total += action.freeable_space()?;
+        total += action.freeable_space(style)?;
     }
     Ok(Size::from_bytes(total))
 }
 
+/// Paths affected by the actions that are relevant for pruning the
+/// dirs that become empty after executing the actions
+pub struct PruneCandidates {
+    // Paths that get removed i.e. deleted, trashed or moved
+    removed: Vec<PathBuf>,
+    // Dest paths of the moved files
+    created: Vec<PathBuf>,
+}
+
+impl PruneCandidates {
+    pub fn of_actions(actions: &[Action]) -> Self {
+        let mut removed = Vec::new();
+        let mut created = Vec::new();
+        for action in actions {
+            match action {
+                Action::Delete { path, .. } | Action::Trash { path, .. } => {
+                    removed.push(path.to_path_buf())
+                }
+                Action::Move { path, dest, .. } => {
+                    removed.push(path.to_path_buf());
+                    created.push(dest.clone());
+                }
+                Action::Keep(_) | Action::Symlink { .. } | Action::Hardlink { .. } => {}
+            }
+        }
+        Self { removed, created }
+    }
+}
+
+/// Returns whether all the entries in the dir are in `gone` i.e. the
+/// dir is (or will be) empty. Dirs that can't be read are considered
+/// as non-empty.
+fn is_dir_empty(dir: &Path, gone: &HashSet<PathBuf>) -> bool {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .into_iter()
+            .all(|e| e.is_ok_and(|e| gone.contains(&e.path()))),
+        Err(_) => false,
+    }
+}
+
+/// Finds the dirs that are empty after executing the actions, in
+/// bottom-up order i.e. a dir is always listed before its parent
+///
+/// If `simulate` is true, the removed paths are assumed to be gone
+/// and the moved files assumed to be in place, so that the dirs can
+/// be found without executing the actions (for dry-run). Only the
+/// parent dirs of the removed paths (and in turn their parents) are
+/// considered, stopping at the rootdir which itself is never
+/// included.
+fn find_empty_dirs(candidates: &PruneCandidates, simulate: bool, rootdir: &Path) -> Vec<PathBuf> {
+    let mut gone: HashSet<PathBuf> = if simulate {
+        candidates.removed.iter().cloned().collect()
+    } else {
+        HashSet::new()
+    };
+    let is_candidate = |dir: &Path| dir != rootdir && dir.starts_with(rootdir);
+    // Deeper dirs are popped first by ordering them by the no. of
+    // components in reverse
+    let mut queue: BTreeSet<(Reverse<usize>, PathBuf)> = candidates
+        .removed
+        .iter()
+        .filter_map(|p| p.parent())
+        .filter(|d| is_candidate(d))
+        .map(|d| (Reverse(d.components().count()), d.to_path_buf()))
+        .collect();
+    let mut result = Vec::new();
+    while let Some((_, dir)) = queue.pop_first() {
+        if gone.contains(&dir) || !dir.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            continue;
+        }
+        if simulate && candidates.created.iter().any(|p| p.starts_with(&dir)) {
+            continue;
+        }
+        if is_dir_empty(&dir, &gone) {
+            if let Some(parent) = dir.parent().filter(|d| is_candidate(d)) {
+                queue.insert((Reverse(parent.components().count()), parent.to_path_buf()));
+            }
+            gone.insert(dir.clone());
+            result.push(dir);
+        }
+    }
+    result
+}
+
+/// Removes the dirs that have become empty after executing the
+/// actions, starting from the parent dirs of the removed paths and
+/// stopping at the rootdir. In case of dry-run, the dirs that would
+/// be removed are only printed.
+///
+/// As the dirs are empty, no backup is taken.
+pub fn prune_empty_dirs(
+    candidates: &PruneCandidates,
+    dry_run: &bool,
+    rootdir: &Path,
+) -> Result<(), AppError> {
+    let dirs = find_empty_dirs(candidates, *dry_run, rootdir);
+    for dir in dirs.iter() {
+        // Use relative path in the output
+        let rel_path = normalize_path(dir, true, rootdir)?;
+        if *dry_run {
+            eprintln!("[DRY RUN] Empty dir to be pruned: {}", rel_path.display());
+        } else {
+            info!("Pruning empty dir: {}", rel_path.display());
+            fs::remove_dir(dir).map_err(AppError::Io)?;
+        }
+    }
+    if !*dry_run {
+        eprintln!("Pruned {} empty dir(s)", dirs.len());
+    }
+    Ok(())
+}
+
+/// Returns the paths of the files that are kept as per the actions
+pub fn kept_paths(actions: &[Action]) -> Vec<PathBuf> {
+    actions
+        .iter()
+        .filter_map(|a| match a {
+            Action::Keep(path) => Some(path.to_path_buf()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Mirrors the kept files into the `dest_dir`, preserving their
+/// directory structure relative to the rootdir. The files are copied
+/// unless `use_hardlinks` is true. In case of dry-run, the files that
+/// would be consolidated are only printed.
+///
+/// Files that already exist with the same contents at the
+/// destination are skipped. All destinations are checked before
+/// consolidating any file, so that it doesn't fail midway if some
+/// other file exists at a destination.
+pub fn consolidate(
+    paths: &[PathBuf],
+    dest_dir: &Path,
+    use_hardlinks: bool,
+    dry_run: &bool,
+    rootdir: &Path,
+) -> Result<(), AppError> {
+    let mut pending: Vec<(&PathBuf, PathBuf, PathBuf)> = Vec::new();
+    let mut num_existing: usize = 0;
+    for path in paths {
+        let rel_path = normalize_path(path, true, rootdir)?;
+        let dest = dest_dir.join(&rel_path);
+        if dest.symlink_metadata().is_ok() {
+            if dest.is_file() && files_equal(path, &dest).map_err(AppError::Io)? {
+                num_existing += 1;
+                continue;
+            }
+            return Err(AppError::Fs(format!(
+                "Can't consolidate file as a different one already exists at: {}",
+                dest.display()
+            )));
+        }
+        pending.push((path, rel_path, dest));
+    }
+    for (path, rel_path, dest) in pending.iter() {
+        if *dry_run {
+            eprintln!("[DRY RUN] File to be consolidated: {}", rel_path.display());
+            continue;
+        }
+        info!("Consolidating file: {}", rel_path.display());
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(AppError::Io)?;
+        }
+        if use_hardlinks {
+            fs::hard_link(path, dest).map_err(AppError::Io)?;
+        } else {
+            fs::copy(path, dest).map_err(AppError::Io)?;
+        }
+    }
+    if *dry_run {
+        eprintln!(
+            "[DRY RUN] {} file(s) will be consolidated into {} ({} already present)",
+            pending.len(),
+            dest_dir.display(),
+            num_existing
+        );
+    } else {
+        eprintln!(
+            "Consolidated {} file(s) into {} ({} already present)",
+            pending.len(),
+            dest_dir.display(),
+            num_existing
+        );
+    }
+    Ok(())
+}
+
+/// Summary of the actions in a snapshot, generated without touching
+/// the filesystem (except for reading the file sizes)
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct Report {
+    // No. of actions per op, excluding the no-op ones
+    pub counts: BTreeMap<&'static str, usize>,
+    pub no_op: usize,
+    // Space (in bytes) that can be reclaimed by executing the actions
+    pub reclaimable: u64,
+}
+
+pub fn report(actions: &[Action], style: SymlinkStyle) -> Result<Report, AppError> {
+    let mut report = Report::default();
+    for action in actions {
+        if action.is_no_op() {
+            report.no_op += 1;
+        } else {
+            *report.counts.entry(action.name()).or_insert(0) += 1;
+        }
+        report.reclaimable += action.freeable_space(style)?;
+    }
+    Ok(report)
+}
+
+/// Record of a file that has been backed up before executing an
+/// action on it
+///
+/// Paths are encoded using `fileutil::encode_path` as they may not be
+/// valid UTF-8.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ManifestEntry {
+    pub original_path: String,
+    pub backup_path: String,
+    pub action: String,
+    // Checksum of the backed up file
+    pub checksum: String,
+}
+
+/// Manifest of the backed up files, written to the backup dir
+///
+/// Every entry is appended to the manifest file as a JSON line as
+/// soon as it's recorded, so that a failure midway through the
+/// execution doesn't lose track of the backups that have already
+/// been taken. As the actions are executed in parallel, the order of
+/// the entries is not deterministic.
+struct Manifest {
+    path: PathBuf,
+}
+
+impl Manifest {
+    fn new(backup_dir: &Path) -> Self {
+        Self {
+            path: backup_dir.join(MANIFEST_FILE),
+        }
+    }
+
+    fn record(&mut self, entry: ManifestEntry) -> Result<(), AppError> {
+        let mut line = serde_json::to_string(&entry)
+            .map_err(|e| AppError::Fs(format!("Couldn't serialize manifest entry: {e}")))?;
+        line.push('\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(AppError::Io)?;
+        file.write_all(line.as_bytes()).map_err(AppError::Io)
+    }
+}
+
+/// Executes a single action, records the backup (if any) in the
+/// manifest and adds the reclaimed space to `reclaimed`
+fn execute_action(
+    action: &Action,
+    backup: &Backup,
+    style: SymlinkStyle,
+    rootdir: &Path,
+    retries: u32,
+    manifest: Option<&Mutex<Manifest>>,
+    reclaimed: &AtomicU64,
+) -> Result<(), AppError> {
+    // Size is measured before the file is removed
+    let size = action.freeable_space(style)?;
+    let backup_path = action.execute(backup, style, rootdir, retries)?;
+    reclaimed.fetch_add(size, Ordering::SeqCst);
+    if let (Some(m), Some(bp)) = (manifest, backup_path) {
+        let checksum = Checksum::of_file(&bp).map_err(AppError::Io)?;
+        m.lock().unwrap().record(ManifestEntry {
+            original_path: encode_path(action.path()),
+            backup_path: encode_path(&bp),
+            action: action.name().to_owned(),
+            checksum: checksum.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Executes the actions, returning the no. of bytes reclaimed (or
+/// projected to be reclaimed in case of dry run)
 pub fn execute(
     actions: Vec<Action>,
     dry_run: &bool,
-    backup_dir: Option<&Path>,
+    dry_run_format: DryRunFormat,
+    backup: Backup,
+    symlink_style: SymlinkStyle,
     rootdir: &Path,
-) -> Result<(), AppError> {
+    retries: u32,
+) -> Result<u64, AppError> {
     // Here we're passing the `dry_run` arg as the 2nd arg so that if,
     //
     //  dry_run == true: no-op actions will be included and displayed
@@ -176,33 +854,103 @@ pub fn execute(
         actions_pending.len(),
         dry_run
     );
-    let freeable_space = total_freeable_space(&actions).map_err(AppError::Io)?;
-    if *dry_run {
-        match backup_dir {
-            Some(d) => eprintln!(
-                "[DRY RUN] Backup will be stored under {}",
-                d.parent().unwrap().display()
-            ),
-            None => eprintln!("[DRY RUN] Backup is disabled (not recommended)"),
+    let reclaimed = if *dry_run {
+        let freeable_space = total_freeable_space(&actions, symlink_style)?;
+        match backup {
+            Backup::Dir(d) => {
+                let backup_size = actions_pending
+                    .iter()
+                    .map(|a| a.backup_size())
+                    .sum::<io::Result<u64>>()
+                    .map_err(AppError::Io)?;
+                eprintln!(
+                    "[DRY RUN] Backup will be stored under {}",
+                    d.parent().unwrap().display()
+                );
+                eprintln!(
+                    "[DRY RUN] Backup will require ~{}",
+                    Size::from_bytes(backup_size)
+                );
+            }
+            Backup::Trash => {
+                eprintln!("[DRY RUN] Files will be moved to the trash instead of backup")
+            }
+            Backup::Disabled => eprintln!("[DRY RUN] Backup is disabled (not recommended)"),
         }
 
+        let mut planned = Vec::new();
         for action in actions_pending {
-            action.dry_run(rootdir);
+            match dry_run_format {
+                DryRunFormat::Human => action.dry_run(rootdir, symlink_style)?,
+                DryRunFormat::Tsv => {
+                    println!("{}", action.dry_run_tsv(rootdir, symlink_style)?)
+                }
+                DryRunFormat::Json => {
+                    action.dry_run(rootdir, symlink_style)?;
+                    planned.push(action.planned(rootdir, symlink_style)?);
+                }
+            }
+        }
+        if dry_run_format == DryRunFormat::Json {
+            let output = serde_json::to_string_pretty(&planned)
+                .map_err(|e| AppError::Fs(format!("Couldn't serialize planned actions: {e}")))?;
+            println!("{}", output);
         }
-        eprintln!("[DRY RUN] {freeable_space} of space will be freed up");
+        eprintln!("[DRY RUN] Projected to reclaim {freeable_space}");
+        freeable_space.bytes() as u64
     } else {
-        for action in actions_pending {
-            action.execute(backup_dir, rootdir)?;
+        let files_dir;
+        let (backup, manifest) = match backup {
+            Backup::Dir(d) => {
+                files_dir = d.join(BACKUP_FILES_DIR);
+                (Backup::Dir(&files_dir), Some(Mutex::new(Manifest::new(d))))
+            }
+            b @ (Backup::Trash | Backup::Disabled) => (b, None),
+        };
+        let reclaimed = AtomicU64::new(0);
+        // Actions are on distinct paths and hence independent of each
+        // other, so they are executed in parallel using the rayon
+        // (global) thread pool. Once an action fails, the ones that
+        // haven't started yet are skipped and the first error is
+        // returned.
+        let first_err: Mutex<Option<AppError>> = Mutex::new(None);
+        let has_failed = AtomicBool::new(false);
+        actions_pending.par_iter().for_each(|action| {
+            if has_failed.load(Ordering::SeqCst) {
+                return;
+            }
+            if let Err(e) = execute_action(
+                action,
+                &backup,
+                symlink_style,
+                rootdir,
+                retries,
+                manifest.as_ref(),
+                &reclaimed,
+            ) {
+                has_failed.store(true, Ordering::SeqCst);
+                first_err.lock().unwrap().get_or_insert(e);
+            }
+        });
+        let reclaimed = reclaimed.load(Ordering::SeqCst);
+        if let Some(e) = first_err.into_inner().unwrap() {
+            eprintln!("Reclaimed {} before failing", Size::from_bytes(reclaimed));
+            return Err(e);
         }
-        eprintln!("{freeable_space} of space has been freed up");
-    }
-    Ok(())
+        eprintln!("Reclaimed {}", Size::from_bytes(reclaimed));
+        reclaimed
+    };
+    Ok(reclaimed)
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
 
     use super::*;
+    use serial_test::serial;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
 
     #[test]
     fn test_pending_actions() {
@@ -210,20 +958,492 @@ mod tests {
         let p2 = Path::new("/a/2.txt");
         let p3 = Path::new("/a/3.txt");
         let p4 = Path::new("/a/4.txt");
+        let p5 = Path::new("/a/5.txt");
         let actions = vec![
-            Action::Keep(&p1),
+            Action::Keep(p1),
             Action::Symlink {
-                path: &p2,
-                source: &p3,
+                path: p2,
+                source: p3,
                 is_no_op: true,
                 is_explicit: true,
             },
+            Action::Delete {
+                path: p4,
+                is_no_op: false,
+            },
+            Action::Hardlink {
+                path: p5,
+                source: p1.to_path_buf(),
+                is_no_op: false,
+            },
+        ];
+        assert_eq!(3, pending_actions(&actions, true).len());
+        assert_eq!(2, pending_actions(&actions, false).len());
+    }
+
+    #[test]
+    #[serial]
+    fn test_execute_writes_manifest() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let rootdir = data_dir.join("root");
+        let backup_dir = data_dir.join("backup");
+        fs::create_dir_all(rootdir.join("foo")).unwrap();
+        let p1 = rootdir.join("1.txt");
+        let p2 = rootdir.join("foo/2.txt");
+        let p3 = rootdir.join("foo/3.txt");
+        for p in [&p1, &p2, &p3] {
+            fs::write(p, "ONE").unwrap();
+        }
+        let actions = vec![
+            Action::Keep(&p1),
+            Action::Delete {
+                path: &p2,
+                is_no_op: false,
+            },
+            Action::Hardlink {
+                path: &p3,
+                source: p1.clone(),
+                is_no_op: false,
+            },
+        ];
+        execute(
+            actions,
+            &false,
+            DryRunFormat::Human,
+            Backup::Dir(&backup_dir),
+            SymlinkStyle::Relative,
+            &rootdir,
+            0,
+        )
+        .unwrap();
+
+        let manifest = fs::read_to_string(backup_dir.join(MANIFEST_FILE)).unwrap();
+        let mut entries = manifest
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect::<Vec<ManifestEntry>>();
+        // Order of the entries depends on the order of execution
+        entries.sort_by(|a, b| a.original_path.cmp(&b.original_path));
+        assert_eq!(2, entries.len());
+        let checksum = Checksum::of_file(&p1).unwrap().to_string();
+        let files_dir = backup_dir.join(BACKUP_FILES_DIR);
+        assert_eq!(encode_path(&p2), entries[0].original_path);
+        assert_eq!(
+            encode_path(&files_dir.join("foo/2.txt")),
+            entries[0].backup_path
+        );
+        assert_eq!("delete", entries[0].action);
+        assert_eq!(checksum, entries[0].checksum);
+        assert_eq!(encode_path(&p3), entries[1].original_path);
+        assert_eq!(
+            encode_path(&files_dir.join("foo/3.txt")),
+            entries[1].backup_path
+        );
+        assert_eq!("hardlink", entries[1].action);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run_tsv() {
+        let rootdir = Path::new("/a");
+        let p1 = Path::new("/a/1.txt");
+        let p2 = Path::new("/a/b/2.txt");
+        let p3 = Path::new("/a/3.txt");
+        let symlink = Action::Symlink {
+            path: p2,
+            source: p1,
+            is_no_op: false,
+            is_explicit: false,
+        };
+        assert_eq!(
+            "symlink\tb/2.txt\t../1.txt\t",
+            symlink
+                .dry_run_tsv(rootdir, SymlinkStyle::Relative)
+                .unwrap()
+        );
+        let delete = Action::Delete {
+            path: p3,
+            is_no_op: true,
+        };
+        assert_eq!(
+            "delete\t3.txt\t\tno-op",
+            delete.dry_run_tsv(rootdir, SymlinkStyle::Relative).unwrap()
+        );
+        let mv = Action::Move {
+            path: p3,
+            dest: PathBuf::from("/q/3.txt"),
+            is_no_op: false,
+        };
+        assert_eq!(
+            "move\t3.txt\t/q/3.txt\t",
+            mv.dry_run_tsv(rootdir, SymlinkStyle::Relative).unwrap()
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_execute_returns_error() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let paths = (0..20)
+            .map(|i| data_dir.join(format!("{}.txt", i)))
+            .collect::<Vec<PathBuf>>();
+        for p in paths.iter() {
+            fs::write(p, "ONE").unwrap();
+        }
+        // Deleting a file that doesn't exist will fail
+        fs::remove_file(&paths[10]).unwrap();
+        let actions = paths
+            .iter()
+            .map(|p| Action::Delete {
+                path: p,
+                is_no_op: false,
+            })
+            .collect::<Vec<Action>>();
+        let res = execute(
+            actions,
+            &false,
+            DryRunFormat::Human,
+            Backup::Disabled,
+            SymlinkStyle::Relative,
+            &data_dir,
+            0,
+        );
+        match res {
+            Err(AppError::Io(e)) => assert_eq!(io::ErrorKind::NotFound, e.kind()),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_report() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let paths = (0..5)
+            .map(|i| data_dir.join(format!("{}.txt", i)))
+            .collect::<Vec<PathBuf>>();
+        for p in paths.iter() {
+            fs::write(p, "ONE").unwrap();
+        }
+        let actions = vec![
+            Action::Keep(&paths[0]),
+            Action::Delete {
+                path: &paths[1],
+                is_no_op: false,
+            },
+            Action::Trash {
+                path: &paths[2],
+                is_no_op: false,
+            },
+            Action::Delete {
+                path: &paths[3],
+                is_no_op: false,
+            },
+            Action::Hardlink {
+                path: &paths[4],
+                source: paths[0].clone(),
+                is_no_op: true,
+            },
+        ];
+        let report = report(&actions, SymlinkStyle::Relative).unwrap();
+        assert_eq!(
+            BTreeMap::from([("delete", 2), ("keep", 1), ("trash", 1)]),
+            report.counts
+        );
+        assert_eq!(1, report.no_op);
+        assert_eq!(9, report.reclaimable);
+        // The files are not touched
+        assert!(paths.iter().all(|p| p.exists()));
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_prune_empty_dirs() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let rootdir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::create_dir_all(rootdir.join("a/b")).unwrap();
+        fs::create_dir_all(rootdir.join("c")).unwrap();
+        fs::create_dir_all(rootdir.join("d")).unwrap();
+        let p1 = rootdir.join("a/b/1.txt");
+        let p2 = rootdir.join("a/2.txt");
+        let p3 = rootdir.join("c/3.txt");
+        let p4 = rootdir.join("d/4.txt");
+        let p5 = rootdir.join("5.txt");
+        for p in [&p1, &p2, &p3, &p4, &p5] {
+            fs::write(p, "ONE").unwrap();
+        }
+        // Not a duplicate
+        fs::write(rootdir.join("d/other.txt"), "TWO").unwrap();
+        let actions = vec![
+            Action::Keep(&p3),
+            Action::Delete {
+                path: &p1,
+                is_no_op: false,
+            },
+            Action::Trash {
+                path: &p2,
+                is_no_op: false,
+            },
             Action::Delete {
                 path: &p4,
                 is_no_op: false,
             },
+            Action::Delete {
+                path: &p5,
+                is_no_op: false,
+            },
+        ];
+        let candidates = PruneCandidates::of_actions(&actions);
+
+        // Before the actions are executed, the empty dirs are found
+        // by simulating them
+        let expected = vec![rootdir.join("a/b"), rootdir.join("a")];
+        assert_eq!(expected, find_empty_dirs(&candidates, true, &rootdir));
+        assert!(find_empty_dirs(&candidates, false, &rootdir).is_empty());
+
+        for p in [&p1, &p2, &p4, &p5] {
+            fs::remove_file(p).unwrap();
+        }
+        assert_eq!(expected, find_empty_dirs(&candidates, false, &rootdir));
+        prune_empty_dirs(&candidates, &false, &rootdir).unwrap();
+        assert!(!rootdir.join("a").exists());
+        assert!(rootdir.join("c/3.txt").exists());
+        assert!(rootdir.join("d/other.txt").exists());
+        // The rootdir is never removed
+        assert!(rootdir.exists());
+
+        // Dirs that will contain moved files are not pruned
+        fs::create_dir_all(rootdir.join("e")).unwrap();
+        let p6 = rootdir.join("e/6.txt");
+        fs::write(&p6, "ONE").unwrap();
+        let actions = vec![Action::Move {
+            path: &p6,
+            dest: rootdir.join("e/moved/6.txt"),
+            is_no_op: false,
+        }];
+        let candidates = PruneCandidates::of_actions(&actions);
+        assert!(find_empty_dirs(&candidates, true, &rootdir).is_empty());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_backup_size() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let paths = (0..5)
+            .map(|i| data_dir.join(format!("{}.txt", i)))
+            .collect::<Vec<PathBuf>>();
+        for p in paths.iter() {
+            fs::write(p, "ONE").unwrap();
+        }
+        let actions = [
+            Action::Keep(&paths[0]),
+            Action::Delete {
+                path: &paths[1],
+                is_no_op: false,
+            },
+            Action::Symlink {
+                path: &paths[2],
+                source: &paths[0],
+                is_explicit: false,
+                is_no_op: false,
+            },
+            // Trashed files are not backed up
+            Action::Trash {
+                path: &paths[3],
+                is_no_op: false,
+            },
+            Action::Delete {
+                path: &paths[4],
+                is_no_op: true,
+            },
+        ];
+        let total = actions
+            .iter()
+            .map(|a| a.backup_size().unwrap())
+            .sum::<u64>();
+        assert_eq!(6, total);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_consolidate() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let rootdir = data_dir.join("root");
+        let dest_dir = data_dir.join("dest");
+        fs::create_dir_all(rootdir.join("foo")).unwrap();
+        fs::create_dir_all(dest_dir.join("foo")).unwrap();
+        let paths = ["1.txt", "2.txt", "foo/3.txt", "foo/4.txt"]
+            .iter()
+            .map(|p| rootdir.join(p))
+            .collect::<Vec<PathBuf>>();
+        for p in paths.iter() {
+            fs::write(p, "ONE").unwrap();
+        }
+        let actions = [
+            Action::Keep(&paths[0]),
+            Action::Delete {
+                path: &paths[1],
+                is_no_op: false,
+            },
+            Action::Keep(&paths[2]),
+            Action::Keep(&paths[3]),
         ];
-        assert_eq!(2, pending_actions(&actions, true).len());
-        assert_eq!(1, pending_actions(&actions, false).len());
+        let kept = kept_paths(&actions);
+        assert_eq!(
+            vec![paths[0].clone(), paths[2].clone(), paths[3].clone()],
+            kept
+        );
+
+        // Identical file at the dest is skipped
+        fs::write(dest_dir.join("foo/3.txt"), "ONE").unwrap();
+        consolidate(&kept, &dest_dir, false, &true, &rootdir).unwrap();
+        assert!(!dest_dir.join("1.txt").exists());
+
+        consolidate(&kept, &dest_dir, false, &false, &rootdir).unwrap();
+        for p in ["1.txt", "foo/3.txt", "foo/4.txt"] {
+            assert_eq!("ONE", fs::read_to_string(dest_dir.join(p)).unwrap());
+        }
+        assert!(!dest_dir.join("2.txt").exists());
+
+        // Different file at the dest results in an error before
+        // consolidating any file
+        fs::remove_file(dest_dir.join("1.txt")).unwrap();
+        fs::write(dest_dir.join("foo/4.txt"), "TWO").unwrap();
+        assert!(consolidate(&kept, &dest_dir, true, &false, &rootdir).is_err());
+        assert!(!dest_dir.join("1.txt").exists());
+
+        // Hardlinks
+        fs::remove_file(dest_dir.join("foo/4.txt")).unwrap();
+        consolidate(&kept, &dest_dir, true, &false, &rootdir).unwrap();
+        assert_eq!(
+            crate::fileutil::file_id(&paths[0]).unwrap(),
+            crate::fileutil::file_id(&dest_dir.join("1.txt")).unwrap()
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    fn test_with_retries() {
+        let path = Path::new("/a/1.txt");
+        let failing = |kind: io::ErrorKind, times: u32| {
+            let mut calls = 0;
+            move || {
+                calls += 1;
+                if calls <= times {
+                    Err(AppError::Io(io::Error::from(kind)))
+                } else {
+                    Ok(calls)
+                }
+            }
+        };
+
+        // Transient errors are retried upto the given no. of times
+        match with_retries(2, path, failing(io::ErrorKind::Interrupted, 2)) {
+            Ok(calls) => assert_eq!(3, calls),
+            Err(_) => assert!(false),
+        }
+        match with_retries(1, path, failing(io::ErrorKind::TimedOut, 2)) {
+            Err(AppError::Io(e)) => assert_eq!(io::ErrorKind::TimedOut, e.kind()),
+            _ => assert!(false),
+        }
+        match with_retries(0, path, failing(io::ErrorKind::Interrupted, 1)) {
+            Err(AppError::Io(e)) => assert_eq!(io::ErrorKind::Interrupted, e.kind()),
+            _ => assert!(false),
+        }
+
+        // Other errors are not retried
+        match with_retries(3, path, failing(io::ErrorKind::PermissionDenied, 1)) {
+            Err(AppError::Io(e)) => assert_eq!(io::ErrorKind::PermissionDenied, e.kind()),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_planned() {
+        let rootdir = Path::new("/a");
+        let symlink = Action::Symlink {
+            path: Path::new("/a/b/2.txt"),
+            source: Path::new("/a/1.txt"),
+            is_no_op: false,
+            is_explicit: false,
+        };
+        let planned = symlink.planned(rootdir, SymlinkStyle::Relative).unwrap();
+        assert_eq!(
+            PlannedAction {
+                action: "symlink",
+                path: "/a/b/2.txt".to_owned(),
+                relative_path: "b/2.txt".to_owned(),
+                source: Some("../1.txt".to_owned()),
+                is_no_op: false,
+            },
+            planned
+        );
+        let json = serde_json::to_value(&planned).unwrap();
+        assert_eq!("/a/b/2.txt", json["path"]);
+        assert_eq!("../1.txt", json["source"]);
+
+        let delete = Action::Delete {
+            path: Path::new("/a/3.txt"),
+            is_no_op: true,
+        };
+        let json =
+            serde_json::to_value(delete.planned(rootdir, SymlinkStyle::Relative).unwrap()).unwrap();
+        assert_eq!("delete", json["action"]);
+        assert_eq!("3.txt", json["relative_path"]);
+        assert!(json["source"].is_null());
+        assert_eq!(true, json["is_no_op"]);
+
+        // Path outside the rootdir
+        let delete = Action::Delete {
+            path: Path::new("/b/3.txt"),
+            is_no_op: false,
+        };
+        match delete.planned(rootdir, SymlinkStyle::Relative) {
+            Err(AppError::Fs(msg)) => assert!(msg.contains("/b/3.txt")),
+            _ => assert!(false),
+        }
+        assert!(delete.dry_run_tsv(rootdir, SymlinkStyle::Relative).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_planned_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let rootdir = Path::new("/a");
+        let path = PathBuf::from(OsStr::from_bytes(b"/a/caf\xe9\t.txt"));
+        let delete = Action::Delete {
+            path: &path,
+            is_no_op: false,
+        };
+        let planned = delete.planned(rootdir, SymlinkStyle::Relative).unwrap();
+        assert_eq!("caf\\xe9\\x09.txt", planned.relative_path);
+        assert!(serde_json::to_string(&planned).is_ok());
+        assert_eq!(
+            "delete\tcaf\\xe9\\x09.txt\t\t",
+            delete.dry_run_tsv(rootdir, SymlinkStyle::Relative).unwrap()
+        );
     }
 }
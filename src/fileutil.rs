@@ -1,18 +1,349 @@
 use crate::error::AppError;
-use log::info;
+use crate::hash::Checksum;
+use clap::ValueEnum;
+use log::{info, warn};
+use memmap2::Mmap;
 use pathdiff::diff_paths;
 use std::fs;
 use std::io::{self, Read};
-use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 
-pub fn file_contents_as_bytes<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
-    let mut f = fs::File::open(path)?;
-    let mut buf: Vec<u8> = Vec::new();
-    f.read_to_end(&mut buf)?;
-    Ok(buf)
+/// Files larger than this size (in bytes) are memory mapped instead
+/// of being read into a buffer, unless configured otherwise (see
+/// `set_mmap_threshold`)
+pub const DEFAULT_MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Current threshold for memory mapping files. `u64::MAX` means that
+/// files are never mapped.
+static MMAP_THRESHOLD: AtomicU64 = AtomicU64::new(DEFAULT_MMAP_THRESHOLD);
+
+/// Sets the size (in bytes) above which files are memory mapped for
+/// hashing. With `None`, files are always read into a buffer.
+pub fn set_mmap_threshold(threshold: Option<u64>) {
+    MMAP_THRESHOLD.store(threshold.unwrap_or(u64::MAX), Ordering::Relaxed);
+}
+
+/// Returns the size (in bytes) above which files are memory mapped
+pub fn mmap_threshold() -> u64 {
+    MMAP_THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// Memory maps the file at `path` if it's larger than `threshold`
+/// bytes
+///
+/// Returns `None` if the file is not larger than the threshold or if
+/// mapping fails for any reason, in which case the caller is
+/// expected to fallback to reading the file in a buffer.
+///
+/// Note that the file may get modified by another process after it's
+/// mapped, in which case the contents of the map can't be trusted.
+/// Callers must verify that the file size hasn't changed after they
+/// are done reading the map. However, if the file gets truncated
+/// while the map is being read, accessing the truncated part causes
+/// the process to be killed with SIGBUS, which can't be guarded
+/// against. Mapping can be disabled altogether using
+/// `set_mmap_threshold` if that's a concern.
+pub fn file_mmap<P: AsRef<Path>>(path: &P, threshold: u64) -> io::Result<Option<Mmap>> {
+    let f = fs::File::open(path)?;
+    if f.metadata()?.len() <= threshold {
+        return Ok(None);
+    }
+    // Safety: The file may be modified by other processes after it's
+    // mapped. The callers guard against modifications other than
+    // truncation by verifying the file size after reading it (see
+    // above).
+    match unsafe { Mmap::map(&f) } {
+        Ok(m) => Ok(Some(m)),
+        Err(e) => {
+            warn!(
+                "Couldn't memory map file {}: {}; falling back to buffered read",
+                path.as_ref().display(),
+                e
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Reads from `reader` until `buf` is full or EOF is reached and
+/// returns the no. of bytes read
+fn fill_buf<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Checks whether the contents of the two files are exactly the same
+/// by comparing them byte-by-byte
+///
+/// The files are read in chunks in a streaming manner and the
+/// comparison short-circuits at the first chunk that differs. Files
+/// of different sizes are considered unequal without reading them.
+pub fn files_equal(a: &Path, b: &Path) -> io::Result<bool> {
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut fa = fs::File::open(a)?;
+    let mut fb = fs::File::open(b)?;
+    let mut buf_a = vec![0_u8; 64 * 1024];
+    let mut buf_b = vec![0_u8; 64 * 1024];
+    loop {
+        let na = fill_buf(&mut fa, &mut buf_a)?;
+        let nb = fill_buf(&mut fb, &mut buf_b)?;
+        if na != nb || buf_a[..na] != buf_b[..nb] {
+            return Ok(false);
+        }
+        if na == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Returns the device and inode no. of the file, which together
+/// uniquely identify it. Hardlinks to the same file will have the same
+/// id. Symlinks are not followed.
+#[cfg(unix)]
+pub fn file_id(path: &Path) -> io::Result<Option<(u64, u64)>> {
+    let metadata = path.symlink_metadata()?;
+    Ok(Some((metadata.dev(), metadata.ino())))
+}
+
+/// Returns `None` as the device and inode no. of the file can't be
+/// determined on this platform. Callers must not assume that two
+/// files are different (or on the same device) in that case.
+///
+/// An error is still returned if the file doesn't exist. Symlinks are
+/// not followed.
+#[cfg(not(unix))]
+pub fn file_id(path: &Path) -> io::Result<Option<(u64, u64)>> {
+    path.symlink_metadata()?;
+    Ok(None)
+}
+
+/// Returns the time when the status of the file (i.e. its contents or
+/// metadata) was last changed
+///
+/// Unlike mtime, it can't be set to an arbitrary value and hence
+/// changes even when the file is replaced by another one having an
+/// older mtime e.g. using `cp -p` or `rsync -t`.
+#[cfg(unix)]
+pub fn changed_at(metadata: &fs::Metadata) -> Option<SystemTime> {
+    let ctime = Duration::new(
+        u64::try_from(metadata.ctime()).ok()?,
+        u32::try_from(metadata.ctime_nsec()).ok()?,
+    );
+    SystemTime::UNIX_EPOCH.checked_add(ctime)
+}
+
+/// Returns the time when the status of the file was last changed,
+/// which is always `None` as it can't be determined on this platform
+#[cfg(not(unix))]
+pub fn changed_at(_metadata: &fs::Metadata) -> Option<SystemTime> {
+    None
+}
+
+/// Returns the no. of hardlinks to the file
+#[cfg(unix)]
+pub fn num_links(metadata: &fs::Metadata) -> u64 {
+    metadata.nlink()
+}
+
+/// Returns the no. of hardlinks to the file, which is always 1 as it
+/// can't be determined on this platform
+#[cfg(not(unix))]
+pub fn num_links(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// Encodes the path as a string that can be decoded back into the
+/// same path using `decode_path`
+///
+/// Bytes that are not valid UTF-8 are escaped as `\xNN` (hex) and
+/// hence backslashes are escaped as `\\`. Tabs are escaped as `\x09`
+/// too, so that a tab in a snapshot line can only start a trailing
+/// comment.
+#[cfg(unix)]
+pub fn encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let mut res = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        res.push_str(&chunk.valid().replace('\\', "\\\\").replace('\t', "\\x09"));
+        for b in chunk.invalid() {
+            res.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    res
+}
+
+/// Encodes the path as a string
+///
+/// On this platform, paths that are not valid unicode are encoded
+/// lossily.
+#[cfg(not(unix))]
+pub fn encode_path(path: &Path) -> String {
+    path.display().to_string()
+}
+
+/// Decodes a path that was encoded using `encode_path`
+///
+/// Backslashes that are not followed by another backslash or a valid
+/// `xNN` sequence are retained as they are.
+#[cfg(unix)]
+pub fn decode_path(s: &str) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    let bytes = s.as_bytes();
+    let mut res: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            match bytes.get(i + 1) {
+                Some(b'\\') => {
+                    res.push(b'\\');
+                    i += 2;
+                    continue;
+                }
+                Some(b'x') => {
+                    let byte = s
+                        .get(i + 2..i + 4)
+                        .filter(|h| h.bytes().all(|c| c.is_ascii_hexdigit()))
+                        .and_then(|h| u8::from_str_radix(h, 16).ok());
+                    if let Some(b) = byte {
+                        res.push(b);
+                        i += 4;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(OsString::from_vec(res))
+}
+
+/// Decodes a path that was encoded using `encode_path`
+#[cfg(not(unix))]
+pub fn decode_path(s: &str) -> PathBuf {
+    PathBuf::from(s)
+}
+
+/// Creates a symlink at `link` pointing to `source`
+#[cfg(unix)]
+pub fn symlink(source: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, link)
+}
+
+/// Creates a symlink at `link` pointing to `source`
+///
+/// On Windows, creating symlinks requires either elevated privileges
+/// or developer mode to be enabled, failing which a clearer error is
+/// returned.
+#[cfg(windows)]
+pub fn symlink(source: &Path, link: &Path) -> io::Result<()> {
+    // Error code returned by Windows when the process doesn't have
+    // the privilege to create symlinks
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+    std::os::windows::fs::symlink_file(source, link).map_err(|e| {
+        if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Creating symlinks requires administrator privileges or developer mode on Windows",
+            )
+        } else {
+            e
+        }
+    })
+}
+
+/// Normalizes the path by resolving `.` and `..` components without
+/// accessing the filesystem
+pub fn lexical_normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push(component);
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    result
 }
 
+/// Returns the deepest dir that's an ancestor of (or same as) all
+/// the `paths`, which are expected to be absolute
+///
+/// The paths are compared lexically. For a single path, the path
+/// itself is returned.
+pub fn common_ancestor(paths: &[&Path]) -> PathBuf {
+    let mut paths = paths.iter().map(|p| lexical_normalize(p));
+    let mut result = match paths.next() {
+        Some(p) => p,
+        None => return PathBuf::new(),
+    };
+    for path in paths {
+        while !path.starts_with(&result) {
+            if !result.pop() {
+                break;
+            }
+        }
+    }
+    result
+}
+
+/// Normalizes the source path (as returned by `read_link`) of the
+/// symlink at `link`, without changing its style
+///
+/// A relative source is relative to the parent dir of the link. It's
+/// normalized by resolving it against the parent dir and then
+/// computing the relative path again, so that e.g. `./../foo/../a.txt`
+/// becomes `../a.txt`. An absolute source is only normalized
+/// lexically. The resolution is lexical, hence the link is expected
+/// to be an absolute path.
+pub fn normalize_link_source(link: &Path, source: &Path) -> PathBuf {
+    if source.is_absolute() {
+        return lexical_normalize(source);
+    }
+    let parent = lexical_normalize(link.parent().unwrap_or(Path::new("")));
+    diff_paths(lexical_normalize(&parent.join(source)), &parent)
+        .unwrap_or_else(|| lexical_normalize(source))
+}
+
+/// Checks whether `path` is located under the `rootdir`
+///
+/// Both the paths are normalized before comparison, so that paths
+/// containing `..` components or a rootdir that's (or is located
+/// under) a symlink are handled correctly. Note that only the parent
+/// dir of `path` is canonicalized and not the path itself, as it may
+/// be a symlink to a file outside the rootdir.
 pub fn within_rootdir(rootdir: &Path, path: &Path) -> bool {
+    let rootdir = rootdir
+        .canonicalize()
+        .unwrap_or_else(|_| lexical_normalize(rootdir));
+    let path = lexical_normalize(path);
+    let path = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|p| p.join(name))
+            .unwrap_or(path.clone()),
+        _ => path.clone(),
+    };
     path.ancestors().any(|d| d == rootdir)
 }
 
@@ -25,6 +356,10 @@ pub fn within_rootdir(rootdir: &Path, path: &Path) -> bool {
 ///    false, then the absolute path is created by joining the
 ///    provided to the `base_dir`.
 ///
+///  The `.` and `..` components in both the `path` and `base_dir` are
+///  resolved lexically (i.e. without accessing the filesystem), so
+///  that this works for non-existent paths as well.
+///
 ///  # Errors
 ///
 ///  This function will return Err if the `path` is not located under
@@ -36,6 +371,8 @@ pub fn normalize_path(
     must_be_relative: bool,
     base_dir: &Path,
 ) -> Result<PathBuf, AppError> {
+    let path = &lexical_normalize(path);
+    let base_dir = &lexical_normalize(base_dir);
     let is_relative = path.is_relative();
     if must_be_relative && !is_relative {
         path.strip_prefix(base_dir)
@@ -47,7 +384,7 @@ pub fn normalize_path(
             })
             .map(|p| p.to_path_buf())
     } else if !must_be_relative && is_relative {
-        Ok(base_dir.join(path))
+        Ok(lexical_normalize(&base_dir.join(path)))
     } else if !is_relative {
         // Even if the path is already absolute, verify that it's
         // under the base_dir
@@ -59,6 +396,17 @@ pub fn normalize_path(
     }
 }
 
+/// Style of the symlinks created for implicit source paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SymlinkStyle {
+    // Source path relative to the parent of the target path
+    Relative,
+    Absolute,
+    // Same style as that of the existing symlink at the target path,
+    // if any, else relative
+    Preserve,
+}
+
 /// Computes normalized source path for a symlink based on whether or
 /// not it's explicitly specified by the user
 ///
@@ -67,10 +415,13 @@ pub fn normalize_path(
 ///    - `source`: Symlink source path
 ///    - `is_explicit`: whether or not the source path is explicit
 ///      i.e. specified by the user
+///    - `style`: style of the symlink in case the source path is not
+///      explicit
 ///
 /// If the `is_explicit` is true, then (a copy of the) source is
-/// returned. If `is_explicit` is false, source path is computed
-/// relative to the parent of the `target` path.
+/// returned. If `is_explicit` is false, source path is computed as
+/// per the `style` i.e. either relative to the parent of the `target`
+/// path or as it is (absolute).
 ///
 /// # Errors
 ///
@@ -88,9 +439,24 @@ pub fn normalize_symlink_src_path(
     target: &Path,
     source: &Path,
     is_explicit: bool,
+    style: SymlinkStyle,
 ) -> Result<PathBuf, AppError> {
+    let is_absolute = match style {
+        SymlinkStyle::Relative => false,
+        SymlinkStyle::Absolute => true,
+        SymlinkStyle::Preserve => target.read_link().is_ok_and(|p| p.is_absolute()),
+    };
     if is_explicit {
         Ok(source.to_path_buf())
+    } else if is_absolute {
+        if source.is_absolute() {
+            Ok(source.to_path_buf())
+        } else {
+            Err(AppError::Fs(format!(
+                "Source path is not absolute: {}",
+                source.display()
+            )))
+        }
     } else {
         let target_parent = target.parent().ok_or(AppError::Fs(format!(
             "Couldn't compute parent dir of the target path: {}",
@@ -129,18 +495,44 @@ pub fn normalize_symlink_src_path(
 /// This function will return an error in the following situations:
 ///
 ///   - `AppError::Fs` if `base_dir` is not found to be an ancestor
-///      of `path`.
+///     of `path`.
 ///   - `AppError::Io` if there's an error writing to the backup
-///      directory.
+///     directory.
+///   - `AppError::Fs` if the checksum of the backed up file doesn't
+///     match that of the original file.
 ///
 fn take_backup(path: &Path, backup_dir: &Path, base_dir: &Path) -> Result<PathBuf, AppError> {
+    take_backup_using(path, backup_dir, base_dir, |src, dst| fs::copy(src, dst))
+}
+
+/// Function used for copying the file to the backup dir. It's only
+/// parameterized so that a failed copy can be simulated in tests.
+type CopyFn = fn(&Path, &Path) -> io::Result<u64>;
+
+fn take_backup_using(
+    path: &Path,
+    backup_dir: &Path,
+    base_dir: &Path,
+    copy: CopyFn,
+) -> Result<PathBuf, AppError> {
     // Find path relative to the rootdir
     let rel_path = path
         .strip_prefix(base_dir)
         .map_err(|_| AppError::Fs(String::from("Could not find path relative to the base dir")))?;
     let backup_path = backup_dir.join(rel_path);
     fs::create_dir_all(backup_path.parent().unwrap()).map_err(AppError::Io)?;
-    fs::copy(path, &backup_path).map_err(AppError::Io)?;
+    copy(path, &backup_path).map_err(AppError::Io)?;
+    // Verify that the file has been copied entirely (e.g. it could
+    // get truncated if the backup destination fills up) before the
+    // original file gets deleted
+    let expected = Checksum::of_file(&path).map_err(AppError::Io)?;
+    let actual = Checksum::of_file(&backup_path).map_err(AppError::Io)?;
+    if actual != expected {
+        return Err(AppError::Fs(format!(
+            "Backup of file {} is corrupt (checksum mismatch)",
+            path.display()
+        )));
+    }
     info!(
         "Backing up {} under {}",
         rel_path.display(),
@@ -149,10 +541,22 @@ fn take_backup(path: &Path, backup_dir: &Path, base_dir: &Path) -> Result<PathBu
     Ok(backup_path)
 }
 
+/// How to safeguard files before they get removed or replaced
+pub enum Backup<'a> {
+    /// Copy the file under the given backup dir
+    Dir(&'a Path),
+    /// Move the file to the trash of the OS instead of deleting it
+    Trash,
+    /// Delete the file permanently
+    Disabled,
+}
+
 /// Deletes a file at the given path, while optionally taking backup
 ///
-/// Backup is optional, which is why the `backup_dir` arg is an
-/// Option. Backup will be taken only if it's a `Some`.
+/// With `Backup::Dir`, the file is copied under the backup dir before
+/// deletion. With `Backup::Trash`, it's moved to the trash instead of
+/// being deleted. Returns the path where the file is backed up, if
+/// applicable.
 ///
 /// The deletion is performed using `std::fs::remove_file`, hence it
 /// works for symlinks too i.e. if `path` is a symlink, only the link
@@ -161,46 +565,216 @@ fn take_backup(path: &Path, backup_dir: &Path, base_dir: &Path) -> Result<PathBu
 /// # Errors
 /// This function will return an `Err` in the following situations:
 ///   - If there's an error while taking backup
-///   - If there is an error while deleting the file
+///   - If there is an error while deleting (or trashing) the file
 ///
 pub fn delete_file(
     path: &Path,
-    backup_dir: Option<&Path>,
+    backup: &Backup,
+    base_dir: &Path,
+) -> Result<Option<PathBuf>, AppError> {
+    delete_file_using(path, backup, base_dir, |src, dst| fs::copy(src, dst))
+}
+
+fn delete_file_using(
+    path: &Path,
+    backup: &Backup,
     base_dir: &Path,
-) -> Result<(), AppError> {
-    if let Some(bd) = backup_dir {
-        take_backup(path, bd, base_dir)?;
+    copy: CopyFn,
+) -> Result<Option<PathBuf>, AppError> {
+    match backup {
+        Backup::Dir(bd) => {
+            let backup_path = take_backup_using(path, bd, base_dir, copy)?;
+            fs::remove_file(path).map_err(AppError::Io)?;
+            Ok(Some(backup_path))
+        }
+        Backup::Trash => {
+            trash_file(path)?;
+            Ok(None)
+        }
+        Backup::Disabled => {
+            fs::remove_file(path).map_err(AppError::Io)?;
+            Ok(None)
+        }
     }
-    fs::remove_file(path).map_err(AppError::Io)?;
-    Ok(())
+}
+
+/// Moves the file at the given path to the trash of the OS
+///
+/// As the file can be restored from the trash, no backup is taken.
+///
+/// # Errors
+/// This function will return an `Err` if the file couldn't be moved
+/// to trash.
+///
+pub fn trash_file(path: &Path) -> Result<(), AppError> {
+    trash::delete(path).map_err(|e| {
+        AppError::Fs(format!(
+            "Couldn't move file to trash: {} ({})",
+            path.display(),
+            e
+        ))
+    })
 }
 
 /// Replaces the file located at `path` with a symlink to
 /// `source_path`, while optionally taking backup of the regular file
 /// located at `path`
 ///
-/// Backup is optional, which is why the `backup_dir` arg is an
-/// Option. Backup will be taken only if it's a `Some`.
+/// With `Backup::Dir`, the file is copied under the backup dir and
+/// with `Backup::Trash`, it's moved to the trash before being
+/// replaced. Returns the path where the file is backed up, if
+/// applicable.
+///
+/// The symlink is first created at a temporary path in the same
+/// directory and then renamed to `path`, which atomically replaces
+/// the original file. Hence if the process gets interrupted midway,
+/// either the original file or the symlink will exist at `path`.
 ///
 /// # Errors
 /// This function will return an `Err` in the following situations:
 ///   - If there's an error while taking backup
-///   - If there's an error when deleting the original file
 ///   - If there's an error when creating the symlink
+///   - If there's an error when renaming the symlink to `path`, in
+///     which case the original file is left untouched
 ///
 pub fn replace_with_symlink(
     path: &Path,
     source_path: &Path,
-    backup_dir: Option<&Path>,
+    backup: &Backup,
+    base_dir: &Path,
+) -> Result<Option<PathBuf>, AppError> {
+    let backup_path = match backup {
+        Backup::Dir(bd) => Some(take_backup(path, bd, base_dir)?),
+        Backup::Trash | Backup::Disabled => None,
+    };
+    let tmp_path = tmp_sibling_path(path)?;
+    symlink(source_path, &tmp_path).map_err(AppError::Io)?;
+    if let Backup::Trash = backup {
+        if let Err(e) = trash_file(path) {
+            fs::remove_file(&tmp_path).unwrap_or(());
+            return Err(e);
+        }
+    }
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        fs::remove_file(&tmp_path).unwrap_or(());
+        return Err(AppError::Io(e));
+    }
+    Ok(backup_path)
+}
+
+/// Returns a path in the same directory as `path` that can be used
+/// temporarily before renaming it to `path`
+fn tmp_sibling_path(path: &Path) -> Result<PathBuf, AppError> {
+    let file_name = path.file_name().ok_or(AppError::Fs(format!(
+        "Invalid file path: {}",
+        path.display()
+    )))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".dupenukem-{}.tmp", std::process::id()));
+    Ok(path.with_file_name(tmp_name))
+}
+
+/// Replaces the file located at `path` with a hardlink to
+/// `source_path`, while optionally taking backup of the file located
+/// at `path`
+///
+/// Backup is taken the same way as in `replace_with_symlink`.
+/// Returns the path where the file is backed up, if applicable.
+///
+/// Same as in case of symlinks, the hardlink is first created at a
+/// temporary path and then renamed to `path`. Hence the original file
+/// is not lost if the hardlink can't be created (e.g. across
+/// filesystems).
+///
+/// Note that a relative `source_path` is resolved in relation to the
+/// current working directory (and not the parent of `path` as in case
+/// of symlinks).
+///
+/// # Errors
+/// This function will return an `Err` in the following situations:
+///   - If there's an error while taking backup
+///   - If there's an error when creating the hardlink
+///   - If there's an error when renaming the hardlink to `path`, in
+///     which case the original file is left untouched
+///
+pub fn replace_with_hardlink(
+    path: &Path,
+    source_path: &Path,
+    backup: &Backup,
+    base_dir: &Path,
+) -> Result<Option<PathBuf>, AppError> {
+    let backup_path = match backup {
+        Backup::Dir(bd) => Some(take_backup(path, bd, base_dir)?),
+        Backup::Trash | Backup::Disabled => None,
+    };
+    let tmp_path = tmp_sibling_path(path)?;
+    fs::hard_link(source_path, &tmp_path).map_err(AppError::Io)?;
+    if let Backup::Trash = backup {
+        if let Err(e) = trash_file(path) {
+            fs::remove_file(&tmp_path).unwrap_or(());
+            return Err(e);
+        }
+    }
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        fs::remove_file(&tmp_path).unwrap_or(());
+        return Err(AppError::Io(e));
+    }
+    Ok(backup_path)
+}
+
+/// Moves the file at `path` to `dest`, while optionally taking backup
+/// of the file
+///
+/// Backup is taken only with `Backup::Dir`, as the file isn't
+/// removed but only moved. Returns the path where the file is backed
+/// up, if applicable.
+///
+/// Parent dirs of `dest` are created if they don't exist. The file is
+/// moved using `std::fs::rename`. As that doesn't work across
+/// filesystems, in such case the file is copied to `dest` and then
+/// deleted.
+///
+/// # Errors
+/// This function will return an `Err` in the following situations:
+///   - If a file already exists at `dest`
+///   - If there's an error while taking backup
+///   - If there's an error while creating the parent dirs of `dest`
+///   - If there's an error while moving (or copying) the file
+///
+pub fn move_file(
+    path: &Path,
+    dest: &Path,
+    backup: &Backup,
     base_dir: &Path,
-) -> Result<(), AppError> {
-    // First delete the existing path (with backup if applicable)
-    delete_file(path, backup_dir, base_dir)?;
-    // Then create the symlink
-    std::os::unix::fs::symlink(source_path, path).map_err(AppError::Io)
+) -> Result<Option<PathBuf>, AppError> {
+    // `fs::rename` silently replaces the dest file if it exists
+    if dest.symlink_metadata().is_ok() {
+        return Err(AppError::Fs(format!(
+            "Destination path already exists: {}",
+            dest.display()
+        )));
+    }
+    let backup_path = match backup {
+        Backup::Dir(bd) => Some(take_backup(path, bd, base_dir)?),
+        Backup::Trash | Backup::Disabled => None,
+    };
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(AppError::Io)?;
+    }
+    match fs::rename(path, dest) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(path, dest).map_err(AppError::Io)?;
+            fs::remove_file(path).map_err(AppError::Io)?;
+        }
+        Err(e) => return Err(AppError::Io(e)),
+    }
+    Ok(backup_path)
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
 
     use super::*;
@@ -267,7 +841,36 @@ mod tests {
         let p = PathBuf::from("../foo/1.txt");
         let res = normalize_path(&p, false, &base_dir);
         match res {
-            Ok(rp) => assert_eq!(PathBuf::from("/root/mydir/../foo/1.txt"), rp),
+            Ok(rp) => assert_eq!(PathBuf::from("/root/foo/1.txt"), rp),
+            Err(_) => assert!(false),
+        }
+
+        let p = PathBuf::from("./foo/../../mydir/bar/./../foo/1.txt");
+        let res = normalize_path(&p, false, &base_dir);
+        match res {
+            Ok(rp) => assert_eq!(PathBuf::from("/root/mydir/foo/1.txt"), rp),
+            Err(_) => assert!(false),
+        }
+
+        // must_be_relative is true and the absolute path contains
+        // `..` components
+        let p = PathBuf::from("/root/mydir/../mydir/foo/../bar/1.txt");
+        let res = normalize_path(&p, true, &base_dir);
+        match res {
+            Ok(rp) => assert_eq!(PathBuf::from("bar/1.txt"), rp),
+            Err(_) => assert!(false),
+        }
+
+        // Path resolves to outside the base_dir
+        let p = PathBuf::from("/root/mydir/../foo/1.txt");
+        assert!(normalize_path(&p, true, &base_dir).is_err());
+        assert!(normalize_path(&p, false, &base_dir).is_err());
+
+        // base_dir contains `..` components
+        let p = PathBuf::from("/root/mydir/foo/1.txt");
+        let res = normalize_path(&p, true, Path::new("/root/other/../mydir"));
+        match res {
+            Ok(rp) => assert_eq!(PathBuf::from("foo/1.txt"), rp),
             Err(_) => assert!(false),
         }
 
@@ -339,6 +942,7 @@ mod tests {
 
     #[test]
     #[serial]
+    #[cfg(unix)]
     fn test_take_backup_symlink() {
         setup();
 
@@ -369,33 +973,315 @@ mod tests {
         setup();
 
         let f = new_file("foo/bar/cat/1.txt", "file to be deleted");
-        let backup_dir = Some(Path::new(TEST_BACKUP_DIR));
-        let res = delete_file(&f, backup_dir, Path::new(TEST_FIXTURES_DIR));
-        assert!(res.is_ok(), "file deletion is successful");
+        let backup_dir = Path::new(TEST_BACKUP_DIR);
+        let res = delete_file(&f, &Backup::Dir(backup_dir), Path::new(TEST_FIXTURES_DIR));
         assert!(!f.try_exists().unwrap(), "file doesn't exist any more");
-        let backup_path = backup_dir.unwrap().join("foo/bar/cat/1.txt");
+        let backup_path = backup_dir.join("foo/bar/cat/1.txt");
+        assert_eq!(Some(backup_path.clone()), res.unwrap());
         assert!(backup_path.is_file());
         assert_eq!("file to be deleted", file_contents(backup_path));
 
+        // Without backup, the file is deleted permanently
+        let f = new_file("foo/bar/cat/2.txt", "file to be deleted");
+        let res = delete_file(&f, &Backup::Disabled, Path::new(TEST_FIXTURES_DIR));
+        assert_eq!(None, res.unwrap());
+        assert!(!f.try_exists().unwrap(), "file doesn't exist any more");
+        assert!(!backup_dir.join("foo/bar/cat/2.txt").exists());
+
+        teardown();
+    }
+
+    #[test]
+    #[serial]
+    fn test_delete_file_corrupt_backup() {
+        setup();
+
+        let f = new_file("foo/1.txt", "file to be deleted");
+        let backup_dir = Path::new(TEST_BACKUP_DIR);
+        // Simulate a short write when copying the file to backup dir
+        let truncating_copy: CopyFn = |src, dst| {
+            let contents = fs::read(src)?;
+            fs::write(dst, &contents[..contents.len() / 2])?;
+            Ok((contents.len() / 2) as u64)
+        };
+        let res = delete_file_using(
+            &f,
+            &Backup::Dir(backup_dir),
+            Path::new(TEST_FIXTURES_DIR),
+            truncating_copy,
+        );
+        match res {
+            Err(AppError::Fs(msg)) => assert!(msg.contains("checksum mismatch")),
+            _ => assert!(false),
+        }
+        assert!(f.is_file(), "file is not deleted");
+        assert_eq!("file to be deleted", file_contents(&f));
+
         teardown();
     }
 
     #[test]
     #[serial]
+    #[cfg(unix)]
     fn test_replace_with_symlink() {
         setup();
 
         let path = new_file("abc/foo.txt", "file to be replaced with a symlink");
-        let backup_dir = Some(Path::new(TEST_BACKUP_DIR));
+        let backup_dir = Path::new(TEST_BACKUP_DIR);
         let base_dir = Path::new(TEST_FIXTURES_DIR);
         let src = new_file("abc/foo/main.txt", "canonical file");
-        let res = replace_with_symlink(&path, &src, backup_dir, &base_dir);
+        let res = replace_with_symlink(&path, &src, &Backup::Dir(backup_dir), base_dir);
         assert!(res.is_ok(), "replace_with_symlink returned Ok result");
-        // let backup_path = backup_dir.unwrap().join("abc/foo.txt");
-        // assert!(backup_path.is_file(), "original file is backed up");
-        // assert!(target.is_symlink(), "file is now a soft link");
-        // assert_eq!(src, target.canonicalize().unwrap(), "file is now a soft link to the src path");
+        let backup_path = backup_dir.join("abc/foo.txt");
+        assert!(backup_path.is_file(), "original file is backed up");
+        assert!(path.is_symlink(), "file is now a soft link");
+        assert_eq!(
+            src,
+            fs::read_link(&path).unwrap(),
+            "file is now a soft link to the src path"
+        );
+        // Temp symlink doesn't remain in the dir
+        assert_eq!(2, fs::read_dir(path.parent().unwrap()).unwrap().count());
+
+        teardown();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_replace_with_hardlink() {
+        setup();
+
+        let path = new_file("abc/foo.txt", "canonical file");
+        let backup_dir = Path::new(TEST_BACKUP_DIR);
+        let base_dir = Path::new(TEST_FIXTURES_DIR);
+        let src = new_file("abc/foo/main.txt", "canonical file");
+        let res = replace_with_hardlink(&path, &src, &Backup::Dir(backup_dir), base_dir);
+        assert!(res.is_ok(), "replace_with_hardlink returned Ok result");
+        let backup_path = backup_dir.join("abc/foo.txt");
+        assert!(backup_path.is_file(), "original file is backed up");
+        assert!(!path.is_symlink(), "file is not a symlink");
+        assert_eq!(
+            file_id(&src).unwrap(),
+            file_id(&path).unwrap(),
+            "file is now a hardlink to the src path"
+        );
+        // Temp hardlink doesn't remain in the dir
+        assert_eq!(2, fs::read_dir(path.parent().unwrap()).unwrap().count());
+
+        // The file is left untouched if the hardlink can't be created
+        let path = new_file("abc/bar.txt", "canonical file");
+        let res = replace_with_hardlink(
+            &path,
+            Path::new(TEST_FIXTURES_DIR)
+                .join("abc/missing.txt")
+                .as_path(),
+            &Backup::Disabled,
+            base_dir,
+        );
+        assert!(res.is_err());
+        assert_eq!("canonical file", fs::read_to_string(&path).unwrap());
+        assert_eq!(3, fs::read_dir(path.parent().unwrap()).unwrap().count());
+
+        teardown();
+    }
+
+    #[test]
+    #[serial]
+    fn test_move_file() {
+        setup();
+
+        let path = new_file("abc/foo.txt", "file to be moved");
+        let backup_dir = Path::new(TEST_BACKUP_DIR);
+        let base_dir = Path::new(TEST_FIXTURES_DIR);
+        let dest = PathBuf::from(TEST_DATA_DIR).join("quarantine/abc/foo.txt");
+        let res = move_file(&path, &dest, &Backup::Dir(backup_dir), base_dir);
+        assert!(res.is_ok(), "move_file returned Ok result");
+        assert!(!path.try_exists().unwrap(), "file doesn't exist any more");
+        assert_eq!("file to be moved", file_contents(&dest));
+        let backup_path = backup_dir.join("abc/foo.txt");
+        assert_eq!("file to be moved", file_contents(backup_path));
+
+        teardown();
+    }
+
+    #[test]
+    #[serial]
+    fn test_file_mmap() {
+        setup();
+
+        let f = new_file("foo.txt", "file to be memory mapped");
+        match file_mmap(&f, 0) {
+            Ok(Some(m)) => assert_eq!(b"file to be memory mapped", &m[..]),
+            _ => assert!(false),
+        }
+
+        // File not larger than the threshold is not mapped
+        let res = file_mmap(&f, 1024);
+        assert!(res.is_ok_and(|m| m.is_none()));
+
+        teardown();
+    }
+
+    #[test]
+    #[serial]
+    fn test_files_equal() {
+        setup();
+
+        let a = new_file("a.txt", "same contents");
+        let b = new_file("b.txt", "same contents");
+        let c = new_file("c.txt", "same_contents");
+        let d = new_file("d.txt", "different size");
+        assert!(files_equal(&a, &b).unwrap());
+        assert!(!files_equal(&a, &c).unwrap());
+        assert!(!files_equal(&a, &d).unwrap());
+        assert!(files_equal(&a, Path::new("non-existing.txt")).is_err());
+
+        teardown();
+    }
+
+    #[test]
+    fn test_within_rootdir_non_canonical() {
+        let rootdir = Path::new("/nonexistent/mydir");
+        assert!(within_rootdir(rootdir, Path::new("/nonexistent/mydir/x")));
+        assert!(within_rootdir(
+            rootdir,
+            Path::new("/nonexistent/mydir/../mydir/x")
+        ));
+        assert!(within_rootdir(
+            Path::new("/nonexistent/./mydir/"),
+            Path::new("/nonexistent/mydir/foo/./x")
+        ));
+        assert!(!within_rootdir(
+            rootdir,
+            Path::new("/nonexistent/mydir/../otherdir/x")
+        ));
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_within_rootdir_symlinked_root() {
+        setup();
+
+        new_file("mydir/foo/1.txt", "file under the rootdir");
+        new_file("otherdir/2.txt", "file outside the rootdir");
+        let fixtures_dir = Path::new(TEST_FIXTURES_DIR).canonicalize().unwrap();
+        let rootdir = fixtures_dir.join("mydir");
+        let link = fixtures_dir.join("link");
+        std::os::unix::fs::symlink(&rootdir, &link).unwrap();
+
+        // Rootdir is a symlink
+        assert!(within_rootdir(&link, &rootdir.join("foo/1.txt")));
+        assert!(within_rootdir(&link, &link.join("foo/1.txt")));
+        // Path is under the symlinked rootdir
+        assert!(within_rootdir(&rootdir, &link.join("foo/1.txt")));
+        assert!(!within_rootdir(&link, &fixtures_dir.join("otherdir/2.txt")));
+        // A symlink to outside the rootdir is still considered to be
+        // within it
+        let outlink = rootdir.join("foo/2.txt");
+        std::os::unix::fs::symlink(fixtures_dir.join("otherdir/2.txt"), &outlink).unwrap();
+        assert!(within_rootdir(&rootdir, &outlink));
+
+        teardown();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_normalize_symlink_src_path() {
+        setup();
+
+        let fixtures_dir = Path::new(TEST_FIXTURES_DIR).canonicalize().unwrap();
+        let source = new_file("foo/1.txt", "hello").canonicalize().unwrap();
+        let target = new_file("bar/2.txt", "hello").canonicalize().unwrap();
+        let normalize = |target: &Path, style: SymlinkStyle| {
+            normalize_symlink_src_path(target, &source, false, style).unwrap()
+        };
+
+        assert_eq!(
+            PathBuf::from("../foo/1.txt"),
+            normalize(&target, SymlinkStyle::Relative)
+        );
+        assert_eq!(source, normalize(&target, SymlinkStyle::Absolute));
+        // Target is a regular file, hence relative
+        assert_eq!(
+            PathBuf::from("../foo/1.txt"),
+            normalize(&target, SymlinkStyle::Preserve)
+        );
+
+        // Style of the existing (broken) symlinks is preserved
+        let abs_link = fixtures_dir.join("bar/3.txt");
+        symlink(&fixtures_dir.join("missing.txt"), &abs_link).unwrap();
+        assert_eq!(source, normalize(&abs_link, SymlinkStyle::Preserve));
+        let rel_link = fixtures_dir.join("bar/4.txt");
+        symlink(Path::new("missing.txt"), &rel_link).unwrap();
+        assert_eq!(
+            PathBuf::from("../foo/1.txt"),
+            normalize(&rel_link, SymlinkStyle::Preserve)
+        );
+
+        // Explicit source paths are returned as they are
+        for style in [
+            SymlinkStyle::Relative,
+            SymlinkStyle::Absolute,
+            SymlinkStyle::Preserve,
+        ] {
+            assert_eq!(
+                PathBuf::from("../foo/1.txt"),
+                normalize_symlink_src_path(&target, Path::new("../foo/1.txt"), true, style)
+                    .unwrap()
+            );
+        }
 
         teardown();
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_encode_decode_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let p = Path::new("foo/bar 1.txt");
+        assert_eq!("foo/bar 1.txt", encode_path(p));
+        assert_eq!(p, decode_path(&encode_path(p)));
+
+        let p = Path::new(OsStr::from_bytes(b"foo/caf\xe9.txt"));
+        assert_eq!("foo/caf\\xe9.txt", encode_path(p));
+        assert_eq!(p, decode_path(&encode_path(p)));
+
+        // Backslashes are escaped so that they aren't confused with
+        // escaped bytes
+        let p = Path::new("foo/a\\xe9.txt");
+        assert_eq!("foo/a\\\\xe9.txt", encode_path(p));
+        assert_eq!(p, decode_path(&encode_path(p)));
+
+        let p = Path::new("foo/a\tb.txt");
+        assert_eq!("foo/a\\x09b.txt", encode_path(p));
+        assert_eq!(p, decode_path(&encode_path(p)));
+
+        // Backslashes not followed by a valid escape sequence are
+        // retained
+        assert_eq!(Path::new("a\\b\\xz"), decode_path("a\\b\\xz"));
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        let p = |s: &str| PathBuf::from(s);
+        assert_eq!(p("/mnt/a"), common_ancestor(&[Path::new("/mnt/a")]));
+        assert_eq!(
+            p("/mnt"),
+            common_ancestor(&[Path::new("/mnt/a"), Path::new("/mnt/b/c")])
+        );
+        assert_eq!(
+            p("/"),
+            common_ancestor(&[Path::new("/mnt/a"), Path::new("/media/ab/../b")])
+        );
+        // Only whole components are considered to be common
+        assert_eq!(
+            p("/mnt"),
+            common_ancestor(&[Path::new("/mnt/foo"), Path::new("/mnt/foobar")])
+        );
+    }
 }
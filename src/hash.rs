@@ -1,45 +1,408 @@
 use crate::error::AppError;
-use crate::fileutil::file_contents_as_bytes;
+use crate::fileutil::{file_mmap, mmap_threshold};
+use clap::ValueEnum;
 use sha2::{Digest, Sha256};
 use std::fmt;
-use std::io;
+use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
-use xxhash_rust::xxh3;
+use xxhash_rust::xxh3::{xxh3_64 as xxh3_64_bytes, Xxh3};
+
+/// Size of the buffer used for reading file contents in chunks while
+/// hashing
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+/// Reads the file at `path` in fixed size chunks and calls `update`
+/// with every chunk read.
+///
+/// This allows the hash to be computed incrementally, which means
+/// only `READ_BUF_SIZE` bytes of the file are held in memory at a
+/// time regardless of the file size.
+///
+/// Files larger than the configured threshold (see
+/// `fileutil::set_mmap_threshold`) are memory mapped instead and
+/// `update` is called only once with the entire mapped contents. In
+/// that case, an error is returned if the size of the file changes
+/// while it's being read. Note that truncation of a mapped file
+/// while it's being read can't be detected this way as it results in
+/// SIGBUS (see `fileutil::file_mmap`).
+fn read_in_chunks<P, F>(path: &P, mut update: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(&[u8]),
+{
+    if let Some(mmap) = file_mmap(path, mmap_threshold())? {
+        update(&mmap);
+        let size = path.as_ref().metadata()?.len();
+        if size != mmap.len() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "File size changed while it was being read",
+            ));
+        }
+        return Ok(());
+    }
+    let mut f = fs::File::open(path)?;
+    let mut buf = vec![0_u8; READ_BUF_SIZE];
+    loop {
+        let n = match f.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        update(&buf[..n]);
+    }
+    Ok(())
+}
 
 pub fn xxh3_64<P: AsRef<Path>>(path: &P) -> io::Result<u64> {
-    let data = file_contents_as_bytes(path)?;
-    let result = xxh3::xxh3_64(&data);
-    Ok(result)
+    let mut hasher = Xxh3::new();
+    read_in_chunks(path, |chunk| hasher.update(chunk))?;
+    Ok(hasher.digest())
+}
+
+/// Computes xxh3_64 hash of only the first `len` bytes of the file
+///
+/// If the file is smaller than `len` bytes, the hash of the entire
+/// file is returned.
+pub fn xxh3_prefix<P: AsRef<Path>>(path: &P, len: u64) -> io::Result<u64> {
+    let mut buf = Vec::new();
+    fs::File::open(path)?.take(len).read_to_end(&mut buf)?;
+    Ok(xxh3_64_bytes(&buf))
 }
 
 pub fn sha256<P: AsRef<Path>>(path: &P) -> io::Result<String> {
-    let data = file_contents_as_bytes(path)?;
-    let result = Sha256::digest(data);
-    Ok(format!("{:x}", result))
+    let mut hasher = Sha256::new();
+    read_in_chunks(path, |chunk| hasher.update(chunk))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn blake3<P: AsRef<Path>>(path: &P) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    read_in_chunks(path, |chunk| {
+        hasher.update(chunk);
+    })?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Hashing algorithm used for confirming that files having the same
+/// xxh3 checksum are actually duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VerificationHash {
+    Sha256,
+    Blake3,
+}
+
+impl VerificationHash {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, AppError> {
+        match s {
+            "sha256" => Ok(Self::Sha256),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(AppError::ChecksumParsing),
+        }
+    }
+
+    pub fn of_file<P: AsRef<Path>>(&self, path: &P) -> io::Result<String> {
+        match self {
+            Self::Sha256 => sha256(path),
+            Self::Blake3 => blake3(path),
+        }
+    }
+
+    /// Computes the hash of the file considering its contents as per
+    /// the `content_hash` mode (see `Checksum::of_file_with`)
+    pub fn of_file_with<P: AsRef<Path>>(
+        &self,
+        path: &P,
+        content_hash: ContentHash,
+    ) -> io::Result<String> {
+        let path = path.as_ref();
+        if content_hash.applies_to(path) {
+            return Ok(self.of_bytes(&content_hash.contents(path)?));
+        }
+        self.of_file(&path)
+    }
+
+    pub fn of_bytes(&self, bytes: &[u8]) -> String {
+        match self {
+            Self::Sha256 => format!("{:x}", Sha256::digest(bytes)),
+            Self::Blake3 => blake3::hash(bytes).to_hex().to_string(),
+        }
+    }
+}
+
+/// Extensions (lowercase) of the image files whose metadata segments
+/// are left out when hashing in `ContentHash::Images` mode
+const IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// PNG file signature (first 8 bytes of the file)
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Markers of the JPEG segments that hold metadata i.e. APP1 (EXIF,
+/// XMP), APP13 (IPTC) and comment. Other APPn segments such as APP2
+/// (ICC profile) and APP14 (Adobe) affect how the image is rendered.
+const JPEG_METADATA_MARKERS: [u8; 3] = [0xE1, 0xED, 0xFE];
+
+/// Types of the ancillary PNG chunks that hold metadata
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"tEXt", b"zTXt", b"iTXt", b"tIME", b"eXIf"];
+
+/// Which contents of the files are considered for computing the
+/// checksums
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ContentHash {
+    /// Raw bytes of all files
+    Raw,
+    /// For JPEG and PNG images, the contents excluding the metadata
+    /// (e.g. EXIF) segments. Raw bytes for all other files.
+    Images,
+}
+
+impl ContentHash {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Raw => "raw",
+            Self::Images => "images",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, AppError> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "images" => Ok(Self::Images),
+            _ => Err(AppError::ChecksumParsing),
+        }
+    }
+
+    /// Returns whether the checksum of the file at `path` is computed
+    /// from something other than its raw bytes in this mode
+    pub fn applies_to(&self, path: &Path) -> bool {
+        match self {
+            Self::Raw => false,
+            Self::Images => image_extension(path).is_some(),
+        }
+    }
+
+    /// Returns the contents of the file that are considered for
+    /// computing its checksum in this mode
+    ///
+    /// These are the raw bytes unless the mode applies to the file
+    /// (and it can be parsed). Note that the entire file is read into
+    /// memory.
+    pub fn contents(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let bytes = fs::read(path)?;
+        let ext = self
+            .applies_to(path)
+            .then(|| image_extension(path))
+            .flatten();
+        let data = match ext.as_deref() {
+            Some("png") => png_image_data(&bytes),
+            Some(_) => jpeg_image_data(&bytes),
+            None => None,
+        };
+        Ok(data.unwrap_or(bytes))
+    }
+}
+
+/// Returns the (lowercase) extension of the path if it's one of
+/// `IMAGE_EXTENSIONS`
+fn image_extension(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    IMAGE_EXTENSIONS.contains(&ext.as_str()).then_some(ext)
+}
+
+/// Returns the contents of a JPEG file excluding the metadata segments
+/// (see `JPEG_METADATA_MARKERS`), or `None` if it can't be parsed
+fn jpeg_image_data(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut res = bytes[..2].to_vec();
+    let mut i = 2;
+    loop {
+        if *bytes.get(i)? != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(i + 1)?;
+        // Fill byte preceding a marker
+        if marker == 0xFF {
+            i += 1;
+            continue;
+        }
+        // Standalone markers don't have a length field
+        if marker == 0x01 || (0xD0..=0xD9).contains(&marker) {
+            res.extend_from_slice(&bytes[i..i + 2]);
+            i += 2;
+            if marker == 0xD9 {
+                return Some(res);
+            }
+            continue;
+        }
+        // Segment length includes the 2 bytes of the length field
+        let len = u16::from_be_bytes([*bytes.get(i + 2)?, *bytes.get(i + 3)?]) as usize;
+        let end = i + 2 + len;
+        if len < 2 || end > bytes.len() {
+            return None;
+        }
+        // Start of scan, which is followed by the entropy coded image
+        // data till the end of the file
+        if marker == 0xDA {
+            res.extend_from_slice(&bytes[i..]);
+            return Some(res);
+        }
+        if !JPEG_METADATA_MARKERS.contains(&marker) {
+            res.extend_from_slice(&bytes[i..end]);
+        }
+        i = end;
+    }
+}
+
+/// Returns the contents of a PNG file excluding the metadata chunks
+/// (see `PNG_METADATA_CHUNKS`), or `None` if it can't be parsed
+fn png_image_data(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+    let mut res = PNG_SIGNATURE.to_vec();
+    let mut i = PNG_SIGNATURE.len();
+    while i < bytes.len() {
+        let len = u32::from_be_bytes(bytes.get(i..i + 4)?.try_into().ok()?) as usize;
+        // Length field, chunk type, data and CRC
+        let end = i.checked_add(len)?.checked_add(12)?;
+        let chunk = bytes.get(i..end)?;
+        if !PNG_METADATA_CHUNKS.iter().any(|t| chunk[4..8] == t[..]) {
+            res.extend_from_slice(chunk);
+        }
+        i = end;
+    }
+    Some(res)
+}
+
+/// Form in which the checksums are written in a snapshot
+///
+/// It's recorded in the snapshot so that the checksums can be parsed
+/// unambiguously. Snapshots generated by older versions of the tool
+/// don't record it, in which case they are in decimal form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumFormat {
+    /// Zero padded hex digits, as rendered by `Display`
+    Hex,
+    Decimal,
+}
+
+impl ChecksumFormat {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Hex => "hex",
+            Self::Decimal => "decimal",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, AppError> {
+        match s {
+            "hex" => Ok(Self::Hex),
+            "decimal" => Ok(Self::Decimal),
+            _ => Err(AppError::ChecksumParsing),
+        }
+    }
 }
 
 /// Wrapper around xx3_64 hash
 ///
 /// The intention is to be able to swap out the checksum/hashing
 /// algorithm in future without having to modify the calling code.
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Checksum {
     xx3_hash: u64,
+    // Distinguishes the sets of duplicates that have the same xxh3
+    // hash but different contents (i.e. in case of a collision), so
+    // that all of them can be reported as separate groups. It's 0
+    // for the first (and practically always the only) set.
+    subgroup: u32,
 }
 
 impl Checksum {
+    /// Name of the algorithm used for computing the checksum, which
+    /// is recorded in the snapshot
+    pub const ALGORITHM: &'static str = "xxh3_64";
+
+    /// No. of (zero padded) hex digits in the string form
+    const HEX_LEN: usize = 16;
+
     pub fn new(value: u64) -> Self {
-        Self { xx3_hash: value }
+        Self {
+            xx3_hash: value,
+            subgroup: 0,
+        }
+    }
+
+    /// Returns the checksum identifying the `n`th set of duplicates
+    /// having the same xxh3 hash as this one
+    pub fn with_subgroup(&self, n: u32) -> Self {
+        Self {
+            xx3_hash: self.xx3_hash,
+            subgroup: n,
+        }
+    }
+
+    pub fn subgroup(&self) -> u32 {
+        self.subgroup
     }
 
     pub fn of_file<P: AsRef<Path>>(path: &P) -> io::Result<Self> {
-        let hash = xxh3_64(path)?;
-        Ok(Self { xx3_hash: hash })
+        Self::of_file_with(path, ContentHash::Raw)
     }
 
-    pub fn parse(s: &str) -> Result<Self, AppError> {
-        let hash = s.parse::<u64>().map_err(|_| AppError::ChecksumParsing)?;
-        Ok(Self { xx3_hash: hash })
+    /// Computes checksum of the file considering its contents as per
+    /// the `content_hash` mode
+    ///
+    /// Image files that can't be parsed are hashed using their raw
+    /// bytes.
+    pub fn of_file_with<P: AsRef<Path>>(path: &P, content_hash: ContentHash) -> io::Result<Self> {
+        let path = path.as_ref();
+        if content_hash.applies_to(path) {
+            return Ok(Self::of_bytes(&content_hash.contents(path)?));
+        }
+        let hash = xxh3_64(&path)?;
+        Ok(Self::new(hash))
+    }
+
+    pub fn of_bytes(bytes: &[u8]) -> Self {
+        Self::new(xxh3_64_bytes(bytes))
+    }
+
+    /// Checksum of zero-length contents i.e. of the group of empty
+    /// files
+    pub fn empty() -> Self {
+        Self::of_bytes(&[])
+    }
+
+    /// Parses the checksum from the string in the given `format`
+    ///
+    /// The subgroup, if any, follows the hash separated by `-`.
+    pub fn parse(s: &str, format: ChecksumFormat) -> Result<Self, AppError> {
+        let (s, subgroup) = match s.split_once('-') {
+            Some((h, n)) => (h, n.parse::<u32>().map_err(|_| AppError::ChecksumParsing)?),
+            None => (s, 0),
+        };
+        let hash = match format {
+            ChecksumFormat::Hex => {
+                if s.len() != Self::HEX_LEN || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(AppError::ChecksumParsing);
+                }
+                u64::from_str_radix(s, 16)
+            }
+            ChecksumFormat::Decimal => s.parse::<u64>(),
+        }
+        .map_err(|_| AppError::ChecksumParsing)?;
+        Ok(Self::new(hash).with_subgroup(subgroup))
     }
 
     // Returns the actual hash value
@@ -56,6 +419,225 @@ impl Checksum {
 
 impl fmt::Display for Checksum {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.xx3_hash)
+        write!(f, "{:016x}", self.xx3_hash)?;
+        if self.subgroup > 0 {
+            write!(f, "-{}", self.subgroup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use xxhash_rust::xxh3;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
+
+    #[test]
+    #[serial]
+    fn test_streamed_hashes_match_one_shot() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+
+        // File that's larger than the read buffer and whose size is
+        // not a multiple of it, so that the last chunk is partial
+        let data = (0..(READ_BUF_SIZE * 3 + 123))
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<u8>>();
+        let path = Path::new(TEST_DATA_DIR).join("large.bin");
+        fs::write(&path, &data).unwrap();
+
+        assert_eq!(xxh3::xxh3_64(&data), xxh3_64(&path).unwrap());
+        assert_eq!(
+            format!("{:x}", Sha256::digest(&data)),
+            sha256(&path).unwrap()
+        );
+        assert_eq!(
+            blake3::hash(&data).to_hex().to_string(),
+            blake3(&path).unwrap()
+        );
+
+        // Same when the file is memory mapped
+        crate::fileutil::set_mmap_threshold(Some(1024));
+        assert_eq!(xxh3::xxh3_64(&data), xxh3_64(&path).unwrap());
+        crate::fileutil::set_mmap_threshold(Some(crate::fileutil::DEFAULT_MMAP_THRESHOLD));
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    fn test_verification_hash_parse() {
+        assert_eq!(
+            VerificationHash::Sha256,
+            VerificationHash::parse("sha256").unwrap()
+        );
+        assert_eq!(
+            VerificationHash::Blake3,
+            VerificationHash::parse("blake3").unwrap()
+        );
+        assert!(VerificationHash::parse("md5").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_xxh3_prefix() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+
+        let data = (0..10000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let path = Path::new(TEST_DATA_DIR).join("prefix.bin");
+        fs::write(&path, &data).unwrap();
+
+        // Only the first `len` bytes are hashed
+        assert_eq!(
+            xxh3::xxh3_64(&data[..4096]),
+            xxh3_prefix(&path, 4096).unwrap()
+        );
+
+        // File smaller than `len` is hashed entirely
+        assert_eq!(xxh3::xxh3_64(&data), xxh3_prefix(&path, 20000).unwrap());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_of_file_with_images() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR);
+
+        // Minimal JPEGs that differ only in the EXIF (APP1) and
+        // comment segments
+        let sof = [0xFF, 0xC0, 0x00, 0x04, 0x01, 0x02];
+        let scan = [0xFF, 0xDA, 0x00, 0x03, 0x01, 0xAB, 0xCD, 0xFF, 0xD9];
+        let jpeg = |meta: &[u8]| {
+            let mut bytes = vec![0xFF, 0xD8];
+            bytes.extend_from_slice(meta);
+            bytes.extend_from_slice(&sof);
+            bytes.extend_from_slice(&scan);
+            bytes
+        };
+        fs::write(
+            data_dir.join("1.jpg"),
+            jpeg(&[0xFF, 0xE1, 0x00, 0x04, 0x01, 0x02]),
+        )
+        .unwrap();
+        fs::write(
+            data_dir.join("2.JPG"),
+            jpeg(&[0xFF, 0xFE, 0x00, 0x03, b'x']),
+        )
+        .unwrap();
+        fs::write(data_dir.join("3.txt"), jpeg(&[])).unwrap();
+        // ICC profile (APP2) is not metadata
+        fs::write(
+            data_dir.join("8.jpg"),
+            jpeg(&[0xFF, 0xE2, 0x00, 0x04, 0x01, 0x02]),
+        )
+        .unwrap();
+
+        // PNGs that differ only in a tEXt chunk
+        let chunk = |typ: &[u8; 4], data: &[u8]| {
+            let mut bytes = (data.len() as u32).to_be_bytes().to_vec();
+            bytes.extend_from_slice(typ);
+            bytes.extend_from_slice(data);
+            bytes.extend_from_slice(&[0, 0, 0, 0]);
+            bytes
+        };
+        let png = |meta: &[u8]| {
+            let mut bytes = PNG_SIGNATURE.to_vec();
+            bytes.extend(chunk(b"IHDR", &[1, 2, 3]));
+            bytes.extend_from_slice(meta);
+            bytes.extend(chunk(b"IDAT", &[4, 5]));
+            bytes.extend(chunk(b"IEND", &[]));
+            bytes
+        };
+        fs::write(data_dir.join("4.png"), png(&chunk(b"tEXt", b"a"))).unwrap();
+        fs::write(data_dir.join("5.png"), png(&chunk(b"tEXt", b"bc"))).unwrap();
+        fs::write(data_dir.join("6.png"), png(&chunk(b"pHYs", b"a"))).unwrap();
+
+        let ck = |name: &str, content_hash: ContentHash| {
+            Checksum::of_file_with(&data_dir.join(name), content_hash)
+                .unwrap()
+                .value()
+        };
+        assert_ne!(ck("1.jpg", ContentHash::Raw), ck("2.JPG", ContentHash::Raw));
+        assert_eq!(
+            ck("1.jpg", ContentHash::Images),
+            ck("2.JPG", ContentHash::Images)
+        );
+        // Non-image files are hashed raw
+        assert_eq!(
+            ck("3.txt", ContentHash::Raw),
+            ck("3.txt", ContentHash::Images)
+        );
+        assert_eq!(
+            ck("1.jpg", ContentHash::Images),
+            Checksum::of_bytes(&jpeg(&[])).value()
+        );
+        assert_ne!(
+            ck("1.jpg", ContentHash::Images),
+            ck("8.jpg", ContentHash::Images)
+        );
+
+        assert_eq!(
+            ck("4.png", ContentHash::Images),
+            ck("5.png", ContentHash::Images)
+        );
+        assert_ne!(
+            ck("4.png", ContentHash::Images),
+            ck("6.png", ContentHash::Images)
+        );
+
+        // Unparseable images are hashed raw
+        fs::write(data_dir.join("7.jpg"), b"not a jpeg").unwrap();
+        assert_eq!(
+            ck("7.jpg", ContentHash::Raw),
+            ck("7.jpg", ContentHash::Images)
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+    }
+
+    #[test]
+    fn test_checksum_parse_display() {
+        let c = Checksum::new(937219074347857651);
+        assert_eq!("0d01abc8533976f3", c.to_string());
+        assert_eq!("000000000000002a", Checksum::new(42).to_string());
+
+        let hex = |s: &str| Checksum::parse(s, ChecksumFormat::Hex);
+        let decimal = |s: &str| Checksum::parse(s, ChecksumFormat::Decimal);
+
+        // Hex form round-trips
+        assert_eq!(c.value(), hex(&c.to_string()).unwrap().value());
+        assert_eq!(u64::MAX, hex("ffffffffffffffff").unwrap().value());
+        // Uppercase hex is accepted too
+        assert_eq!(c.value(), hex("0D01ABC8533976F3").unwrap().value());
+        assert!(hex("").is_err());
+        assert!(hex("0d01abc8533976").is_err());
+        assert!(hex("0d01abc8533976f3a").is_err());
+        assert!(hex("937219074347857651").is_err());
+
+        // Decimal form (from older snapshots)
+        assert_eq!(c.value(), decimal("937219074347857651").unwrap().value());
+        assert_eq!(42, decimal("42").unwrap().value());
+        // A 16 digit decimal value isn't mistaken for hex
+        assert_eq!(
+            1234567890123456,
+            decimal("1234567890123456").unwrap().value()
+        );
+        assert!(decimal("").is_err());
+        assert!(decimal("-1").is_err());
+        assert!(decimal("0d01abc8533976f3").is_err());
+
+        // Subgroup of a set of duplicates having the same xxh3 hash
+        let c = Checksum::new(42).with_subgroup(2);
+        assert_eq!("000000000000002a-2", c.to_string());
+        assert!(c == hex("000000000000002a-2").unwrap());
+        assert!(c != Checksum::new(42));
+        assert!(hex("000000000000002a-").is_err());
+        assert!(c == decimal("42-2").unwrap());
     }
 }
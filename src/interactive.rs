@@ -0,0 +1,69 @@
+use crate::error::AppError;
+use crate::snapshot::{self, DefaultOp, Snapshot};
+use inquire::{InquireError, Select};
+use log::{debug, info};
+use size::Size;
+
+/// Asks the user to select one of the `options` and returns its
+/// index, or `None` if the user skips the prompt by pressing `Esc`
+fn select(msg: &str, options: Vec<String>) -> Result<Option<usize>, AppError> {
+    let ans = Select::new(msg, options)
+        .with_help_message("Press Esc to skip the group")
+        .raw_prompt();
+    match ans {
+        Ok(opt) => Ok(Some(opt.index)),
+        Err(InquireError::OperationCanceled) => Ok(None),
+        Err(InquireError::OperationInterrupted) => {
+            Err(AppError::Cmd("Interrupted by user".to_owned()))
+        }
+        Err(e) => Err(AppError::Cmd(format!("Error in interactive prompt: {}", e))),
+    }
+}
+
+/// Asks the user to choose the keeper of every duplicate group and
+/// what to do with the rest of the files in it, and marks the files
+/// in the snapshot accordingly
+///
+/// Groups skipped by the user (by pressing `Esc`) are left as they
+/// are. Returns the no. of groups that were resolved.
+pub fn resolve_groups(snap: &mut Snapshot) -> Result<usize, AppError> {
+    let groups = snap.keeper_candidates().map_err(AppError::Io)?;
+    let num_groups = groups.len();
+    let mut num_resolved = 0;
+    for (i, (checksum, space, paths)) in groups.into_iter().enumerate() {
+        // Nothing to choose from if there's only one file to keep
+        if paths.len() < 2 {
+            continue;
+        }
+        let options = paths
+            .iter()
+            .map(|p| snapshot::entry_path(p, &snap.rootdir).display().to_string())
+            .collect::<Vec<String>>();
+        let msg = format!(
+            "Group {} of {} ({} reclaimable). Which file to keep?",
+            i + 1,
+            num_groups,
+            Size::from_bytes(space)
+        );
+        let keeper_idx = match select(&msg, options)? {
+            Some(idx) => idx,
+            None => {
+                debug!("Skipping group {}", checksum);
+                continue;
+            }
+        };
+        let ops = vec!["symlink".to_owned(), "delete".to_owned()];
+        let op = match select("What to do with the other files?", ops)? {
+            Some(0) => DefaultOp::Symlink,
+            Some(_) => DefaultOp::Delete,
+            None => {
+                debug!("Skipping group {}", checksum);
+                continue;
+            }
+        };
+        snap.resolve_group(&checksum, &paths[keeper_idx], op);
+        num_resolved += 1;
+    }
+    info!("Resolved {} of {} group(s)", num_resolved, num_groups);
+    Ok(num_resolved)
+}
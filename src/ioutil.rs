@@ -1,7 +1,13 @@
-use std::fs::File;
-use std::io::{self, BufRead};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
+/// Magic bytes at the start of gzip-compressed data
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
 pub fn stdin_to_vec() -> io::Result<Vec<String>> {
     let stdin = io::stdin();
     let mut result = Vec::new();
@@ -12,12 +18,117 @@ pub fn stdin_to_vec() -> io::Result<Vec<String>> {
     Ok(result)
 }
 
+/// Reads lines in the file at `path`, transparently decompressing it
+/// if it's gzip-compressed (detected by the magic bytes at the start
+/// of the file, irrespective of the extension)
 pub fn read_lines_in_file(path: &Path) -> io::Result<Vec<String>> {
     let file = File::open(path)?;
-    let mut result = Vec::new();
-    for line in io::BufReader::new(file).lines() {
-        let s = line?;
-        result.push(s);
+    let mut reader = io::BufReader::new(file);
+    let is_gzip = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    if is_gzip {
+        io::BufReader::new(GzDecoder::new(reader)).lines().collect()
+    } else {
+        reader.lines().collect()
+    }
+}
+
+/// Writes lines to stdout, optionally compressing them using gzip
+pub fn write_lines_to_stdout(lines: &[String], gzip: bool) -> io::Result<()> {
+    write_lines_to(io::stdout().lock(), lines, gzip)
+}
+
+/// Writes lines to the file at `path`, optionally compressing them
+/// using gzip
+///
+/// Unless `overwrite` is true, an error of kind `AlreadyExists` is
+/// returned if the file already exists.
+pub fn write_lines_to_file(
+    path: &Path,
+    lines: &[String],
+    gzip: bool,
+    overwrite: bool,
+) -> io::Result<()> {
+    let file = if overwrite {
+        File::create(path)?
+    } else {
+        OpenOptions::new().write(true).create_new(true).open(path)?
+    };
+    write_lines_to(file, lines, gzip)
+}
+
+fn write_lines_to<W: Write>(w: W, lines: &[String], gzip: bool) -> io::Result<()> {
+    if gzip {
+        let mut encoder = GzEncoder::new(w, Compression::default());
+        write_lines(&mut encoder, lines)?;
+        encoder.finish()?.flush()
+    } else {
+        let mut writer = io::BufWriter::new(w);
+        write_lines(&mut writer, lines)?;
+        writer.flush()
+    }
+}
+
+fn write_lines<W: Write>(w: &mut W, lines: &[String]) -> io::Result<()> {
+    for line in lines {
+        writeln!(w, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::fs;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
+
+    #[test]
+    #[serial]
+    fn test_read_lines_in_gzip_file() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let lines = vec!["#! Root Directory: /foo".to_owned(), "".to_owned()];
+
+        let plain = Path::new(TEST_DATA_DIR).join("snap.txt");
+        let mut f = File::create(&plain).unwrap();
+        write_lines(&mut f, &lines).unwrap();
+        assert_eq!(lines, read_lines_in_file(&plain).unwrap());
+
+        // Compressed file is detected without relying on the
+        // extension
+        let compressed = Path::new(TEST_DATA_DIR).join("snap.txt.gz.bak");
+        let f = File::create(&compressed).unwrap();
+        let mut encoder = GzEncoder::new(f, Compression::default());
+        write_lines(&mut encoder, &lines).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(lines, read_lines_in_file(&compressed).unwrap());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_lines_to_file() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let lines = vec!["#! Root Directory: /foo".to_owned(), "".to_owned()];
+        let path = Path::new(TEST_DATA_DIR).join("snap.txt.gz");
+
+        write_lines_to_file(&path, &lines, true, false).unwrap();
+        assert_eq!(lines, read_lines_in_file(&path).unwrap());
+
+        // Existing file is not overwritten unless specified
+        let new_lines = vec!["#! Root Directory: /bar".to_owned()];
+        match write_lines_to_file(&path, &new_lines, false, false) {
+            Err(e) => assert_eq!(io::ErrorKind::AlreadyExists, e.kind()),
+            Ok(_) => assert!(false),
+        }
+        assert_eq!(lines, read_lines_in_file(&path).unwrap());
+        write_lines_to_file(&path, &new_lines, false, true).unwrap();
+        assert_eq!(new_lines, read_lines_in_file(&path).unwrap());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
     }
-    Ok(result)
 }
@@ -0,0 +1,59 @@
+//! Find duplicate files and de-duplicate them through an editable
+//! snapshot.
+//!
+//! The items re-exported at the crate root form the public API for
+//! embedding duplicate detection in other tools without shelling out
+//! to the `dupenukem` binary. A typical workflow is to scan a
+//! directory for duplicates, render the resulting [`Snapshot`] (or
+//! modify the ops of its paths), parse it back and [`validate`] it to
+//! get the actions which can then be [`execute`]d.
+//!
+//! ```
+//! use dupenukem::{textformat, ScanOptions, Snapshot};
+//! use std::fs;
+//!
+//! let rootdir = std::env::temp_dir().join("dupenukem-doctest");
+//! fs::create_dir_all(&rootdir).unwrap();
+//! fs::write(rootdir.join("a.txt"), "Hello").unwrap();
+//! fs::write(rootdir.join("b.txt"), "Hello").unwrap();
+//!
+//! let opts = ScanOptions::default();
+//! let (snap, _stats) = Snapshot::of_rootdir(&rootdir, &opts, &false, &false, None).unwrap();
+//! assert_eq!(1, snap.duplicates().len());
+//!
+//! let lines = textformat::render(&snap);
+//! assert!(lines.contains(&"keep a.txt".to_owned()));
+//! assert!(lines.contains(&"keep b.txt".to_owned()));
+//!
+//! // The rendered snapshot can be parsed back
+//! let parsed = textformat::parse(lines).unwrap();
+//! assert!(snap.duplicates() == parsed.duplicates());
+//!
+//! fs::remove_dir_all(&rootdir).unwrap();
+//! ```
+
+#[doc(hidden)]
+pub mod backups;
+#[doc(hidden)]
+pub mod cache;
+pub mod error;
+pub mod executor;
+pub mod fileutil;
+pub mod hash;
+#[doc(hidden)]
+pub mod interactive;
+#[doc(hidden)]
+pub mod ioutil;
+#[doc(hidden)]
+pub mod relink;
+pub mod scanner;
+pub mod snapshot;
+#[doc(hidden)]
+pub mod undo;
+
+pub use error::AppError;
+pub use executor::{execute, Action};
+pub use hash::Checksum;
+pub use scanner::{scan, ScanOptions};
+pub use snapshot::validation::validate;
+pub use snapshot::{textformat, FileOp, FilePath, Snapshot};
@@ -1,117 +1,911 @@
-use crate::error::AppError;
-use crate::snapshot::{textformat, Snapshot};
 use chrono::offset::Local;
-use clap::{self, Parser, Subcommand};
+use clap::{self, Args, Parser, Subcommand, ValueEnum};
 use dirs::home_dir;
+use dupenukem::cache::HashCache;
+use dupenukem::error::AppError;
+use dupenukem::executor::{self, DryRunFormat};
+use dupenukem::fileutil::{self, Backup, SymlinkStyle};
+use dupenukem::hash::{Checksum, ContentHash, VerificationHash};
+use dupenukem::scanner::{self, Excludes, ScanOptions, ScanStats, Verification};
+use dupenukem::snapshot::{
+    self, checksumsformat, csvformat, jsonformat, textformat, validation, DefaultOp, KeeperRule,
+    Snapshot,
+};
+use dupenukem::{backups, interactive, ioutil, relink, undo};
 use inquire::Confirm;
-use log::{debug, info};
-use std::collections::HashSet;
+use log::{debug, info, warn};
+use size::Size;
+use std::env;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
 
-mod error;
-mod executor;
-mod fileutil;
-mod hash;
-mod ioutil;
-mod scanner;
-mod snapshot;
+/// Parses human readable size e.g. `10M` into no. of bytes
+///
+/// The suffixes `K`, `M`, `G` and `T` (case insensitive) are
+/// supported and are considered as powers of 1024. Without a suffix,
+/// the value is considered as no. of bytes.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (num, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1_u64 << 10),
+        Some('M') => (&s[..s.len() - 1], 1_u64 << 20),
+        Some('G') => (&s[..s.len() - 1], 1_u64 << 30),
+        Some('T') => (&s[..s.len() - 1], 1_u64 << 40),
+        _ => (s, 1_u64),
+    };
+    num.trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or(format!("Invalid size: {}", s))
+}
+
+/// Parses human readable duration e.g. `30d` into `Duration`
+///
+/// The suffixes `s`, `m`, `h`, `d` and `w` are supported for
+/// seconds, minutes, hours, days and weeks respectively. A suffix is
+/// required.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let multiplier = match s.chars().last() {
+        Some('s') => 1_u64,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration: {}", s)),
+    };
+    s[..s.len() - 1]
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .map(Duration::from_secs)
+        .ok_or(format!("Invalid duration: {}", s))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Checksums,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BackupMode {
+    Dir,
+    Trash,
+    #[value(name = "none")]
+    NoBackup,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum VerifyMethod {
+    Hash,
+    Bytes,
+}
+
+#[derive(Args)]
+struct ScanArgs {
+    #[arg(
+        long,
+        help = "Exclude paths (absolute or relative to the rootdir). Patterns containing any of *, ?, [ or { are matched as globs against the relative path of every file and dir (note that * also matches /, so *.tmp excludes such files at any depth), others are matched exactly e.g. --exclude .git --exclude '**/node_modules/**'"
+    )]
+    exclude: Option<Vec<String>>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Quick mode in which sha256 comparison is skipped and only xxhash3(64) hashes are compared instead"
+    )]
+    quick: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = VerifyMethod::Hash,
+        help = "Method for confirming duplicates i.e. comparing hashes or bytes (ignored in quick mode)"
+    )]
+    verify: VerifyMethod,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = VerificationHash::Sha256,
+        help = "Hashing algorithm for confirming duplicates (ignored in quick mode)"
+    )]
+    verify_hash: VerificationHash,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ContentHash::Raw,
+        help = "Contents of the files to be considered for finding duplicates. With 'images', only the image data of JPEG and PNG files is hashed, leaving out metadata such as EXIF"
+    )]
+    content_hash: ContentHash,
+    #[arg(
+        long,
+        help = "Max no. of threads to use for hashing files. If not specified, it defaults to the no. of CPUs"
+    )]
+    jobs: Option<usize>,
+    #[arg(long, help = "Donot use the on-disk cache of file hashes")]
+    no_cache: bool,
+    #[arg(long, help = "Show progress bar (on stderr) while scanning")]
+    progress: bool,
+    #[arg(
+        long,
+        help = "Descend into symlinked directories that resolve to a path inside the rootdir"
+    )]
+    follow_symlinks: bool,
+    #[arg(
+        long,
+        help = "Max no. of levels below the rootdir to descend into (0 means only the files directly under rootdir)"
+    )]
+    max_depth: Option<usize>,
+    #[arg(
+        long,
+        help = "Only consider files having this extension (case insensitive) e.g. --ext jpg --ext png"
+    )]
+    ext: Option<Vec<String>>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Skip hidden files and dirs (i.e. names starting with '.') found inside the rootdir"
+    )]
+    skip_hidden: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Skip paths ignored by .gitignore files found in the rootdir and its sub dirs"
+    )]
+    respect_gitignore: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report hardlinks to the same file as duplicates (by default only one of them is considered)"
+    )]
+    keep_hardlinks: bool,
+    #[arg(
+        long,
+        value_parser = parse_size,
+        help = "Ignore files smaller than this size. Accepts suffixes K, M, G and T e.g. 10M"
+    )]
+    min_size: Option<u64>,
+    #[arg(
+        long,
+        value_parser = parse_size,
+        help = "Ignore files larger than this size. Accepts suffixes K, M, G and T e.g. 1G"
+    )]
+    max_size: Option<u64>,
+    #[arg(
+        long,
+        help = "Report empty (zero-length) files as a group of duplicates. By default, they are skipped"
+    )]
+    include_empty: bool,
+    #[arg(
+        long,
+        default_value_t = 2,
+        help = "Only report groups having at least these many copies of the file"
+    )]
+    min_copies: usize,
+    #[arg(
+        long,
+        help = "Warn about paths that differ only by case, as they may clash on case-insensitive filesystems. Doesn't affect the duplicates found"
+    )]
+    case_insensitive_paths: bool,
+    #[arg(
+        long,
+        help = "Exclude files that get modified while the scan is in progress, as their checksums may be stale"
+    )]
+    skip_modified: bool,
+    #[arg(
+        long,
+        help = "Previously generated snapshot whose checksums can be reused for unmodified files"
+    )]
+    baseline: Option<PathBuf>,
+    #[arg(
+        required = true,
+        value_name = "ROOTDIR",
+        help = "Dir to be scanned. Multiple non-overlapping dirs can be specified to find duplicates across them, in which case paths under the dirs other than the first one are recorded as absolute paths in the snapshot"
+    )]
+    rootdirs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+struct FindArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(long, help = "Donot list symlinks in snapshot output")]
+    skip_deduped: bool,
+    #[arg(
+        long,
+        help = "Donot list groups that need no further action i.e. the ones that are de-duped or contain only one distinct file (others being symlinks or hardlinks to it)"
+    )]
+    skip_done: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Format of the snapshot output. Text and JSON formats are accepted by the validate and apply commands, whereas CSV is only meant for reviewing and checksums (one '<checksum> <path>' line per file) for exporting"
+    )]
+    format: OutputFormat,
+    #[arg(
+        long,
+        help = "Append the size and modification date of every file as a trailing comment to its line. Only applies to the text format"
+    )]
+    annotate: bool,
+    #[arg(
+        long,
+        help = "Don't append the reference of the ops as comments at the end. Only applies to the text format"
+    )]
+    no_help: bool,
+    #[arg(
+        long,
+        help = "Compress the output using gzip. Compressed snapshot files are accepted by the validate and apply commands"
+    )]
+    gzip: bool,
+    #[arg(
+        long,
+        help = "Print stats about the scan (no. of files at every stage and time taken for hashing) to stderr"
+    )]
+    stats: bool,
+    #[arg(
+        long,
+        value_enum,
+        help = "Rule for choosing the file to keep in every group. The rest of the files are marked with the op specified by '--default-op'. If not specified, the lexicographically first path is kept"
+    )]
+    keeper: Option<KeeperRule>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Op with which the files other than the keeper are marked. Files that are already symlinks retain their op [default: symlink if '--keeper' is specified]"
+    )]
+    default_op: Option<DefaultOp>,
+    #[arg(
+        long,
+        help = "Dir (absolute or relative to the rootdir) whose files are preferred as keepers over the ones outside it. Can be specified multiple times, in which case the earlier ones take precedence. Ties are broken as per '--keeper'"
+    )]
+    prefer: Option<Vec<PathBuf>>,
+    #[arg(long, help = "Write the snapshot to this file instead of stdout")]
+    output: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "output",
+        help = "Overwrite the output file if it already exists"
+    )]
+    force: bool,
+}
+
+#[derive(Args)]
+struct SizeArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(long, help = "Also print the reclaimable space of every group")]
+    by_group: bool,
+    #[arg(
+        long,
+        help = "Print the space as a bare integer no. of bytes (useful for scripting)"
+    )]
+    bytes: bool,
+}
+
+#[derive(Args)]
+struct ApplyArgs {
+    #[arg(long, help = "Read text from std input")]
+    stdin: bool,
+    #[arg(
+        long,
+        help = "Dry run i.e. the actions will only be logged and not actually run"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DryRunFormat::Human,
+        help = "Format of the dry run output. Human readable messages are printed to stderr, whereas tab separated values (action, path, source/dest, no-op) are printed to stdout. With 'json', the planned actions are printed to stdout as a JSON array in addition to the human readable messages on stderr"
+    )]
+    dry_run_format: DryRunFormat,
+    #[arg(
+        long,
+        short = 'y',
+        help = "Don't ask for confirmation before executing the changes. Meant for automation, e.g. scripts and cron jobs. Backups are still taken as per '--backup-mode'"
+    )]
+    yes: bool,
+    #[arg(long, help = "Allow deletion of all files in a group")]
+    allow_full_deletion: bool,
+    #[arg(
+        long,
+        help = "Don't check whether files have been modified since the snapshot was generated"
+    )]
+    ignore_mtime: bool,
+    #[arg(
+        long,
+        help = "Skip the groups that fail validation and apply the changes in the rest of them"
+    )]
+    skip_invalid: bool,
+    #[arg(
+        long,
+        value_name = "SUBSTR",
+        help = "Only consider the groups in which at least one path contains the substring. Can be specified multiple times, in which case a group matching any of them is considered"
+    )]
+    only: Option<Vec<String>>,
+    #[arg(
+        long,
+        help = "After applying the changes, remove the dirs that have become empty (bottom-up, stopping at the rootdir)"
+    )]
+    prune_empty_dirs: bool,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "After applying the changes, mirror the kept files into this dir (preserving the structure relative to the rootdir)"
+    )]
+    consolidate: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "consolidate",
+        help = "Consolidate the kept files by hardlinking instead of copying them"
+    )]
+    consolidate_with_hardlinks: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = BackupMode::Dir,
+        help = "How to safeguard files before removing them. 'dir' copies them to the backup dir, 'trash' moves them to the trash of the OS and 'none' deletes them permanently"
+    )]
+    backup_mode: BackupMode,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "No. of times to retry a filesystem operation that fails due to a transient error (e.g. on a flaky network share), with backoff"
+    )]
+    retries: u32,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = SymlinkStyle::Relative,
+        help = "Style of the symlinks created for implicit source paths. 'preserve' matches the style of the existing (broken) symlink if any, else relative. Explicit source paths are always used as specified"
+    )]
+    symlink_style: SymlinkStyle,
+    #[arg(
+        long,
+        help = "Max no. of threads to use for executing the actions. If not specified, it defaults to the no. of CPUs"
+    )]
+    jobs: Option<usize>,
+    #[arg(
+        long,
+        help = "Custom backup directory. If not specified, a default one based on current timestamp will be used (under $DUPENUKEM_BACKUP_ROOT if set). Only applicable with '--backup-mode dir'"
+    )]
+    backup_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["stdin", "snapshot_path"],
+        help = "Apply the snapshots whose paths are listed in the file (one per line) in turn, continuing past the ones that fail. Backups of every snapshot are taken in a separate sub dir (named after its position in the list) of the backup dir"
+    )]
+    snapshots_from: Option<PathBuf>,
+    snapshot_path: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct InteractiveArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(
+        long,
+        help = "Apply the changes right away instead of printing the snapshot to stdout. Backup is taken in the default backup dir"
+    )]
+    apply: bool,
+}
 
 #[derive(Subcommand)]
 enum Command {
     #[command(about = "Find duplicates and generate a snapshot (text representation)")]
-    Find {
-        #[arg(long, help = "Exclude (relative) paths")]
-        exclude: Option<Vec<String>>,
-        #[arg(
-            long,
-            default_value_t = false,
-            help = "Quick mode in which sha256 comparison is skipped and only xxhash3(64) hashes are compared instead"
-        )]
-        quick: bool,
-        #[arg(long, help = "Donot list symlinks in snapshot output")]
-        skip_deduped: bool,
-        rootdir: PathBuf,
-    },
+    Find(FindArgs),
 
-    #[command(about = "Validate snapshot (from text representation)")]
+    #[command(
+        about = "Validate snapshot (from text representation)",
+        after_help = "Exit codes: 0 if the snapshot is valid and there are no pending actions, 2 if it's valid but there are pending actions, 3 if it's valid except for the groups skipped with '--skip-invalid' (irrespective of pending actions), 1 if it's invalid or in case of any other error (e.g. invalid arguments)"
+    )]
     Validate {
         #[arg(long, help = "Read text from std input")]
         stdin: bool,
         #[arg(long, help = "Allow deletion of all files in a group")]
         allow_full_deletion: bool,
+        #[arg(long, help = "Print the result of validation as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            help = "Don't check whether files have been modified since the snapshot was generated"
+        )]
+        ignore_mtime: bool,
+        #[arg(
+            long,
+            help = "Skip the groups that fail validation instead of failing the validation of the entire snapshot"
+        )]
+        skip_invalid: bool,
+        #[arg(
+            long,
+            value_name = "SUBSTR",
+            help = "Only consider the groups in which at least one path contains the substring. Can be specified multiple times, in which case a group matching any of them is considered"
+        )]
+        only: Option<Vec<String>>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["stdin", "snapshot_path", "json"],
+            help = "Validate the snapshots whose paths are listed in the file (one per line) in turn, continuing past the invalid ones"
+        )]
+        snapshots_from: Option<PathBuf>,
         snapshot_path: Option<PathBuf>,
     },
 
     #[command(about = "Apply changes from snapshot file")]
-    Apply {
+    Apply(ApplyArgs),
+
+    #[command(
+        about = "Verify every path in the snapshot against the files on disk, reporting all the problems found",
+        after_help = "Checksums of all files are computed afresh (the hash cache is not used). Exit codes: 0 if no problems are found, 1 otherwise"
+    )]
+    Verify {
         #[arg(long, help = "Read text from std input")]
         stdin: bool,
+        #[arg(long, help = "Allow deletion of all files in a group")]
+        allow_full_deletion: bool,
         #[arg(
             long,
-            help = "Dry run i.e. the actions will only be logged and not actually run"
+            help = "Don't check whether files have been modified since the snapshot was generated"
         )]
-        dry_run: bool,
+        ignore_mtime: bool,
+        snapshot_path: Option<PathBuf>,
+    },
+
+    #[command(about = "Print a summary of the changes in the snapshot without applying them")]
+    Report {
+        #[arg(long, help = "Read text from std input")]
+        stdin: bool,
         #[arg(long, help = "Allow deletion of all files in a group")]
         allow_full_deletion: bool,
         #[arg(
             long,
-            help = "Custom backup directory. If not specified, a default one based on current timestamp will be used"
+            help = "Don't check whether files have been modified since the snapshot was generated"
         )]
-        backup_dir: Option<PathBuf>,
+        ignore_mtime: bool,
+        #[arg(long, help = "Print the summary as JSON")]
+        json: bool,
         snapshot_path: Option<PathBuf>,
     },
+
+    #[command(
+        about = "Compare two snapshots and print the groups and paths that differ between them"
+    )]
+    Diff {
+        old_snapshot: PathBuf,
+        new_snapshot: PathBuf,
+    },
+
+    #[command(about = "Find duplicates and print the total space that can be reclaimed")]
+    Size(SizeArgs),
+
+    #[command(
+        about = "Find duplicates and choose which file to keep in every group interactively"
+    )]
+    Interactive(InteractiveArgs),
+
+    #[command(about = "Restore files from a backup dir to their original locations")]
+    Undo {
+        #[arg(long, help = "Root directory under which the files are to be restored")]
+        rootdir: PathBuf,
+        #[arg(
+            long,
+            help = "Dry run i.e. the files to be restored will only be logged and not actually restored"
+        )]
+        dry_run: bool,
+        #[arg(
+            long,
+            help = "Overwrite files whose current contents differ from the backup"
+        )]
+        force: bool,
+        backup_dir: PathBuf,
+    },
+
+    #[command(
+        about = "Repoint broken symlinks to other copies of their original sources (by hash)"
+    )]
+    Relink {
+        #[arg(
+            long,
+            help = "Dry run i.e. the symlinks to be repointed will only be logged and not actually repointed"
+        )]
+        dry_run: bool,
+        rootdir: PathBuf,
+    },
+
+    #[command(about = "Manage the timestamped backup dirs")]
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum BackupsCommand {
+    #[command(
+        about = "Remove the oldest backup dirs as per the retention policy",
+        after_help = "Only the dirs named after a timestamp (as created by the tool) are considered; other entries are left untouched. If both '--keep' and '--older-than' are specified, only the dirs older than the duration, other than the latest N, are removed"
+    )]
+    Prune {
+        #[arg(long, value_name = "N", help = "No. of latest backup dirs to retain")]
+        keep: Option<usize>,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            help = "Remove backup dirs older than this duration. Accepts suffixes s, m, h, d and w e.g. 30d"
+        )]
+        older_than: Option<Duration>,
+        #[arg(
+            long,
+            help = "Dry run i.e. the backup dirs to be removed will only be logged and not actually removed"
+        )]
+        dry_run: bool,
+        #[arg(
+            long,
+            short = 'y',
+            help = "Don't ask for confirmation before removing the backup dirs"
+        )]
+        yes: bool,
+        #[arg(
+            long,
+            help = "Dir under which the backup dirs are located. Defaults to $DUPENUKEM_BACKUP_ROOT if set, else ~/.dupenukem/backups"
+        )]
+        backup_root: Option<PathBuf>,
+    },
 }
 
 #[derive(Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    after_help = "Exit codes: 0 on success and 1 in case of an error, including invalid usage (e.g. missing or conflicting arguments). The 'validate' and 'verify' commands use the exit code to report the result as mentioned in their help"
+)]
 struct Cli {
     #[arg(short, global = true, action = clap::ArgAction::Count, help = "Verbosity level (can be specified multiple times)")]
     verbose: u8,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Format of the log messages. With 'json', every message is logged as a JSON object on a single line"
+    )]
+    log_format: LogFormat,
+    #[arg(
+        long,
+        global = true,
+        value_parser = parse_size,
+        help = "Files larger than this size (e.g. 64M) are memory mapped for hashing instead of being read in chunks. Defaults to 16M"
+    )]
+    mmap_threshold: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "mmap_threshold",
+        help = "Don't memory map files for hashing. A mapped file that gets truncated by another process while it's being hashed causes the process to crash (SIGBUS)"
+    )]
+    no_mmap: bool,
     #[command(subcommand)]
     command: Option<Command>,
 }
 
-fn cmd_find(
-    rootdir: &Path,
-    exclude: Option<&Vec<String>>,
-    quick: &bool,
+/// Initializes the global rayon thread pool with `n` threads
+fn init_thread_pool(n: usize) -> Result<(), AppError> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(n)
+        .build_global()
+        .map_err(|e| AppError::Cmd(format!("Couldn't initialize thread pool: {}", e)))
+}
+
+/// Returns an error if the rootdir specified as the cli arg is not an
+/// existing directory
+fn check_rootdir_arg(rootdir: &Path) -> Result<(), AppError> {
+    if !rootdir.exists() {
+        Err(AppError::Cmd(format!(
+            "Root directory doesn't exist: {}",
+            rootdir.display()
+        )))
+    } else if !rootdir.is_dir() {
+        Err(AppError::Cmd(format!(
+            "Root directory is not a directory: {}",
+            rootdir.display()
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Scans the rootdir as per the `args` and returns the snapshot
+fn scan_rootdir(
+    args: &ScanArgs,
     skip_deduped: &bool,
-) -> Result<(), AppError> {
-    let rootdir = if !rootdir.is_absolute() {
-        info!("Relative path found for the specified rootdir. Normalizing it to absolute path");
-        rootdir.canonicalize().map_err(AppError::Io)?
+    skip_done: &bool,
+) -> Result<(Snapshot, ScanStats), AppError> {
+    if let Some(n) = args.jobs {
+        info!("Limiting the no. of threads for hashing to {}", n);
+        init_thread_pool(n)?;
+    }
+    let mut rootdirs: Vec<PathBuf> = Vec::with_capacity(args.rootdirs.len());
+    for rootdir in args.rootdirs.iter() {
+        check_rootdir_arg(rootdir)?;
+        let rootdir = if !rootdir.is_absolute() {
+            info!("Relative path found for the specified rootdir. Normalizing it to absolute path");
+            rootdir.canonicalize().map_err(AppError::Io)?
+        } else {
+            // @NOTE: How to avoid creating a copy here?
+            rootdir.to_path_buf()
+        };
+        // A file under overlapping rootdirs would otherwise be
+        // found twice and be reported as a duplicate of itself
+        if let Some(other) = rootdirs
+            .iter()
+            .find(|d| d.starts_with(&rootdir) || rootdir.starts_with(d))
+        {
+            return Err(AppError::Cmd(format!(
+                "Rootdirs must not overlap: {} and {}",
+                other.display(),
+                rootdir.display()
+            )));
+        }
+        info!("Generating snapshot for dir: {}", rootdir.display());
+        rootdirs.push(rootdir);
+    }
+    let patterns = args.exclude.clone().unwrap_or_default();
+    if !patterns.is_empty() {
+        info!("Exclusions: {}", patterns.join(", "));
+    }
+    let mut excludes = Excludes::of_rootdirs(&rootdirs, &patterns)
+        .map_err(|e| AppError::Cmd(format!("Invalid exclude pattern: {}", e)))?;
+    // Exclusions persisted in the rootdirs are added to the ones
+    // specified on the command line
+    for rootdir in rootdirs.iter() {
+        if let Some(patterns) = scanner::read_ignore_file(rootdir).map_err(AppError::Io)? {
+            info!(
+                "Exclusions from {}: {}",
+                rootdir.join(scanner::IGNORE_FILE).display(),
+                patterns.join(", ")
+            );
+            excludes.add_ignore_file(rootdir, &patterns).map_err(|e| {
+                AppError::Cmd(format!(
+                    "Invalid exclude pattern in {}: {}",
+                    rootdir.join(scanner::IGNORE_FILE).display(),
+                    e
+                ))
+            })?;
+        }
+    }
+    let excludes = if excludes.is_empty() {
+        None
     } else {
-        // @NOTE: How to avoid creating a copy here?
-        rootdir.to_path_buf()
+        Some(excludes)
     };
-    let excludes = exclude.map(|paths| HashSet::from_iter(paths.iter().map(|p| rootdir.join(p))));
-    info!("Generating snapshot for dir: {}", rootdir.display());
-    if let Some(exs) = &excludes {
-        info!(
-            "Exclusions: {}",
-            exs.iter()
-                .map(|p| p.display().to_string())
-                .collect::<Vec<String>>()
-                .join(", ")
-        );
+    let cache = if args.no_cache {
+        None
+    } else {
+        let cache_path = default_cache_path();
+        match HashCache::load(&cache_path) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                warn!("Couldn't load hash cache {}: {}", cache_path.display(), e);
+                None
+            }
+        }
+    };
+    let baseline = match &args.baseline {
+        Some(p) => {
+            info!("Reusing checksums from baseline snapshot: {}", p.display());
+            let input = read_input(Some(p), &false)?;
+            Some(snapshot::parse(input)?.baseline())
+        }
+        None => None,
+    };
+    let scan_opts = ScanOptions {
+        excludes,
+        quick: args.quick,
+        verification: match args.verify {
+            VerifyMethod::Hash => Verification::Hash(args.verify_hash),
+            VerifyMethod::Bytes => Verification::Bytes,
+        },
+        progress: args.progress,
+        follow_symlinks: args.follow_symlinks,
+        max_depth: args.max_depth,
+        extensions: args.ext.as_ref().map(|exts| {
+            exts.iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect()
+        }),
+        skip_hidden: args.skip_hidden,
+        respect_gitignore: args.respect_gitignore,
+        keep_hardlinks: args.keep_hardlinks,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        min_copies: args.min_copies,
+        case_insensitive_paths: args.case_insensitive_paths,
+        skip_modified: args.skip_modified,
+        baseline,
+        content_hash: args.content_hash,
+        include_empty: args.include_empty,
+    };
+    let (snap, stats) = Snapshot::of_rootdirs(
+        &rootdirs,
+        &scan_opts,
+        skip_deduped,
+        skip_done,
+        cache.as_ref(),
+    )
+    .map_err(AppError::Io)?;
+    if let Some(c) = &cache {
+        c.save()
+            .unwrap_or_else(|e| warn!("Couldn't save hash cache: {}", e));
+    }
+    Ok((snap, stats))
+}
+
+/// Returns the rootdirs of the snapshot as a comma separated string
+fn display_rootdirs(snap: &Snapshot) -> String {
+    snap.rootdirs()
+        .iter()
+        .map(|d| d.display().to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn cmd_find(args: &FindArgs) -> Result<(), AppError> {
+    // Fail early instead of after scanning the rootdir
+    if let Some(p) = &args.output {
+        if p.exists() && !args.force {
+            return Err(AppError::Cmd(format!(
+                "Output file {} already exists. Use '--force' to overwrite it",
+                p.display()
+            )));
+        }
     }
-    let snap = Snapshot::of_rootdir(&rootdir, excludes.as_ref(), quick, skip_deduped)
-        .map_err(AppError::Io)?;
-    snap.freeable_space()
-        .map(|total| info!("A max of {} space can be freed by deduplication", total))
-        .map_err(AppError::Io)?;
-    let output = textformat::render(&snap);
-    if !output.is_empty() {
-        for line in output.iter() {
-            println!("{}", line);
+    let (mut snap, stats) = scan_rootdir(&args.scan, &args.skip_deduped, &args.skip_done)?;
+    if args.stats {
+        eprintln!("Files traversed: {}", stats.files_traversed);
+        eprintln!("Files with the same size: {}", stats.size_candidates);
+        eprintln!("Files with the same xxh3 checksum: {}", stats.xxh3_grouped);
+        if args.scan.quick {
+            eprintln!("Groups found (not confirmed): {}", stats.groups);
+        } else {
+            eprintln!("Groups confirmed: {}", stats.groups);
         }
+        eprintln!("Hashing time: {:.2?}", stats.hashing_time);
+    }
+    if args.keeper.is_some() || args.default_op.is_some() || args.prefer.is_some() {
+        let op = args.default_op.unwrap_or(DefaultOp::Symlink);
+        snap.mark_duplicates(args.keeper, args.prefer.as_deref().unwrap_or(&[]), op)
+            .map_err(AppError::Io)?;
+    }
+    let freeable = snap.freeable_space().map_err(AppError::Io)?;
+    info!("A max of {} space can be freed by deduplication", freeable);
+    if (args.annotate || args.no_help) && !matches!(args.format, OutputFormat::Text) {
+        warn!("Ignoring '--annotate' and '--no-help' as they only apply to the text format");
+    }
+    let output = match args.format {
+        OutputFormat::Text => textformat::render_with(
+            &snap,
+            &textformat::RenderOptions {
+                annotate: args.annotate,
+                help: !args.no_help,
+            },
+        ),
+        OutputFormat::Json => jsonformat::render(&snap),
+        OutputFormat::Csv => csvformat::render(&snap),
+        OutputFormat::Checksums => checksumsformat::render(&snap),
+    };
+    if output.is_empty() {
+        eprintln!(
+            "No duplicates found under path: {}",
+            display_rootdirs(&snap)
+        );
     } else {
-        eprintln!("No duplicates found under path: {}", rootdir.display());
+        match &args.output {
+            Some(p) => {
+                ioutil::write_lines_to_file(p, &output, args.gzip, args.force)
+                    .map_err(AppError::Io)?;
+                eprintln!("Snapshot written to {}", p.display());
+            }
+            None => ioutil::write_lines_to_stdout(&output, args.gzip).map_err(AppError::Io)?,
+        }
+    }
+    eprintln!("{}", find_summary(&snap, &stats, freeable));
+    Ok(())
+}
+
+/// Returns a one line summary of the snapshot generated by the
+/// `find` command e.g. "Scanned 120 file(s), found 2 group(s) of
+/// duplicates having 5 file(s) (1 group(s) hidden). 2.50 KiB can be
+/// reclaimed"
+fn find_summary(snap: &Snapshot, stats: &ScanStats, freeable: Size) -> String {
+    let num_files: usize = snap.duplicates().values().map(|fps| fps.len()).sum();
+    let hidden = if stats.groups_hidden > 0 {
+        format!(" ({} group(s) hidden)", stats.groups_hidden)
+    } else {
+        String::new()
+    };
+    format!(
+        "Scanned {} file(s), found {} group(s) of duplicates having {} file(s){}. {} can be reclaimed",
+        stats.files_traversed,
+        snap.duplicates().len(),
+        num_files,
+        hidden,
+        freeable
+    )
+}
+
+fn cmd_size(args: &SizeArgs) -> Result<(), AppError> {
+    let (snap, _) = scan_rootdir(&args.scan, &false, &false)?;
+    let fmt_space = |space: u64| {
+        if args.bytes {
+            space.to_string()
+        } else {
+            Size::from_bytes(space).to_string()
+        }
+    };
+    if args.by_group {
+        for (checksum, num_paths, space) in snap.freeable_space_by_group().map_err(AppError::Io)? {
+            println!("[{}]\t{} paths\t{}", checksum, num_paths, fmt_space(space));
+        }
     }
+    let total = snap.freeable_space().map_err(AppError::Io)?;
+    println!("{}", fmt_space(total.bytes() as u64));
     Ok(())
 }
 
+fn cmd_interactive(args: &InteractiveArgs) -> Result<(), AppError> {
+    // Groups that need no further action are skipped
+    let (mut snap, _) = scan_rootdir(&args.scan, &false, &true)?;
+    let num_resolved = interactive::resolve_groups(&mut snap)?;
+    if args.apply {
+        if num_resolved == 0 {
+            eprintln!("No groups were resolved. Nothing to apply");
+            return Ok(());
+        }
+        let actions = snap.validate(&false, &false)?;
+        let summary = actions_summary(&actions, SymlinkStyle::Relative)?;
+        confirm_or_exit(
+            "All changes will be executed. Do you want to proceed?",
+            Some(&summary),
+        );
+        let backup_dir = default_backup_dir();
+        executor::execute(
+            actions,
+            &false,
+            DryRunFormat::Human,
+            Backup::Dir(&backup_dir),
+            SymlinkStyle::Relative,
+            &snap.base_dir(),
+            0,
+        )
+        .map(|_| ())
+    } else {
+        let output = textformat::render(&snap);
+        if !output.is_empty() {
+            ioutil::write_lines_to_stdout(&output, false).map_err(AppError::Io)?;
+        } else {
+            eprintln!(
+                "No duplicates found under path: {}",
+                display_rootdirs(&snap)
+            );
+        }
+        Ok(())
+    }
+}
+
 fn read_input(path: Option<&Path>, stdin: &bool) -> Result<Vec<String>, AppError> {
     match path {
         Some(p) => ioutil::read_lines_in_file(p).map_err(AppError::Io),
@@ -127,145 +921,1145 @@ fn read_input(path: Option<&Path>, stdin: &bool) -> Result<Vec<String>, AppError
     }
 }
 
+/// Reads the snapshot paths listed in the file, one per line. Blank
+/// lines and lines starting with `#` are ignored.
+fn read_snapshot_list(path: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let lines = ioutil::read_lines_in_file(path).map_err(AppError::Io)?;
+    Ok(lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Returns whether the error is due to the snapshot itself being
+/// invalid, as opposed to a failure in acting upon it
+fn is_invalid_snapshot(e: &AppError) -> bool {
+    matches!(
+        e,
+        AppError::SnapshotParsing
+            | AppError::SnapshotMetadata(_)
+            | AppError::SnapshotValidation(_)
+            | AppError::ChecksumParsing
+    )
+}
+
+/// Retains only the groups in the snapshot having a path that contains
+/// any of the `substrs`
+fn filter_groups(snap: &mut Snapshot, substrs: &[String]) {
+    let num_removed = snap.retain_groups_matching(substrs);
+    info!(
+        "Considering {} group(s) matching '--only' ({} left out)",
+        snap.duplicates().len(),
+        num_removed
+    );
+}
+
 fn cmd_validate(
     snapshot_path: Option<&Path>,
     stdin: &bool,
     allow_full_deletion: &bool,
-) -> Result<(), AppError> {
+    json: &bool,
+    ignore_mtime: &bool,
+    skip_invalid: &bool,
+    only: Option<&[String]>,
+) -> Result<Validated, AppError> {
     let input = read_input(snapshot_path, stdin)?;
-    let snapshot = textformat::parse(input)?;
-    match snapshot.validate(allow_full_deletion) {
-        Ok(actions) => {
-            println!("Snapshot is valid!");
+    validate_input(
+        &mut io::stdout(),
+        input,
+        allow_full_deletion,
+        json,
+        ignore_mtime,
+        skip_invalid,
+        only,
+    )
+}
+
+/// Validates the snapshots listed in the file in turn, returning the
+/// exit code
+///
+/// The exit code is 1 if any of the snapshots is invalid. Otherwise
+/// it's the same as that of validating a single snapshot having all
+/// the pending actions and skipped groups (see `Validated::exit_code`).
+fn cmd_validate_batch(
+    list_path: &Path,
+    allow_full_deletion: &bool,
+    ignore_mtime: &bool,
+    skip_invalid: &bool,
+    only: Option<&[String]>,
+) -> Result<i32, AppError> {
+    let paths = read_snapshot_list(list_path)?;
+    let mut num_valid = 0;
+    let mut total = Validated::default();
+    for path in paths.iter() {
+        eprintln!("==> {}", path.display());
+        // Everything is written to stderr, same as the headers, so that
+        // the output of the snapshots doesn't get interleaved across
+        // two streams
+        let result = read_input(Some(path), &false).and_then(|input| {
+            validate_input(
+                &mut io::stderr(),
+                input,
+                allow_full_deletion,
+                &false,
+                ignore_mtime,
+                skip_invalid,
+                only,
+            )
+        });
+        match result {
+            Ok(validated) => {
+                num_valid += 1;
+                total.pending += validated.pending;
+                total.skipped += validated.skipped;
+            }
+            Err(e) => print_error(&e),
+        }
+    }
+    eprintln!(
+        "{} of {} snapshot(s) are valid. No. of pending action(s): {}{}",
+        num_valid,
+        paths.len(),
+        total.pending,
+        if total.skipped > 0 {
+            format!(". No. of skipped group(s): {}", total.skipped)
+        } else {
+            String::new()
+        }
+    );
+    Ok(if num_valid < paths.len() {
+        1
+    } else {
+        total.exit_code()
+    })
+}
+
+/// Outcome of validating a snapshot
+#[derive(Default)]
+struct Validated {
+    // No. of pending actions
+    pending: usize,
+    // No. of groups skipped due to validation errors (only with
+    // `--skip-invalid`)
+    skipped: usize,
+}
+
+impl Validated {
+    /// Returns the exit code of the `validate` command, which is 3 if
+    /// any groups were skipped (as the snapshot isn't entirely
+    /// valid), 2 if there are pending actions and 0 otherwise
+    fn exit_code(&self) -> i32 {
+        if self.skipped > 0 {
+            EXIT_CODE_SKIPPED_GROUPS
+        } else if self.pending > 0 {
+            EXIT_CODE_PENDING_ACTIONS
+        } else {
+            0
+        }
+    }
+}
+
+/// Validates the snapshot and writes the result to `out`, returning
+/// the no. of pending actions and skipped groups
+///
+/// The summary of the skipped groups is printed to stderr irrespective
+/// of `out` (see `print_skipped_groups`).
+fn validate_input(
+    out: &mut impl Write,
+    input: Vec<String>,
+    allow_full_deletion: &bool,
+    json: &bool,
+    ignore_mtime: &bool,
+    skip_invalid: &bool,
+    only: Option<&[String]>,
+) -> Result<Validated, AppError> {
+    let mut snapshot = snapshot::parse(input)?;
+    if let Some(substrs) = only {
+        filter_groups(&mut snapshot, substrs);
+    }
+    let result = if *skip_invalid {
+        snapshot.validate_skipping_invalid(allow_full_deletion, ignore_mtime)
+    } else {
+        snapshot
+            .validate(allow_full_deletion, ignore_mtime)
+            .map(|actions| (actions, vec![]))
+    };
+    if *json {
+        return match result {
+            Ok((actions, skipped)) => {
+                let num_pending = executor::pending_actions(&actions, false).len();
+                let report = validation::Report {
+                    valid: skipped.is_empty(),
+                    pending: num_pending,
+                    errors: skipped
+                        .iter()
+                        .map(|(_, e)| validation::ErrorReport::from(e))
+                        .collect(),
+                };
+                writeln!(out, "{}", serde_json::to_string_pretty(&report).unwrap())
+                    .map_err(AppError::Io)?;
+                Ok(Validated {
+                    pending: num_pending,
+                    skipped: skipped.len(),
+                })
+            }
+            Err(AppError::SnapshotValidation(e)) => {
+                let report = validation::Report {
+                    valid: false,
+                    pending: 0,
+                    errors: vec![validation::ErrorReport::from(&e)],
+                };
+                writeln!(out, "{}", serde_json::to_string_pretty(&report).unwrap())
+                    .map_err(AppError::Io)?;
+                Err(AppError::SnapshotValidation(e))
+            }
+            Err(e) => Err(e),
+        };
+    }
+    match result {
+        Ok((actions, skipped)) => {
+            if skipped.is_empty() {
+                writeln!(out, "Snapshot is valid!").map_err(AppError::Io)?;
+            } else {
+                print_skipped_groups(&skipped);
+                writeln!(out, "Snapshot is valid except for the skipped group(s)")
+                    .map_err(AppError::Io)?;
+            }
             let num_pending = executor::pending_actions(&actions, false).len();
             if num_pending == 0 {
-                println!("No pending actions");
+                writeln!(out, "No pending actions").map_err(AppError::Io)?;
             } else {
-                println!("No. of pending action(s): {}", num_pending);
+                writeln!(out, "No. of pending action(s): {}", num_pending).map_err(AppError::Io)?;
             }
-            Ok(())
+            Ok(Validated {
+                pending: num_pending,
+                skipped: skipped.len(),
+            })
         }
         Err(e) => {
-            println!("Snapshot is invalid!");
+            writeln!(out, "Snapshot is invalid!").map_err(AppError::Io)?;
             Err(e)
         }
     }
 }
 
-/// Returns default backup dir derived from the current timestamp.
+/// Returns path of the file in which the hash cache is stored.
 ///
-/// The path prefix will be `~/.dupenukem/backups` if home dir can be
-/// obtained for the user otherwise it will be under the `$CWD`
+/// Similar to the backups, it will be under `~/.dupenukem/cache` if
+/// home dir can be obtained for the user otherwise it will be under
+/// the `$CWD` i.e. `./.dupenukem/cache`
+fn default_cache_path() -> PathBuf {
+    home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".dupenukem/cache/hashes.txt")
+}
+
+/// Env var for overriding the path prefix of the default backup dir
+const BACKUP_ROOT_ENV_VAR: &str = "DUPENUKEM_BACKUP_ROOT";
+
+/// Returns the dir under which the default backup dirs are created.
+///
+/// It will be the value of the `DUPENUKEM_BACKUP_ROOT` env var if
+/// it's set. Otherwise it will be `~/.dupenukem/backups` if home dir
+/// can be obtained for the user or else it will be under the `$CWD`
 /// i.e. `./.dupenukem/backups`
+fn default_backup_root() -> PathBuf {
+    match env::var_os(BACKUP_ROOT_ENV_VAR) {
+        Some(root) if !root.is_empty() => PathBuf::from(root),
+        _ => home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".dupenukem/backups"),
+    }
+}
+
+/// Returns default backup dir derived from the current timestamp,
+/// under the `default_backup_root`
 ///
 /// Example backup dir path: `~/.dupenukem/backups/20240109163803`
 ///
 fn default_backup_dir() -> PathBuf {
-    let path_prefix = home_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(".dupenukem/backups");
-    let dirname = Local::now().format("%Y%m%d%H%M%S");
-    path_prefix.join(dirname.to_string())
+    let dirname = Local::now().format(backups::DIRNAME_FORMAT);
+    default_backup_root().join(dirname.to_string())
+}
+
+/// Applies the changes, returning the exit code
+fn cmd_apply(args: &ApplyArgs) -> Result<i32, AppError> {
+    // The backup dir would otherwise be silently ignored
+    if args.backup_dir.is_some() && !matches!(args.backup_mode, BackupMode::Dir) {
+        return Err(AppError::Cmd(
+            "'--backup-dir' can only be used with '--backup-mode dir'".to_owned(),
+        ));
+    }
+    // Confirmation can't be asked for without a terminal, in which
+    // case it's better to fail early than wait for input that may
+    // never come. When the snapshot is read from stdin, the prompt
+    // uses the terminal directly.
+    let is_terminal = if args.stdin {
+        io::stderr().is_terminal()
+    } else {
+        io::stdin().is_terminal()
+    };
+    if !args.dry_run && !args.yes && !is_terminal {
+        return Err(AppError::Cmd(
+            "Can't ask for confirmation as there's no terminal. Use '--yes' to apply the changes without confirmation".to_owned(),
+        ));
+    }
+    if let Some(n) = args.jobs {
+        info!("Limiting the no. of threads for executing actions to {}", n);
+        init_thread_pool(n)?;
+    }
+    let backup_dir = args.backup_dir.clone().unwrap_or_else(default_backup_dir);
+    match &args.snapshots_from {
+        Some(list_path) => cmd_apply_batch(args, list_path, &backup_dir),
+        None => {
+            let input = read_input(args.snapshot_path.as_deref(), &args.stdin)?;
+            if apply_input(args, input, &backup_dir)?.is_none() {
+                println!("Aborting..");
+            }
+            Ok(0)
+        }
+    }
+}
+
+/// Applies the snapshots listed in the file in turn, returning the
+/// exit code, which is 1 if any of them failed and 0 otherwise
+///
+/// A snapshot whose changes are not confirmed by the user is skipped
+/// and is not considered as failed.
+fn cmd_apply_batch(args: &ApplyArgs, list_path: &Path, backup_dir: &Path) -> Result<i32, AppError> {
+    let paths = read_snapshot_list(list_path)?;
+    let mut num_valid = 0;
+    let mut num_applied = 0;
+    let mut num_declined = 0;
+    let mut reclaimed = 0_u64;
+    for (i, path) in paths.iter().enumerate() {
+        eprintln!("==> {}", path.display());
+        let input = match read_input(Some(path), &false) {
+            Ok(input) => input,
+            Err(e) => {
+                print_error(&e);
+                continue;
+            }
+        };
+        // Backups of the snapshots are kept separate, so that each of
+        // them can be undone independently
+        match apply_input(args, input, &backup_dir.join((i + 1).to_string())) {
+            Ok(Some(n)) => {
+                num_valid += 1;
+                num_applied += 1;
+                reclaimed += n;
+            }
+            Ok(None) => {
+                eprintln!("Skipped as the changes were not confirmed");
+                num_valid += 1;
+                num_declined += 1;
+            }
+            Err(e) => {
+                if !is_invalid_snapshot(&e) {
+                    num_valid += 1;
+                }
+                print_error(&e);
+            }
+        }
+    }
+    eprintln!(
+        "{} snapshot(s): {} valid, {} applied{}{}. {} {}",
+        paths.len(),
+        num_valid,
+        num_applied,
+        if args.dry_run { " (dry run)" } else { "" },
+        if num_declined > 0 {
+            format!(", {} not confirmed", num_declined)
+        } else {
+            String::new()
+        },
+        if args.dry_run {
+            "Projected to reclaim"
+        } else {
+            "Reclaimed"
+        },
+        Size::from_bytes(reclaimed)
+    );
+    Ok(if num_applied + num_declined < paths.len() {
+        1
+    } else {
+        0
+    })
 }
 
-fn cmd_apply(
+/// Applies the changes in the snapshot, returning the no. of bytes
+/// reclaimed (or projected to be reclaimed in case of dry run), or
+/// `None` if the user didn't confirm the changes
+fn apply_input(
+    args: &ApplyArgs,
+    input: Vec<String>,
+    backup_dir: &Path,
+) -> Result<Option<u64>, AppError> {
+    let mut snapshot = snapshot::parse(input)?;
+    if let Some(substrs) = &args.only {
+        filter_groups(&mut snapshot, substrs);
+    }
+    let backup = match args.backup_mode {
+        BackupMode::Dir => Backup::Dir(backup_dir),
+        BackupMode::Trash => Backup::Trash,
+        BackupMode::NoBackup => Backup::Disabled,
+    };
+    let (actions, skipped) = if args.skip_invalid {
+        snapshot.validate_skipping_invalid(&args.allow_full_deletion, &args.ignore_mtime)?
+    } else {
+        (
+            snapshot.validate(&args.allow_full_deletion, &args.ignore_mtime)?,
+            vec![],
+        )
+    };
+    if args.yes {
+        debug!("Skipping confirmation as '--yes' is specified");
+    } else if !args.dry_run {
+        let summary = actions_summary(&actions, args.symlink_style)?;
+        if !confirm(
+            "All changes will be executed. Do you want to proceed?",
+            Some(&summary),
+        )? {
+            return Ok(None);
+        }
+    }
+    let prune_candidates = executor::PruneCandidates::of_actions(&actions);
+    let kept_paths = executor::kept_paths(&actions);
+    // Paths are displayed and backed up relative to the common
+    // ancestor in case of multiple rootdirs
+    let base_dir = snapshot.base_dir();
+    let result = executor::execute(
+        actions,
+        &args.dry_run,
+        args.dry_run_format,
+        backup,
+        args.symlink_style,
+        &base_dir,
+        args.retries,
+    )
+    .and_then(|reclaimed| {
+        if args.prune_empty_dirs {
+            // Pruning stops at each of the rootdirs
+            snapshot.rootdirs().into_iter().try_for_each(|rootdir| {
+                executor::prune_empty_dirs(&prune_candidates, &args.dry_run, rootdir)
+            })?;
+        }
+        Ok(reclaimed)
+    })
+    .and_then(|reclaimed| {
+        if let Some(dir) = &args.consolidate {
+            executor::consolidate(
+                &kept_paths,
+                dir,
+                args.consolidate_with_hardlinks,
+                &args.dry_run,
+                &base_dir,
+            )?;
+        }
+        Ok(reclaimed)
+    });
+    if !skipped.is_empty() {
+        print_skipped_groups(&skipped);
+    }
+    result.map(Some)
+}
+
+/// Verifies the snapshot and prints all the problems found, returning
+/// their count
+fn cmd_verify(
+    snapshot_path: Option<&Path>,
+    stdin: &bool,
+    allow_full_deletion: &bool,
+    ignore_mtime: &bool,
+) -> Result<usize, AppError> {
+    let input = read_input(snapshot_path, stdin)?;
+    let snapshot = snapshot::parse(input)?;
+    let problems = snapshot.verify(allow_full_deletion, ignore_mtime)?;
+    for (checksum, path, e) in problems.iter() {
+        match path {
+            Some(p) => println!("[{}] {}: {}", checksum, p.display(), e.message()),
+            None => println!("[{}] {}", checksum, e.message()),
+        }
+    }
+    let num_files = snapshot
+        .duplicates()
+        .values()
+        .map(|fps| fps.len())
+        .sum::<usize>();
+    if problems.is_empty() {
+        eprintln!(
+            "Verified {} file(s) in {} group(s). No problems found",
+            num_files,
+            snapshot.duplicates().len()
+        );
+    } else {
+        eprintln!(
+            "Verified {} file(s) in {} group(s). Found {} problem(s)",
+            num_files,
+            snapshot.duplicates().len(),
+            problems.len()
+        );
+    }
+    Ok(problems.len())
+}
+
+/// Prints a summary of the groups skipped due to validation errors
+/// to stderr
+fn print_skipped_groups(skipped: &[(&Checksum, validation::Error)]) {
+    eprintln!("Skipped {} invalid group(s):", skipped.len());
+    for (checksum, e) in skipped {
+        eprintln!("  [{}] {}", checksum, e.message());
+    }
+}
+
+fn cmd_diff(old_path: &Path, new_path: &Path) -> Result<(), AppError> {
+    let old = snapshot::parse(read_input(Some(old_path), &false)?)?;
+    let new = snapshot::parse(read_input(Some(new_path), &false)?)?;
+    let group_diffs = snapshot::diff::diff(&old, &new);
+    if group_diffs.is_empty() {
+        eprintln!("No differences found");
+        return Ok(());
+    }
+    let mut output: Vec<String> = Vec::new();
+    for group_diff in group_diffs.iter() {
+        output.extend(group_diff.render());
+        output.push(String::from(""));
+    }
+    ioutil::write_lines_to_stdout(&output, false).map_err(AppError::Io)
+}
+
+fn cmd_report(
     snapshot_path: Option<&Path>,
     stdin: &bool,
-    dry_run: &bool,
     allow_full_deletion: &bool,
-    backup_dir: Option<&Path>,
+    ignore_mtime: &bool,
+    json: &bool,
 ) -> Result<(), AppError> {
     let input = read_input(snapshot_path, stdin)?;
-    let snapshot = textformat::parse(input)?;
-    // A tmp let binding for default backup dir is required here
-    // because the fallback value in `unwrap_or` is a pointer and not
-    // a value.
-    let dbd = default_backup_dir();
-    let backup_dir_path = backup_dir.unwrap_or(dbd.as_ref());
-    snapshot.validate(allow_full_deletion).and_then(|actions| {
-        if !*dry_run {
-            let ans = Confirm::new("All changes will be executed. Do you want to proceed?")
-                .with_default(false)
-                .with_help_message(
-                    "Tip: To see the changes run the command with '--dry-run' option",
-                )
-                .prompt();
-            match ans {
-                Ok(true) => debug!("Received confirmation from user. Proceeding.."),
-                Ok(false) => {
-                    debug!("User asked to abort");
-                    println!("Aborting..");
-                    process::exit(0);
-                }
-                Err(e) => {
-                    debug!("Error encountered in confirm prompt: {:?}", e);
-                    println!("Something went wrong. Aborting..");
-                    process::exit(1);
-                }
-            }
+    let snapshot = snapshot::parse(input)?;
+    let actions = snapshot.validate(allow_full_deletion, ignore_mtime)?;
+    // The style of symlinks only affects the size of the symlinks
+    // and hence the reclaimable space negligibly
+    let report = executor::report(&actions, SymlinkStyle::Relative)?;
+    if *json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        for (op, count) in report.counts.iter() {
+            println!("{}: {}", op, count);
+        }
+        if report.no_op > 0 {
+            println!("no-op: {}", report.no_op);
+        }
+        println!("Reclaimable: {}", Size::from_bytes(report.reclaimable));
+    }
+    Ok(())
+}
+
+/// Returns a summary of the actions to be shown before asking for
+/// confirmation e.g. "2 delete, 3 symlink (1 no-op). 6.00 KiB can be
+/// reclaimed"
+fn actions_summary(actions: &[executor::Action], style: SymlinkStyle) -> Result<String, AppError> {
+    let report = executor::report(actions, style)?;
+    let counts = report
+        .counts
+        .iter()
+        .filter(|(op, _)| **op != "keep")
+        .map(|(op, count)| format!("{} {}", count, op))
+        .collect::<Vec<String>>();
+    let mut summary = if counts.is_empty() {
+        String::from("No changes")
+    } else {
+        counts.join(", ")
+    };
+    // No-ops are reported separately so that they aren't mistaken
+    // for actual changes
+    if report.no_op > 0 {
+        summary.push_str(&format!(" ({} no-op)", report.no_op));
+    }
+    summary.push_str(&format!(
+        ". {} can be reclaimed",
+        Size::from_bytes(report.reclaimable)
+    ));
+    Ok(summary)
+}
+
+/// Asks the user for confirmation and exits the process if the user
+/// doesn't confirm. The `summary` of the changes, if specified, is
+/// shown in the help message of the prompt.
+fn confirm_or_exit(msg: &str, summary: Option<&str>) {
+    match confirm(msg, summary) {
+        Ok(true) => {}
+        Ok(false) => {
+            println!("Aborting..");
+            process::exit(0);
+        }
+        Err(_) => {
+            println!("Something went wrong. Aborting..");
+            process::exit(1);
+        }
+    }
+}
+
+/// Asks the user for confirmation, returning whether the user
+/// confirmed. The `summary` is shown the same way as in
+/// `confirm_or_exit`.
+fn confirm(msg: &str, summary: Option<&str>) -> Result<bool, AppError> {
+    let tip = "Tip: To see the changes run the command with '--dry-run' option";
+    let help = match summary {
+        Some(s) => format!("{}. {}", s, tip),
+        None => tip.to_owned(),
+    };
+    let ans = Confirm::new(msg)
+        .with_default(false)
+        .with_help_message(&help)
+        .prompt();
+    match ans {
+        Ok(true) => {
+            debug!("Received confirmation from user. Proceeding..");
+            Ok(true)
+        }
+        Ok(false) => {
+            debug!("User asked to abort");
+            Ok(false)
+        }
+        Err(e) => {
+            debug!("Error encountered in confirm prompt: {:?}", e);
+            Err(AppError::Cmd(format!(
+                "Error in confirmation prompt: {}",
+                e
+            )))
+        }
+    }
+}
+
+fn cmd_undo(
+    backup_dir: &Path,
+    rootdir: &Path,
+    dry_run: &bool,
+    force: &bool,
+) -> Result<(), AppError> {
+    let rootdir = rootdir.canonicalize().map_err(AppError::Io)?;
+    undo::undo(backup_dir, &rootdir, dry_run, force, || {
+        confirm_or_exit(
+            "Files will be restored from the backup. Do you want to proceed?",
+            None,
+        );
+        true
+    })
+}
+
+fn cmd_backups_prune(
+    keep: Option<usize>,
+    older_than: Option<Duration>,
+    dry_run: &bool,
+    yes: &bool,
+    backup_root: Option<&Path>,
+) -> Result<(), AppError> {
+    let backup_root = backup_root
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(default_backup_root);
+    backups::prune(&backup_root, keep, older_than, dry_run, || {
+        if !*yes {
+            confirm_or_exit(
+                "Backup dirs will be removed permanently. Do you want to proceed?",
+                None,
+            );
         }
-        executor::execute(actions, dry_run, Some(backup_dir_path), &snapshot.rootdir)
+        true
+    })
+}
+
+fn cmd_relink(rootdir: &Path, dry_run: &bool) -> Result<(), AppError> {
+    let rootdir = rootdir.canonicalize().map_err(AppError::Io)?;
+    // Hashes of the sources of broken symlinks can only be found in
+    // the cache
+    let cache = HashCache::load(&default_cache_path()).map_err(AppError::Io)?;
+    relink::relink(&rootdir, &cache, dry_run, || {
+        confirm_or_exit(
+            "Broken symlinks will be repointed. Do you want to proceed?",
+            None,
+        );
+        true
     })
 }
 
-fn init_logging(verbosity: u8) {
+/// Collects the structured fields (key-values) of a log record into
+/// a JSON object
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> log::kv::Visitor<'kvs> for JsonFields<'_> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.insert(
+            key.as_str().to_owned(),
+            serde_json::Value::String(value.to_string()),
+        );
+        Ok(())
+    }
+}
+
+/// Returns the log record as a JSON object, including the structured
+/// fields (e.g. path and action) if any
+fn log_record_json(record: &log::Record, timestamp: &str) -> serde_json::Value {
+    let mut obj = serde_json::Map::new();
+    obj.insert("timestamp".to_owned(), timestamp.into());
+    obj.insert("level".to_owned(), record.level().as_str().into());
+    obj.insert("target".to_owned(), record.target().into());
+    obj.insert("message".to_owned(), record.args().to_string().into());
+    // Fields that can't be visited are not worth failing the logging
+    // for, hence the error is ignored
+    let _ = record.key_values().visit(&mut JsonFields(&mut obj));
+    serde_json::Value::Object(obj)
+}
+
+fn init_logging(verbosity: u8, format: LogFormat) {
     let log_level = match verbosity {
         0 => "warn",
         1 => "info",
         _ => "debug",
     };
     let env = env_logger::Env::default().default_filter_or(log_level);
-    env_logger::Builder::from_env(env).init()
+    let mut builder = env_logger::Builder::from_env(env);
+    if let LogFormat::Json = format {
+        builder.format(|buf, record| {
+            let timestamp = Local::now().to_rfc3339();
+            writeln!(buf, "{}", log_record_json(record, &timestamp))
+        });
+    }
+    builder.init()
 }
 
+/// Exit code when the snapshot is valid but there are pending actions
+const EXIT_CODE_PENDING_ACTIONS: i32 = 2;
+
+/// Exit code when the snapshot is valid except for the groups skipped
+/// with `--skip-invalid`
+const EXIT_CODE_SKIPPED_GROUPS: i32 = 3;
+
 impl Cli {
-    fn execute(&self) -> Result<(), AppError> {
-        init_logging(self.verbose);
+    /// Executes the command, returning the exit code on success
+    fn execute(&self) -> Result<i32, AppError> {
+        init_logging(self.verbose, self.log_format);
+        if self.no_mmap {
+            fileutil::set_mmap_threshold(None);
+        } else if let Some(n) = self.mmap_threshold {
+            fileutil::set_mmap_threshold(Some(n));
+        }
         match &self.command {
-            Some(Command::Find {
-                exclude,
-                quick,
-                skip_deduped,
-                rootdir,
-            }) => cmd_find(rootdir, exclude.as_ref(), quick, skip_deduped),
+            Some(Command::Find(args)) => cmd_find(args).map(|_| 0),
+            Some(Command::Validate {
+                allow_full_deletion,
+                ignore_mtime,
+                skip_invalid,
+                only,
+                snapshots_from: Some(list_path),
+                ..
+            }) => cmd_validate_batch(
+                list_path,
+                allow_full_deletion,
+                ignore_mtime,
+                skip_invalid,
+                only.as_deref(),
+            ),
             Some(Command::Validate {
                 stdin,
                 allow_full_deletion,
+                json,
+                ignore_mtime,
+                skip_invalid,
+                only,
+                snapshots_from: None,
                 snapshot_path,
             }) => cmd_validate(
                 snapshot_path.as_ref().map(|p| p.as_ref()),
                 stdin,
                 allow_full_deletion,
-            ),
-            Some(Command::Apply {
+                json,
+                ignore_mtime,
+                skip_invalid,
+                only.as_deref(),
+            )
+            .map(|validated| validated.exit_code()),
+            Some(Command::Apply(args)) => cmd_apply(args),
+            Some(Command::Verify {
                 stdin,
+                allow_full_deletion,
+                ignore_mtime,
                 snapshot_path,
-                dry_run,
+            }) => cmd_verify(
+                snapshot_path.as_deref(),
+                stdin,
                 allow_full_deletion,
-                backup_dir,
-            }) => cmd_apply(
-                snapshot_path.as_ref().map(|p| p.as_ref()),
+                ignore_mtime,
+            )
+            .map(|num_problems| if num_problems > 0 { 1 } else { 0 }),
+            Some(Command::Report {
                 stdin,
-                dry_run,
                 allow_full_deletion,
-                backup_dir.as_ref().map(|p| p.as_ref()),
-            ),
+                ignore_mtime,
+                json,
+                snapshot_path,
+            }) => cmd_report(
+                snapshot_path.as_deref(),
+                stdin,
+                allow_full_deletion,
+                ignore_mtime,
+                json,
+            )
+            .map(|_| 0),
+            Some(Command::Diff {
+                old_snapshot,
+                new_snapshot,
+            }) => cmd_diff(old_snapshot, new_snapshot).map(|_| 0),
+            Some(Command::Size(args)) => cmd_size(args).map(|_| 0),
+            Some(Command::Interactive(args)) => cmd_interactive(args).map(|_| 0),
+            Some(Command::Undo {
+                rootdir,
+                dry_run,
+                force,
+                backup_dir,
+            }) => cmd_undo(backup_dir, rootdir, dry_run, force).map(|_| 0),
+            Some(Command::Relink { rootdir, dry_run }) => cmd_relink(rootdir, dry_run).map(|_| 0),
+            Some(Command::Backups {
+                command:
+                    BackupsCommand::Prune {
+                        keep,
+                        older_than,
+                        dry_run,
+                        yes,
+                        backup_root,
+                    },
+            }) => cmd_backups_prune(*keep, *older_than, dry_run, yes, backup_root.as_deref())
+                .map(|_| 0),
             None => Err(AppError::Cmd("Please specify the command".to_owned())),
         }
     }
 }
 
+/// Prints the error to stderr in a user friendly way where possible
+fn print_error(e: &AppError) {
+    match e {
+        AppError::Cmd(msg) => eprintln!("Command Error: {}", msg),
+        AppError::SnapshotMetadata(msg) => eprintln!("Snapshot Error: {}", msg),
+        _ => eprintln!("Error: {:?}", e),
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
     let result = cli.execute();
     match result {
-        Ok(()) => process::exit(0),
-        Err(AppError::Cmd(msg)) => {
-            eprintln!("Command Error: {}", msg);
-        }
+        Ok(code) => process::exit(code),
         Err(e) => {
-            eprintln!("Error: {:?}", e);
+            print_error(&e);
             process::exit(1);
         }
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::fs;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Ok(0), parse_size("0"));
+        assert_eq!(Ok(512), parse_size("512"));
+        assert_eq!(Ok(10 * 1024), parse_size("10K"));
+        assert_eq!(Ok(10 * 1024), parse_size("10k"));
+        assert_eq!(Ok(10 * 1024 * 1024), parse_size("10M"));
+        assert_eq!(Ok(2 * 1024 * 1024 * 1024), parse_size("2G"));
+        assert_eq!(Ok(1024 * 1024 * 1024 * 1024), parse_size("1T"));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("1.5M").is_err());
+        assert!(parse_size("-1K").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_check_rootdir_arg() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create .tmp-test-data");
+        let data_dir = Path::new(".tmp-test-data");
+        fs::write(data_dir.join("1.txt"), "ONE").unwrap();
+
+        assert!(check_rootdir_arg(data_dir).is_ok());
+        match check_rootdir_arg(&data_dir.join("1.txt")) {
+            Err(AppError::Cmd(msg)) => assert_eq!(
+                "Root directory is not a directory: .tmp-test-data/1.txt",
+                msg
+            ),
+            _ => assert!(false),
+        }
+        match check_rootdir_arg(&data_dir.join("2")) {
+            Err(AppError::Cmd(msg)) => {
+                assert_eq!("Root directory doesn't exist: .tmp-test-data/2", msg)
+            }
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_read_snapshot_list() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create .tmp-test-data");
+        let list_path = Path::new(".tmp-test-data").join("snapshots.txt");
+        fs::write(
+            &list_path,
+            "# Photos\n/tmp/photos.txt\n\n  music.txt  \n#/tmp/skipped.txt\n",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![PathBuf::from("/tmp/photos.txt"), PathBuf::from("music.txt")],
+            read_snapshot_list(&list_path).unwrap()
+        );
+        match read_snapshot_list(&Path::new(".tmp-test-data").join("nonexistent.txt")) {
+            Err(AppError::Io(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_batch() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create .tmp-test-data");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        let generated_at = Local::now().to_rfc2822();
+        // Writes a snapshot of a group of two identical files under
+        // the `rootdir`, with the given ops
+        let write_snapshot = |name: &str, ops: [&str; 2]| -> PathBuf {
+            let rootdir = data_dir.join(name);
+            fs::create_dir(&rootdir).unwrap();
+            fs::write(rootdir.join("1.txt"), "ONE").unwrap();
+            fs::write(rootdir.join("2.txt"), "ONE").unwrap();
+            let checksum =
+                Checksum::of_file_with(&rootdir.join("1.txt"), ContentHash::Raw).unwrap();
+            let path = data_dir.join(format!("{}.txt", name));
+            let lines = [
+                format!("#! Root Directory: {}", rootdir.display()),
+                format!("#! Generated at: {}", generated_at),
+                String::from("#! Checksum Format: hex"),
+                String::from(""),
+                format!("[{}]", checksum),
+                format!("{} 1.txt", ops[0]),
+                format!("{} 2.txt", ops[1]),
+            ];
+            fs::write(&path, lines.join("\n")).unwrap();
+            path
+        };
+        // Full deletion of the group is not allowed
+        let invalid = write_snapshot("invalid", ["delete", "delete"]);
+        let valid = write_snapshot("valid", ["keep", "delete"]);
+        let list_path = data_dir.join("snapshots.txt");
+        let write_list = |paths: &[&Path]| {
+            let lines = paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<String>>();
+            fs::write(&list_path, lines.join("\n")).unwrap();
+        };
+
+        // Result of validation is written to the writer passed in, which
+        // is stderr in case of batch
+        let mut out: Vec<u8> = Vec::new();
+        let input = read_input(Some(&valid), &false).unwrap();
+        validate_input(&mut out, input, &false, &false, &true, &false, None).unwrap();
+        assert_eq!(
+            "Snapshot is valid!\nNo. of pending action(s): 1\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        write_list(&[&valid]);
+        assert_eq!(
+            EXIT_CODE_PENDING_ACTIONS,
+            cmd_validate_batch(&list_path, &false, &true, &false, None).unwrap()
+        );
+        write_list(&[&invalid, &valid]);
+        assert_eq!(
+            1,
+            cmd_validate_batch(&list_path, &false, &true, &false, None).unwrap()
+        );
+        // With '--skip-invalid', the invalid group is skipped, which is
+        // reported with a distinct exit code
+        assert_eq!(
+            EXIT_CODE_SKIPPED_GROUPS,
+            cmd_validate_batch(&list_path, &false, &true, &true, None).unwrap()
+        );
+
+        // The valid snapshot is applied in spite of the invalid one
+        // before it, with the backup taken in the sub dir named after
+        // its position in the list
+        let backup_dir = data_dir.join("backups");
+        let cli = Cli::try_parse_from([
+            "dupenukem",
+            "apply",
+            "--yes",
+            "--ignore-mtime",
+            "--snapshots-from",
+            list_path.to_str().unwrap(),
+        ])
+        .unwrap();
+        let args = match &cli.command {
+            Some(Command::Apply(args)) => args,
+            _ => panic!("Expected the apply command"),
+        };
+        assert_eq!(1, cmd_apply_batch(args, &list_path, &backup_dir).unwrap());
+        assert!(data_dir.join("invalid/1.txt").exists());
+        assert!(data_dir.join("invalid/2.txt").exists());
+        assert!(data_dir.join("valid/1.txt").exists());
+        assert!(!data_dir.join("valid/2.txt").exists());
+        assert!(!backup_dir.join("1").exists());
+        assert!(backup_dir
+            .join("2")
+            .join(executor::BACKUP_FILES_DIR)
+            .join("2.txt")
+            .exists());
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Ok(Duration::from_secs(90)), parse_duration("90s"));
+        assert_eq!(Ok(Duration::from_secs(15 * 60)), parse_duration("15m"));
+        assert_eq!(Ok(Duration::from_secs(2 * 3600)), parse_duration("2h"));
+        assert_eq!(Ok(Duration::from_secs(30 * 86400)), parse_duration("30d"));
+        assert_eq!(Ok(Duration::from_secs(2 * 604800)), parse_duration("2w"));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("1.5d").is_err());
+        assert!(parse_duration("30D").is_err());
+    }
+
+    #[test]
+    fn test_actions_summary() {
+        let p1 = Path::new("/a/1.txt");
+        let p2 = Path::new("/a/2.txt");
+        let p3 = Path::new("/a/3.txt");
+        // No-op actions don't require the files to exist
+        let actions = vec![
+            executor::Action::Keep(p1),
+            executor::Action::Delete {
+                path: p2,
+                is_no_op: true,
+            },
+        ];
+        assert_eq!(
+            "No changes (1 no-op). 0 bytes can be reclaimed",
+            actions_summary(&actions, SymlinkStyle::Relative).unwrap()
+        );
+        let actions = vec![
+            executor::Action::Keep(p1),
+            executor::Action::Move {
+                path: p2,
+                dest: PathBuf::from("/b/2.txt"),
+                is_no_op: false,
+            },
+            executor::Action::Move {
+                path: p3,
+                dest: PathBuf::from("/b/3.txt"),
+                is_no_op: false,
+            },
+        ];
+        assert_eq!(
+            "2 move. 0 bytes can be reclaimed",
+            actions_summary(&actions, SymlinkStyle::Relative).unwrap()
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_default_backup_dir() {
+        env::set_var(BACKUP_ROOT_ENV_VAR, "/mnt/backups/dpnk");
+        let dir = default_backup_dir();
+        assert_eq!(Some(Path::new("/mnt/backups/dpnk")), dir.parent());
+        assert_eq!(14, dir.file_name().unwrap().len());
+
+        // An empty value is same as not being set
+        env::set_var(BACKUP_ROOT_ENV_VAR, "");
+        let dir = default_backup_dir();
+        assert!(dir.parent().unwrap().ends_with(".dupenukem/backups"));
+
+        env::remove_var(BACKUP_ROOT_ENV_VAR);
+        let dir = default_backup_dir();
+        assert!(dir.parent().unwrap().ends_with(".dupenukem/backups"));
+    }
+
+    #[test]
+    fn test_log_record_json() {
+        let fields: &[(&str, &str)] = &[("action", "delete"), ("path", "foo/1.txt")];
+        let record = log::Record::builder()
+            .args(format_args!("Deleting file: foo/1.txt"))
+            .level(log::Level::Info)
+            .target("dupenukem::executor")
+            .key_values(&fields)
+            .build();
+        assert_eq!(
+            serde_json::json!({
+                "timestamp": "2024-01-09T16:38:03+05:30",
+                "level": "INFO",
+                "target": "dupenukem::executor",
+                "message": "Deleting file: foo/1.txt",
+                "action": "delete",
+                "path": "foo/1.txt",
+            }),
+            log_record_json(&record, "2024-01-09T16:38:03+05:30")
+        );
+
+        // Records without fields
+        let record = log::Record::builder()
+            .args(format_args!("Pruning empty dir: foo"))
+            .level(log::Level::Warn)
+            .build();
+        let value = log_record_json(&record, "2024-01-09T16:38:03+05:30");
+        assert_eq!("WARN", value["level"]);
+        assert!(value.get("path").is_none());
+    }
+
+    #[test]
+    fn test_find_summary() {
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Sun, 24 Dec 2023 13:07:06 +0530",
+            "[1]",
+            "keep a.txt",
+            "keep b.txt",
+            "[2]",
+            "keep c.txt",
+            "keep d.txt",
+            "keep e.txt",
+        ];
+        let snap = textformat::parse(input.iter().map(|s| s.to_string()).collect()).unwrap();
+        let mut stats = ScanStats {
+            files_traversed: 12,
+            ..Default::default()
+        };
+        assert_eq!(
+            "Scanned 12 file(s), found 2 group(s) of duplicates having 5 file(s). 30 bytes can be reclaimed",
+            find_summary(&snap, &stats, Size::from_bytes(30))
+        );
+        stats.groups_hidden = 3;
+        assert_eq!(
+            "Scanned 12 file(s), found 2 group(s) of duplicates having 5 file(s) (3 group(s) hidden). 30 bytes can be reclaimed",
+            find_summary(&snap, &stats, Size::from_bytes(30))
+        );
+    }
+}
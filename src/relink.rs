@@ -0,0 +1,232 @@
+use crate::cache::HashCache;
+use crate::error::AppError;
+use crate::fileutil::{self, normalize_path, Backup, SymlinkStyle};
+use crate::hash::Checksum;
+use log::info;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A broken symlink that can be repointed to another file having the
+/// same contents as its original source
+#[derive(Debug)]
+struct Relink {
+    path: PathBuf,
+    source: PathBuf,
+}
+
+impl Relink {
+    fn dry_run(&self, rootdir: &Path) {
+        // Use relative path in dry-run output
+        let rel_path = normalize_path(&self.path, true, rootdir).unwrap();
+        eprintln!(
+            "[DRY RUN] Symlink to be repointed: {} -> {}",
+            rel_path.display(),
+            self.source.display()
+        );
+    }
+
+    fn execute(&self, rootdir: &Path) -> Result<(), AppError> {
+        // Show relative path in log messages
+        let rel_path = normalize_path(&self.path, true, rootdir).unwrap();
+        info!(
+            "Repointing symlink: {} -> {}",
+            rel_path.display(),
+            self.source.display()
+        );
+        // A broken symlink has no contents to be backed up
+        fileutil::replace_with_symlink(&self.path, &self.source, &Backup::Disabled, rootdir)?;
+        Ok(())
+    }
+}
+
+/// Recursively collects paths of the broken symlinks and the regular
+/// files under the `dir`. Symlinks to dirs are not descended into.
+fn collect_paths(
+    dir: &Path,
+    broken_symlinks: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_paths(&path, broken_symlinks, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        } else if file_type.is_symlink() && !path.exists() {
+            broken_symlinks.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Finds broken symlinks under the `rootdir` and the files that they
+/// can be repointed to
+///
+/// As the original source of a broken symlink doesn't exist anymore,
+/// its contents are known only if its hash is found in the `cache`
+/// (i.e. it was hashed by an earlier run of the `find` command). Such
+/// a symlink is repointed to a file under the `rootdir` having the
+/// same hash, preserving the style (relative or absolute) of the
+/// symlink. Broken symlinks whose source is not found in the cache or
+/// whose contents don't exist anymore are skipped.
+fn plan(rootdir: &Path, cache: &HashCache) -> Result<Vec<Relink>, AppError> {
+    let mut broken_symlinks = Vec::new();
+    let mut files = Vec::new();
+    collect_paths(rootdir, &mut broken_symlinks, &mut files).map_err(AppError::Io)?;
+    broken_symlinks.sort();
+    files.sort();
+    let mut checksums: HashMap<&Path, u64> = HashMap::new();
+    let mut relinks = Vec::new();
+    for path in broken_symlinks {
+        let link = path.read_link().map_err(AppError::Io)?;
+        // Unwrap is fine as the path has been obtained by traversing
+        // the rootdir
+        let old_source = fileutil::lexical_normalize(&path.parent().unwrap().join(&link));
+        let (size, hash) = match cache.lookup(&old_source) {
+            Some(v) => v,
+            None => {
+                info!(
+                    "Skipping broken symlink as contents of its source are not known: {}",
+                    path.display()
+                );
+                continue;
+            }
+        };
+        let mut new_source = None;
+        for file in files.iter() {
+            if file.metadata().map_err(AppError::Io)?.len() != size {
+                continue;
+            }
+            let file_hash = match checksums.get(file.as_path()) {
+                Some(h) => *h,
+                None => {
+                    let h = Checksum::of_file(file).map_err(AppError::Io)?.value();
+                    checksums.insert(file, h);
+                    h
+                }
+            };
+            if file_hash == hash {
+                new_source = Some(file);
+                break;
+            }
+        }
+        match new_source {
+            Some(src) => {
+                let source = fileutil::normalize_symlink_src_path(
+                    &path,
+                    src,
+                    false,
+                    SymlinkStyle::Preserve,
+                )?;
+                relinks.push(Relink { path, source });
+            }
+            None => info!(
+                "Skipping broken symlink as contents of its source don't exist anymore: {}",
+                path.display()
+            ),
+        }
+    }
+    Ok(relinks)
+}
+
+/// Repoints broken symlinks under the `rootdir` to the surviving
+/// copies of their original sources
+pub fn relink(
+    rootdir: &Path,
+    cache: &HashCache,
+    dry_run: &bool,
+    confirm: impl FnOnce() -> bool,
+) -> Result<(), AppError> {
+    let relinks = plan(rootdir, cache)?;
+    info!(
+        "Repointing {} symlink(s) with dry_run={}",
+        relinks.len(),
+        dry_run
+    );
+    if *dry_run {
+        for relink in relinks.iter() {
+            relink.dry_run(rootdir);
+        }
+        eprintln!("[DRY RUN] {} symlink(s) will be repointed", relinks.len());
+    } else if relinks.is_empty() {
+        eprintln!("No symlinks to be repointed");
+    } else if confirm() {
+        for relink in relinks.iter() {
+            relink.execute(rootdir)?;
+        }
+        eprintln!("{} symlink(s) have been repointed", relinks.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::xxh3_64;
+    use serial_test::serial;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_relink() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let rootdir = data_dir.join("root");
+        for d in ["a", "b", "c"] {
+            fs::create_dir_all(rootdir.join(d)).unwrap();
+        }
+        let keeper = rootdir.join("a/1.txt");
+        fs::write(&keeper, "ONE").unwrap();
+        fs::write(rootdir.join("a/2.txt"), "TWO").unwrap();
+        fileutil::symlink(Path::new("../a/1.txt"), &rootdir.join("b/1.txt")).unwrap();
+        fileutil::symlink(&keeper, &rootdir.join("b/abs.txt")).unwrap();
+        fileutil::symlink(Path::new("../a/2.txt"), &rootdir.join("b/2.txt")).unwrap();
+        fileutil::symlink(Path::new("../a/3.txt"), &rootdir.join("b/3.txt")).unwrap();
+
+        // Hashes of the sources get recorded in the cache
+        let cache = HashCache::load(&data_dir.join("hashes.txt")).unwrap();
+        for p in ["a/1.txt", "a/2.txt"] {
+            let path = rootdir.join(p);
+            cache.xxh3(&path, || xxh3_64(&path)).unwrap();
+        }
+
+        // Keeper is moved whereas the other source is deleted
+        fs::rename(&keeper, rootdir.join("c/1.txt")).unwrap();
+        fs::remove_file(rootdir.join("a/2.txt")).unwrap();
+
+        let relinks = plan(&rootdir, &cache)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.path, r.source))
+            .collect::<Vec<(PathBuf, PathBuf)>>();
+        assert_eq!(
+            vec![
+                (rootdir.join("b/1.txt"), PathBuf::from("../c/1.txt")),
+                (rootdir.join("b/abs.txt"), rootdir.join("c/1.txt")),
+            ],
+            relinks
+        );
+
+        // Nothing is repointed in case of dry run
+        relink(&rootdir, &cache, &true, || true).unwrap();
+        assert!(!rootdir.join("b/1.txt").exists());
+
+        relink(&rootdir, &cache, &false, || true).unwrap();
+        for p in ["b/1.txt", "b/abs.txt"] {
+            let path = rootdir.join(p);
+            assert!(path.is_symlink());
+            assert_eq!("ONE", fs::read_to_string(&path).unwrap());
+        }
+        assert!(!rootdir.join("b/2.txt").exists());
+        assert!(!rootdir.join("b/3.txt").exists());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+}
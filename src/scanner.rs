@@ -1,31 +1,510 @@
+use crate::cache::HashCache;
 use crate::fileutil;
-use crate::hash::{self, Checksum};
-use log::warn;
-use std::collections::{HashMap, HashSet, VecDeque};
+use crate::hash::{self, Checksum, ContentHash, VerificationHash};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, warn};
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Method for confirming that the files having the same xxh3
+/// checksum are actually duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Hash(VerificationHash),
+    Bytes,
+}
+
+/// Paths to be excluded during traversal
+///
+/// A pattern containing any of the glob metacharacters (`*`, `?`,
+/// `[` or `{`) is considered a glob and is matched against the path
+/// of every entry relative to the rootdir. Other patterns are
+/// considered as exact paths, which may either be absolute or
+/// relative to the rootdir. In case of multiple rootdirs, relative
+/// patterns apply to each of them.
+pub struct Excludes {
+    paths: HashSet<PathBuf>,
+    // Globs along with the dir relative to which they are matched
+    globs: Vec<(PathBuf, GlobSet)>,
+}
+
+/// Name of the file in the rootdir that lists the paths to be excluded
+/// during traversal, one pattern per line
+pub const IGNORE_FILE: &str = ".dupenukemignore";
+
+/// Reads the exclude patterns from the `IGNORE_FILE` in the `rootdir`
+/// ignoring comment (starting with `#`) and blank lines. Returns
+/// `None` if the file doesn't exist.
+pub fn read_ignore_file(rootdir: &Path) -> io::Result<Option<Vec<String>>> {
+    let path = rootdir.join(IGNORE_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let patterns = fs::read_to_string(&path)?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_owned())
+        .collect();
+    Ok(Some(patterns))
+}
+
+impl Excludes {
+    pub fn new(rootdir: &Path, patterns: &[String]) -> Result<Self, globset::Error> {
+        Self::of_rootdirs(&[rootdir.to_path_buf()], patterns)
+    }
+
+    pub fn of_rootdirs(rootdirs: &[PathBuf], patterns: &[String]) -> Result<Self, globset::Error> {
+        let mut paths = HashSet::new();
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            if pattern.contains(['*', '?', '[', '{']) {
+                builder.add(Glob::new(pattern)?);
+            } else {
+                // Absolute paths are used as they are as `join`
+                // replaces the rootdir with them
+                let mut is_inside = false;
+                for rootdir in rootdirs {
+                    let path = fileutil::lexical_normalize(&rootdir.join(pattern));
+                    if path.starts_with(rootdir) && path != *rootdir {
+                        paths.insert(path);
+                        is_inside = true;
+                    }
+                }
+                if !is_inside {
+                    warn!(
+                        "Ignoring exclude path {} as it's not inside the rootdir {}",
+                        pattern,
+                        rootdirs
+                            .iter()
+                            .map(|d| d.display().to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    );
+                }
+            }
+        }
+        let globs = builder.build()?;
+        Ok(Self {
+            paths,
+            globs: rootdirs
+                .iter()
+                .map(|rootdir| (rootdir.to_path_buf(), globs.clone()))
+                .collect(),
+        })
+    }
+
+    /// Adds the patterns read from the `IGNORE_FILE` in the `rootdir`,
+    /// which only apply to the paths inside that rootdir. The ignore
+    /// file itself is excluded as well, as otherwise identical ignore
+    /// files in multiple rootdirs would be found as duplicates.
+    pub fn add_ignore_file(
+        &mut self,
+        rootdir: &Path,
+        patterns: &[String],
+    ) -> Result<(), globset::Error> {
+        let other = Self::of_rootdirs(&[rootdir.to_path_buf()], patterns)?;
+        self.paths.extend(other.paths);
+        self.paths.insert(rootdir.join(IGNORE_FILE));
+        self.globs.extend(other.globs);
+        Ok(())
+    }
+
+    /// Returns whether there are no paths to be excluded
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.globs.iter().all(|(_, g)| g.is_empty())
+    }
+
+    /// Returns whether the path (of a file or dir inside the rootdir)
+    /// is excluded
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        if self.paths.contains(path) {
+            return true;
+        }
+        self.globs
+            .iter()
+            .any(|(rootdir, globs)| match path.strip_prefix(rootdir) {
+                Ok(relpath) => globs.is_match(relpath),
+                Err(_) => false,
+            })
+    }
+}
+
+/// Options that control how the rootdir is scanned for duplicates
+pub struct ScanOptions {
+    /// Paths to be excluded during traversal
+    pub excludes: Option<Excludes>,
+    /// Whether to skip confirming the duplicates found by comparing
+    /// xxh3 hashes
+    pub quick: bool,
+    /// Method for confirming duplicates (if not quick)
+    pub verification: Verification,
+    /// Whether to show progress bar on stderr
+    pub progress: bool,
+    /// Whether to descend into symlinked directories (that resolve to
+    /// a path inside the rootdir)
+    pub follow_symlinks: bool,
+    /// Max no. of levels below the rootdir to descend into
+    pub max_depth: Option<usize>,
+    /// Lowercase extensions (without the leading dot) of the files to
+    /// be considered. Other files are ignored.
+    pub extensions: Option<HashSet<String>>,
+    /// Whether to skip hidden files and dirs i.e. the ones with names
+    /// starting with `.`
+    pub skip_hidden: bool,
+    /// Whether to skip the paths ignored by `.gitignore` files found
+    /// during traversal
+    pub respect_gitignore: bool,
+    /// Whether to report hardlinks to the same file as duplicates
+    pub keep_hardlinks: bool,
+    /// Files smaller than this size (in bytes) are ignored
+    pub min_size: Option<u64>,
+    /// Files larger than this size (in bytes) are ignored
+    pub max_size: Option<u64>,
+    /// Groups having fewer files than this are not reported
+    pub min_copies: usize,
+    /// Whether to warn about paths that differ only by case
+    pub case_insensitive_paths: bool,
+    /// Whether to exclude the files that got modified while the scan
+    /// was in progress, as their checksums may be stale
+    pub skip_modified: bool,
+    /// Checksums from a previously generated snapshot to be reused
+    pub baseline: Option<Baseline>,
+    /// Which contents of the files are considered for finding
+    /// duplicates
+    pub content_hash: ContentHash,
+    /// Whether to report the empty (zero-length) files as a group of
+    /// duplicates. By default, they are skipped.
+    pub include_empty: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            excludes: None,
+            quick: false,
+            verification: Verification::Hash(VerificationHash::Sha256),
+            progress: false,
+            follow_symlinks: false,
+            max_depth: None,
+            extensions: None,
+            skip_hidden: false,
+            respect_gitignore: false,
+            keep_hardlinks: false,
+            min_size: None,
+            max_size: None,
+            min_copies: 2,
+            case_insensitive_paths: false,
+            skip_modified: false,
+            baseline: None,
+            content_hash: ContentHash::Raw,
+            include_empty: false,
+        }
+    }
+}
+
+/// Checksums recorded in a previously generated snapshot
+///
+/// The recorded checksum of a file is reused only if its size is the
+/// same as the recorded one and it has not been modified since the
+/// snapshot was generated. Where available, the time of the last
+/// status change (see `fileutil::changed_at`) is checked along with
+/// the mtime, so that a file replaced by another one having an older
+/// mtime is not missed. Note that a snapshot only includes the
+/// duplicate files, so the other files still need to be hashed.
+pub struct Baseline {
+    generated_at: SystemTime,
+    // Recorded checksum and size of the files keyed by their paths
+    entries: HashMap<PathBuf, (u64, u64)>,
+}
+
+impl Baseline {
+    pub fn new(generated_at: SystemTime, entries: HashMap<PathBuf, (u64, u64)>) -> Self {
+        Self {
+            generated_at,
+            entries,
+        }
+    }
+
+    /// Returns the recorded checksum for the `path` if found and
+    /// if the file hasn't been changed since then
+    fn checksum(&self, path: &Path) -> Option<Checksum> {
+        let (value, size) = self.entries.get(path)?;
+        let metadata = path.metadata().ok()?;
+        let mtime = metadata.modified().ok()?;
+        let is_unchanged = metadata.len() == *size
+            && mtime < self.generated_at
+            && fileutil::changed_at(&metadata).is_none_or(|t| t < self.generated_at);
+        is_unchanged.then(|| Checksum::new(*value))
+    }
+}
+
+/// No. of bytes from the beginning of the file that are hashed for a
+/// quick comparison before computing the full hash
+const PREFIX_HASH_LEN: u64 = 4096;
+
+/// Files up to this size (in bytes) are compared by reading their
+/// contents fully instead of hashing them
+const SMALL_FILE_LEN: u64 = 4096;
+
+/// Returns a progress bar that's drawn to stderr if `enabled` is
+/// true, otherwise a hidden one.
+///
+/// If `len` is `None`, a spinner showing only the count is returned
+/// as the total is not known upfront. Note that indicatif doesn't
+/// draw anything if stderr is not a terminal, so the progress bar
+/// gets suppressed automatically in that case.
+fn new_progress_bar(enabled: bool, len: Option<u64>, msg: &'static str) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+    let (pb, template) = match len {
+        Some(n) => (
+            ProgressBar::new(n),
+            "{msg} [{elapsed_precise}] [{bar:40}] {pos}/{len}",
+        ),
+        None => (
+            ProgressBar::new_spinner(),
+            "{msg} [{elapsed_precise}] {spinner} {pos} files",
+        ),
+    };
+    // Unwrap is fine here as the templates are hard coded
+    pb.set_style(ProgressStyle::with_template(template).unwrap());
+    pb.set_draw_target(ProgressDrawTarget::stderr());
+    pb.set_message(msg);
+    pb
+}
+
+/// Gitignore rules of a directory along with those of its ancestors
+/// (up to the rootdir)
+struct GitignoreChain {
+    gitignore: Gitignore,
+    parent: Option<Arc<GitignoreChain>>,
+}
+
+impl GitignoreChain {
+    /// Returns the chain for `dirpath` given the chain of its parent
+    /// dir. If there's no `.gitignore` file in `dirpath`, the chain
+    /// of the parent is returned as it is.
+    fn load(dirpath: &Path, parent: Option<Arc<GitignoreChain>>) -> Option<Arc<GitignoreChain>> {
+        let path = dirpath.join(".gitignore");
+        if !path.is_file() {
+            return parent;
+        }
+        let mut builder = GitignoreBuilder::new(dirpath);
+        if let Some(e) = builder.add(&path) {
+            warn!("Error in gitignore file {}: {}", path.display(), e);
+        }
+        match builder.build() {
+            Ok(gitignore) => Some(Arc::new(Self { gitignore, parent })),
+            Err(e) => {
+                warn!("Skipping gitignore file {}: {}", path.display(), e);
+                parent
+            }
+        }
+    }
+
+    /// Returns whether the path is ignored. As in git, the rules in
+    /// the `.gitignore` of the nearest dir take precedence over
+    /// those in the ancestor dirs.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        match self.gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => true,
+            Match::Whitelist(_) => false,
+            Match::None => self
+                .parent
+                .as_ref()
+                .is_some_and(|p| p.is_ignored(path, is_dir)),
+        }
+    }
+}
+
+/// Returns whether the file has one of the `extensions` (compared
+/// case-insensitively). Files without extension are never matched.
+fn has_extension(path: &Path, extensions: &HashSet<String>) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.contains(&e.to_lowercase()))
+}
+
+/// Reads the entries of a single directory and returns a tuple of
+/// sub directories and files in it (excluding `opts.excludes` and
+/// the paths ignored by `gitignore`). If `opts.extensions` is
+/// specified, only files having one of the extensions are
+/// returned. If `opts.skip_hidden` is true, hidden files and dirs are
+/// not returned.
+///
+/// Symlinks to directories are not included in the files. They are
+/// included in the sub directories only if `opts.follow_symlinks` is
+/// true.
+///
+/// Errors in reading individual entries are logged and the entries
+/// are skipped. The no. of such entries is returned along with the sub
+/// directories and files.
+fn read_dir_entries(
+    dirpath: &Path,
+    opts: &ScanOptions,
+    gitignore: Option<&GitignoreChain>,
+) -> io::Result<(Vec<PathBuf>, Vec<PathBuf>, usize)> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut num_skipped: usize = 0;
+    for entry in fs::read_dir(dirpath)? {
+        // Note that `file_type` doesn't follow symlinks
+        let (entry, file_type) = match entry.and_then(|e| e.file_type().map(|ft| (e, ft))) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("Skipping entry in dir {}: {}", dirpath.display(), e);
+                num_skipped += 1;
+                continue;
+            }
+        };
+        let ep = entry.path();
+        let is_dir = file_type.is_dir() || (file_type.is_symlink() && ep.is_dir());
+        if (opts.skip_hidden && entry.file_name().as_encoded_bytes().starts_with(b"."))
+            || opts.excludes.as_ref().is_some_and(|e| e.is_excluded(&ep))
+            || gitignore.is_some_and(|g| g.is_ignored(&ep, is_dir))
+        {
+            continue;
+        } else if file_type.is_dir() {
+            dirs.push(ep);
+        } else if is_dir {
+            if opts.follow_symlinks {
+                dirs.push(ep);
+            }
+        } else if opts
+            .extensions
+            .as_ref()
+            .is_none_or(|exts| has_extension(&ep, exts))
+        {
+            files.push(ep);
+        }
+    }
+    Ok((dirs, files, num_skipped))
+}
 
 /// Traverses the `dirpath` recursively using breadth first search
 /// approach and returns a vector of `PathBuf`.
 ///
-/// Optionally, a hashset of `PathBuf` refs can be passed as the
-/// `excludes` arg. These paths will be excluded during traversal.
-fn traverse_bfs(dirpath: &Path, excludes: Option<&HashSet<PathBuf>>) -> io::Result<Vec<PathBuf>> {
-    let mut queue: VecDeque<PathBuf> = VecDeque::new();
+/// All directories at one level are read in parallel (using the
+/// rayon thread pool) before moving on to the next level. Hence the
+/// order of the paths in the result is not guaranteed.
+///
+/// Paths matching `opts.excludes` will be excluded during traversal.
+///
+/// Directories (other than `dirpath` itself) and entries that can't
+/// be read e.g. due to insufficient permissions are skipped with a
+/// warning, so that the rest of the tree still gets traversed.
+///
+/// If `opts.max_depth` is specified, directories that are more than
+/// `max_depth` levels below the `dirpath` are not descended into
+/// e.g. if it's 0, only the files directly under `dirpath` are
+/// returned.
+///
+/// If `opts.respect_gitignore` is true, `.gitignore` files found in
+/// the directories are parsed and the matching paths are skipped. The
+/// rules in nested `.gitignore` files compose the same way as in git.
+///
+/// If `opts.follow_symlinks` is true, symlinks to directories that
+/// resolve to a path inside the `dirpath` are also descended
+/// into. To avoid infinite loops, canonical paths of the visited
+/// directories are tracked and a directory is never visited twice.
+fn traverse_bfs(dirpath: &Path, opts: &ScanOptions) -> io::Result<Vec<PathBuf>> {
+    let pb = new_progress_bar(opts.progress, None, "Traversing");
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    let canon_dirpath = dirpath.canonicalize()?;
+    if opts.follow_symlinks {
+        visited.insert(canon_dirpath.clone());
+    }
+    // Every dir in a level is paired with the gitignore rules of its
+    // parent dir
+    let mut level: Vec<(PathBuf, Option<Arc<GitignoreChain>>)> =
+        vec![(dirpath.to_path_buf(), None)];
+    let mut depth: usize = 0;
     let mut result: Vec<PathBuf> = Vec::new();
-    queue.push_back(dirpath.to_path_buf());
-    while let Some(p) = queue.pop_front() {
-        for entry in fs::read_dir(p)? {
-            let ep = entry?.path();
-            if excludes.is_some_and(|s| s.contains(&ep)) {
+    let mut num_skipped: usize = 0;
+    while !level.is_empty() {
+        let entries = level
+            .par_iter()
+            .map(|(p, parent_gitignore)| {
+                let gitignore = if opts.respect_gitignore {
+                    GitignoreChain::load(p, parent_gitignore.clone())
+                } else {
+                    None
+                };
+                match read_dir_entries(p, opts, gitignore.as_deref()) {
+                    Ok((dirs, files, n)) => {
+                        pb.inc(files.len() as u64);
+                        Ok((dirs, files, n, gitignore))
+                    }
+                    // Failure to read the rootdir itself is not
+                    // recoverable
+                    Err(e) if depth == 0 => Err(e),
+                    Err(e) => {
+                        warn!(
+                            "Skipping dir that couldn't be read: {} ({})",
+                            p.display(),
+                            e
+                        );
+                        Ok((vec![], vec![], 1, None))
+                    }
+                }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        level = Vec::new();
+        let is_max_depth = opts.max_depth.is_some_and(|d| depth >= d);
+        for (dirs, files, n, gitignore) in entries {
+            result.extend(files);
+            num_skipped += n;
+            if is_max_depth {
                 continue;
-            } else if ep.is_dir() {
-                queue.push_back(ep);
+            }
+            if opts.follow_symlinks {
+                for dir in dirs {
+                    let canon_dir = match dir.canonicalize() {
+                        Ok(d) => d,
+                        Err(e) => {
+                            warn!(
+                                "Skipping dir that couldn't be resolved: {} ({})",
+                                dir.display(),
+                                e
+                            );
+                            num_skipped += 1;
+                            continue;
+                        }
+                    };
+                    if !fileutil::within_rootdir(&canon_dirpath, &canon_dir) {
+                        warn!(
+                            "Skipping symlink to outside the root dir: {}",
+                            dir.display()
+                        );
+                    } else if visited.insert(canon_dir) {
+                        level.push((dir, gitignore.clone()));
+                    } else {
+                        warn!("Skipping already visited dir: {}", dir.display());
+                    }
+                }
             } else {
-                result.push(ep);
+                level.extend(dirs.into_iter().map(|d| (d, gitignore.clone())));
             }
         }
+        depth += 1;
+    }
+    pb.finish_and_clear();
+    if num_skipped > 0 {
+        warn!(
+            "Skipped {} paths due to errors during traversal",
+            num_skipped
+        );
     }
     Ok(result)
 }
@@ -40,24 +519,15 @@ fn traverse_bfs(dirpath: &Path, excludes: Option<&HashSet<PathBuf>>) -> io::Resu
 // May panic if the rootdir is a broken symlink. But since we can
 // assume that rootdir is already verified before this point, it's ok
 // to skip error handling for that case.
-fn is_path_valid(rootdir: &Path, path: &Path) -> bool {
+fn is_path_valid(rootdirs: &[PathBuf], path: &Path) -> bool {
     if path.is_symlink() {
         match path.canonicalize() {
             Ok(t) => {
-                // Here we canonicalize the rootdir as well before
-                // checking that the file that the symlink points to
-                // is under the rootdir. This is to handle the case
-                // where the rootdir itself is a symlink (For eg. on
-                // MacOS, the `tmp` dir is a symlink to
-                // `/private/tmp`).
-                //
-                // Also note that the use of `unwrap` here is
-                // acceptable because at this point, it's safe to
-                // assume that `rootdir` exists and is a valid file
-                // path and hence, it doesn't make sense to handle
-                // errors.
-                let canon_rootdir = rootdir.canonicalize().unwrap();
-                if fileutil::within_rootdir(&canon_rootdir, &t) {
+                // Note that `within_rootdir` canonicalizes the rootdir
+                // as well, which handles the case where the rootdir
+                // itself is a symlink (For eg. on MacOS, the `tmp`
+                // dir is a symlink to `/private/tmp`).
+                if rootdirs.iter().any(|d| fileutil::within_rootdir(d, &t)) {
                     true
                 } else {
                     warn!("Skipping symlink to outside the root dir: {}", t.display());
@@ -93,8 +563,56 @@ fn group_by_size(paths: Vec<&Path>) -> io::Result<HashMap<u64, Vec<&Path>>> {
     Ok(res)
 }
 
-fn possible_duplicates(paths: Vec<&Path>) -> io::Result<Vec<&Path>> {
+/// Filters out the files having size outside the range specified by
+/// `min_size` and `max_size` (both inclusive)
+fn filter_by_size(
+    paths: Vec<&Path>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+) -> io::Result<Vec<&Path>> {
+    if min_size.is_none() && max_size.is_none() {
+        return Ok(paths);
+    }
+    let mut res: Vec<&Path> = Vec::new();
+    for path in paths {
+        let size = path.metadata()?.len();
+        if min_size.is_some_and(|n| size < n) || max_size.is_some_and(|n| size > n) {
+            continue;
+        }
+        res.push(path);
+    }
+    Ok(res)
+}
+
+/// Collapses the paths that are hardlinks to the same file into a
+/// single path (the first one in sorted order)
+///
+/// Deleting a hardlink doesn't reclaim any space as long as the other
+/// links to the file exist, hence they are not to be reported as
+/// duplicates. Symlinks are not followed.
+fn collapse_hardlinks(mut paths: Vec<&Path>) -> io::Result<Vec<&Path>> {
+    paths.sort();
+    let mut seen: HashSet<(u64, u64)> = HashSet::new();
+    let mut res: Vec<&Path> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let metadata = path.symlink_metadata()?;
+        if fileutil::num_links(&metadata) > 1
+            && fileutil::file_id(path)?.is_some_and(|id| !seen.insert(id))
+        {
+            debug!("Skipping hardlink: {}", path.display());
+            continue;
+        }
+        res.push(path);
+    }
+    Ok(res)
+}
+
+/// Returns the paths having the same size as at least one other path,
+/// along with the empty (zero-length) files which are left out of the
+/// former as there's no point in hashing them.
+fn possible_duplicates(paths: Vec<&Path>) -> io::Result<(Vec<&Path>, Vec<&Path>)> {
     let mut grps = group_by_size(paths)?;
+    let empty = grps.remove(&0).unwrap_or_default();
     grps.retain(|_, v| v.len() > 1);
     let mut res: Vec<&Path> = Vec::new();
     for (_, paths) in grps {
@@ -102,13 +620,226 @@ fn possible_duplicates(paths: Vec<&Path>) -> io::Result<Vec<&Path>> {
             res.push(path)
         }
     }
+    Ok((res, empty))
+}
+
+/// Filters out paths that can't be duplicates by comparing xxh3
+/// hashes of only the first `PREFIX_HASH_LEN` bytes of the files.
+///
+/// This is a cheap pre-check to avoid hashing the full contents of
+/// large files that differ early on. Files are grouped by (size,
+/// prefix hash) and only the groups having more than one path are
+/// retained. Files that are smaller than `PREFIX_HASH_LEN` are
+/// retained as they are, because hashing their prefix would cost as
+/// much as hashing them fully in the next step.
+fn filter_by_prefix_hash(paths: Vec<&Path>) -> io::Result<Vec<&Path>> {
+    let keyed = paths
+        .par_iter()
+        .filter_map(|path| {
+            let res = path.metadata().and_then(|m| {
+                let size = m.len();
+                if size <= PREFIX_HASH_LEN {
+                    Ok((size, None, *path))
+                } else {
+                    let prefix_hash = hash::xxh3_prefix(path, PREFIX_HASH_LEN)?;
+                    Ok((size, Some(prefix_hash), *path))
+                }
+            });
+            skip_unreadable(path, res)
+        })
+        .collect::<Vec<(u64, Option<u64>, &Path)>>();
+    let mut res: Vec<&Path> = Vec::new();
+    let mut grps: HashMap<(u64, u64), Vec<&Path>> = HashMap::new();
+    for (size, prefix_hash, path) in keyed {
+        match prefix_hash {
+            None => res.push(path),
+            Some(h) => grps.entry((size, h)).or_default().push(path),
+        }
+    }
+    for (_, paths) in grps {
+        if paths.len() > 1 {
+            res.extend(paths);
+        }
+    }
     Ok(res)
 }
 
-fn group_dups_by_xxh3(paths: Vec<&Path>) -> io::Result<HashMap<Checksum, Vec<&Path>>> {
-    let mut res: HashMap<Checksum, Vec<&Path>> = HashMap::new();
+/// Splits the paths into small files (i.e. up to `SMALL_FILE_LEN`
+/// bytes) and the rest. Files whose size can't be read are skipped
+/// with a warning.
+fn split_small_files(paths: Vec<&Path>) -> (Vec<&Path>, Vec<&Path>) {
+    let mut small: Vec<&Path> = Vec::new();
+    let mut large: Vec<&Path> = Vec::new();
     for path in paths {
-        let hash = Checksum::of_file(&path)?;
+        match skip_unreadable(path, path.metadata()) {
+            Some(m) if m.len() <= SMALL_FILE_LEN => small.push(path),
+            Some(_) => large.push(path),
+            None => {}
+        }
+    }
+    (small, large)
+}
+
+/// Groups small files by comparing their contents directly,
+/// retaining only the groups having more than one path.
+///
+/// For small files, reading them fully once is cheaper than the
+/// xxh3 and verification hash steps, which are hence skipped. The
+/// groups are keyed by the xxh3 checksum of the contents, same as
+/// the ones found by hashing.
+fn group_small_dups(paths: Vec<&Path>) -> HashMap<Checksum, Vec<&Path>> {
+    let contents = paths
+        .par_iter()
+        .filter_map(|path| skip_unreadable(path, fs::read(path)).map(|bytes| (bytes, *path)))
+        .collect::<Vec<(Vec<u8>, &Path)>>();
+    let mut grps: HashMap<Vec<u8>, Vec<&Path>> = HashMap::new();
+    for (bytes, path) in contents {
+        grps.entry(bytes).or_default().push(path);
+    }
+    // Sorted so that the subgroups are assigned in the same order
+    // across runs in case of an xxh3 collision
+    let mut grps = grps
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect::<Vec<(Vec<u8>, Vec<&Path>)>>();
+    grps.sort();
+    let mut res: HashMap<Checksum, Vec<&Path>> = HashMap::new();
+    for (bytes, paths) in grps {
+        insert_group(&mut res, Checksum::of_bytes(&bytes), paths);
+    }
+    res
+}
+
+/// Inserts the group of duplicates keyed by the `checksum`
+///
+/// In case another group has the same checksum i.e. the two sets of
+/// files have the same xxh3 hash but different contents, the group is
+/// keyed by the next available subgroup of the checksum instead (see
+/// `Checksum::with_subgroup`), so that both get reported.
+fn insert_group<'a>(
+    groups: &mut HashMap<Checksum, Vec<&'a Path>>,
+    checksum: Checksum,
+    paths: Vec<&'a Path>,
+) {
+    let mut key = checksum;
+    if groups.contains_key(&key) {
+        warn!(
+            "Found multiple sets of duplicates with the same xxh3 hash; they will be reported as separate groups for {}",
+            key
+        );
+    }
+    while groups.contains_key(&key) {
+        key = key.with_subgroup(key.subgroup() + 1);
+    }
+    groups.insert(key, paths);
+}
+
+/// Groups the files by their checksums computed as per the
+/// `content_hash` mode, retaining only the groups having more than
+/// one path.
+///
+/// This is meant for files whose contents are not hashed raw (see
+/// `ContentHash::applies_to`). As such files may differ in their
+/// metadata and hence in size, they are not pre-filtered by size or
+/// prefix hash. For the same reason, the groups are confirmed by
+/// comparing the contents as per the `content_hash` mode rather than
+/// the raw contents (see `confirm_dups`).
+///
+/// Empty files are returned separately (as the second item), same as
+/// `possible_duplicates`. They are identified by their checksum which
+/// saves a stat per file.
+fn group_dups_by_content(
+    paths: Vec<&Path>,
+    content_hash: ContentHash,
+) -> (HashMap<Checksum, Vec<&Path>>, Vec<&Path>) {
+    let hashed = paths
+        .par_iter()
+        .filter_map(|path| {
+            skip_unreadable(path, Checksum::of_file_with(path, content_hash)).map(|c| (c, *path))
+        })
+        .collect::<Vec<(Checksum, &Path)>>();
+    let mut res: HashMap<Checksum, Vec<&Path>> = HashMap::new();
+    for (checksum, path) in hashed {
+        res.entry(checksum).or_default().push(path);
+    }
+    let empty = res.remove(&Checksum::empty()).unwrap_or_default();
+    res.retain(|_, paths| paths.len() > 1);
+    (res, empty)
+}
+
+/// Returns the hash computed for a file if it could be read, otherwise
+/// logs a warning and returns `None` so that the file gets skipped
+///
+/// This is so that a file that can't be read (or that gets modified
+/// e.g. truncated while it's being read) doesn't abort the entire
+/// scan.
+fn skip_unreadable<T>(path: &Path, result: io::Result<T>) -> Option<T> {
+    match result {
+        Ok(hash) => Some(hash),
+        Err(e) => {
+            warn!(
+                "Skipping file that couldn't be hashed: {} ({})",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Computes xxh3 checksum of the file, reusing the one recorded in
+/// the baseline snapshot or the cache if available
+fn xxh3_checksum(
+    path: &Path,
+    cache: Option<&HashCache>,
+    baseline: Option<&Baseline>,
+) -> io::Result<Checksum> {
+    if let Some(checksum) = baseline.and_then(|b| b.checksum(path)) {
+        return Ok(checksum);
+    }
+    match cache {
+        Some(c) => c.xxh3(path, || hash::xxh3_64(&path)).map(Checksum::new),
+        None => Checksum::of_file(&path),
+    }
+}
+
+/// Computes verification hash of the file, using the cache if
+/// provided
+fn verification_hash(
+    path: &Path,
+    verify_hash: VerificationHash,
+    cache: Option<&HashCache>,
+) -> io::Result<String> {
+    match cache {
+        Some(c) => c.verification_hash(path, verify_hash, || verify_hash.of_file(&path)),
+        None => verify_hash.of_file(&path),
+    }
+}
+
+/// Groups paths by their xxh3 checksums, retaining only the groups
+/// having more than one path.
+///
+/// The files are hashed in parallel using the rayon (global) thread
+/// pool, hence the order of paths within a group is not guaranteed.
+fn group_dups_by_xxh3<'a>(
+    paths: Vec<&'a Path>,
+    cache: Option<&HashCache>,
+    baseline: Option<&Baseline>,
+    progress: &bool,
+) -> io::Result<HashMap<Checksum, Vec<&'a Path>>> {
+    let pb = new_progress_bar(*progress, Some(paths.len() as u64), "Hashing");
+    let hashes = paths
+        .par_iter()
+        .filter_map(|path| {
+            let res = skip_unreadable(path, xxh3_checksum(path, cache, baseline))
+                .map(|hash| (hash, *path));
+            pb.inc(1);
+            res
+        })
+        .collect::<Vec<(Checksum, &Path)>>();
+    pb.finish_and_clear();
+    let mut res: HashMap<Checksum, Vec<&Path>> = HashMap::new();
+    for (hash, path) in hashes {
         match res.get_mut(&hash) {
             None => {
                 res.insert(hash, vec![path]);
@@ -122,48 +853,303 @@ fn group_dups_by_xxh3(paths: Vec<&Path>) -> io::Result<HashMap<Checksum, Vec<&Pa
     Ok(res)
 }
 
-fn confirm_dups(dups: HashMap<Checksum, Vec<&Path>>) -> io::Result<HashMap<Checksum, Vec<&Path>>> {
+/// Partitions the paths into groups of files having exactly the same
+/// contents by comparing them byte-by-byte
+///
+/// The first of the remaining paths is compared with all the others
+/// and the ones that match form a group. This is repeated until no
+/// paths remain. Files that can't be read are skipped with a warning.
+fn partition_by_bytes(paths: Vec<&Path>) -> Vec<Vec<&Path>> {
+    let mut remaining = paths
+        .into_iter()
+        .filter(|p| skip_unreadable(p, fs::File::open(p)).is_some())
+        .collect::<Vec<&Path>>();
+    let mut partitions: Vec<Vec<&Path>> = Vec::new();
+    while !remaining.is_empty() {
+        let first = remaining.remove(0);
+        let mut partition = vec![first];
+        let mut rest: Vec<&Path> = Vec::new();
+        for path in remaining {
+            match skip_unreadable(path, fileutil::files_equal(first, path)) {
+                Some(true) => partition.push(path),
+                Some(false) => rest.push(path),
+                None => {}
+            }
+        }
+        partitions.push(partition);
+        remaining = rest;
+    }
+    partitions
+}
+
+/// Partitions the paths into groups of files having the same
+/// verification hash, preserving the order in which the hashes are
+/// first encountered. Files that can't be read are skipped with a
+/// warning.
+fn partition_by_hash<'a>(
+    paths: Vec<&'a Path>,
+    verify_hash: VerificationHash,
+    cache: Option<&HashCache>,
+) -> Vec<Vec<&'a Path>> {
+    let hashed = paths.into_iter().filter_map(|p| {
+        skip_unreadable(p, verification_hash(p, verify_hash, cache)).map(|h| (h, p))
+    });
+    let mut partitions: Vec<(String, Vec<&Path>)> = Vec::new();
+    for (h, path) in hashed {
+        match partitions.iter_mut().find(|(ph, _)| *ph == h) {
+            Some((_, ps)) => ps.push(path),
+            None => partitions.push((h, vec![path])),
+        }
+    }
+    partitions.into_iter().map(|(_, ps)| ps).collect()
+}
+
+/// Partitions the paths into groups of files whose contents as per
+/// the `content_hash` mode are the same, by comparing either their
+/// verification hashes or the contents themselves. Files that can't
+/// be read are skipped with a warning.
+///
+/// The hash cache is not used as it holds the hashes of the raw
+/// contents.
+fn partition_by_content(
+    paths: Vec<&Path>,
+    verification: Verification,
+    content_hash: ContentHash,
+) -> Vec<Vec<&Path>> {
+    let keyed = paths.into_iter().filter_map(|p| {
+        let key = match verification {
+            Verification::Hash(verify_hash) => verify_hash
+                .of_file_with(&p, content_hash)
+                .map(String::into_bytes),
+            Verification::Bytes => content_hash.contents(p),
+        };
+        skip_unreadable(p, key).map(|k| (k, p))
+    });
+    let mut partitions: Vec<(Vec<u8>, Vec<&Path>)> = Vec::new();
+    for (k, path) in keyed {
+        match partitions.iter_mut().find(|(pk, _)| *pk == k) {
+            Some((_, ps)) => ps.push(path),
+            None => partitions.push((k, vec![path])),
+        }
+    }
+    partitions.into_iter().map(|(_, ps)| ps).collect()
+}
+
+/// Confirms that the files in every group are actually duplicates as
+/// per the `verification` method
+///
+/// Groups of files that are not hashed raw as per the `content_hash`
+/// mode (see `group_dups_by_content`) are confirmed by considering
+/// the same contents that were hashed.
+fn confirm_dups<'a>(
+    dups: HashMap<Checksum, Vec<&'a Path>>,
+    verification: Verification,
+    content_hash: ContentHash,
+    cache: Option<&HashCache>,
+) -> io::Result<HashMap<Checksum, Vec<&'a Path>>> {
     let mut res: HashMap<Checksum, Vec<&Path>> = HashMap::new();
     for (hash, paths) in dups {
-        let sha256hashes = paths
-            .iter()
-            .map(hash::sha256)
-            .map(|x| x.unwrap())
-            .collect::<HashSet<String>>();
-        if sha256hashes.len() == 1 {
-            res.insert(hash, paths);
+        let mut partitions = if paths.iter().any(|p| content_hash.applies_to(p)) {
+            partition_by_content(paths, verification, content_hash)
+        } else {
+            match verification {
+                Verification::Hash(verify_hash) => partition_by_hash(paths, verify_hash, cache),
+                Verification::Bytes => partition_by_bytes(paths),
+            }
+        };
+        // A group may be split into multiple partitions in case of an
+        // xxh3 collision. Partitions having a single file are not
+        // duplicates, but each of the rest is reported as a separate
+        // group, the largest one being keyed by the checksum itself.
+        partitions.retain(|p| p.len() > 1);
+        partitions.sort_by_key(|p| Reverse(p.len()));
+        for partition in partitions {
+            insert_group(&mut res, hash.clone(), partition);
         }
     }
     Ok(res)
 }
 
+/// Counters collected while scanning the rootdir
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    // No. of files found by traversing the rootdir
+    pub files_traversed: usize,
+    // No. of files having the same size as some other file
+    pub size_candidates: usize,
+    // No. of files in groups having the same xxh3 checksum
+    pub xxh3_grouped: usize,
+    // No. of groups of duplicates found (confirmed unless quick mode
+    // is used)
+    pub groups: usize,
+    // No. of groups left out of the snapshot as they are already
+    // de-duped or done
+    pub groups_hidden: usize,
+    // Total time spent in hashing (and comparing) the files
+    pub hashing_time: Duration,
+}
+
 fn group_duplicates<'a>(
-    rootdir: &Path,
+    rootdirs: &[PathBuf],
     paths: &'a [&'a Path],
-    quick: &bool,
+    opts: &ScanOptions,
+    cache: Option<&HashCache>,
+    stats: &mut ScanStats,
 ) -> io::Result<HashMap<Checksum, Vec<&'a Path>>> {
     let valid_paths = paths
         .iter()
-        .filter(|p| is_path_valid(rootdir, p))
+        .filter(|p| is_path_valid(rootdirs, p))
         .copied()
         .collect::<Vec<&Path>>();
-    let poss_dups = possible_duplicates(valid_paths)?;
-    let dups = group_dups_by_xxh3(poss_dups)?;
-    if !*quick {
-        confirm_dups(dups)
+    let valid_paths = filter_by_size(valid_paths, opts.min_size, opts.max_size)?;
+    let valid_paths = if opts.keep_hardlinks {
+        valid_paths
+    } else {
+        collapse_hardlinks(valid_paths)?
+    };
+    let (content_hashed, valid_paths): (Vec<&Path>, Vec<&Path>) = valid_paths
+        .into_iter()
+        .partition(|p| opts.content_hash.applies_to(p));
+    let (poss_dups, mut empty) = possible_duplicates(valid_paths)?;
+    stats.size_candidates = poss_dups.len();
+    let started_at = Instant::now();
+    let (small, large) = split_small_files(poss_dups);
+    let small_dups = group_small_dups(small);
+    let (content_dups, content_empty) = group_dups_by_content(content_hashed, opts.content_hash);
+    // All empty files are identical to each other, so there's no
+    // point in hashing them. Unless asked for, they are skipped as
+    // they would only make for a large group of no use.
+    empty.extend(content_empty);
+    if !opts.include_empty && !empty.is_empty() {
+        debug!("Skipping {} empty file(s)", empty.len());
+    }
+    let poss_dups = filter_by_prefix_hash(large)?;
+    let dups = group_dups_by_xxh3(poss_dups, cache, opts.baseline.as_ref(), &opts.progress)?;
+    stats.xxh3_grouped = dups
+        .values()
+        .chain(small_dups.values())
+        .chain(content_dups.values())
+        .map(|ps| ps.len())
+        .sum();
+    let (mut dups, content_dups) = if !opts.quick {
+        (
+            confirm_dups(dups, opts.verification, opts.content_hash, cache)?,
+            confirm_dups(content_dups, opts.verification, opts.content_hash, None)?,
+        )
     } else {
-        Ok(dups)
+        (dups, content_dups)
+    };
+    // Small and large files can't have the same contents, hence the
+    // checksums don't clash unless there's an xxh3 collision. Same
+    // for the files hashed as per the content hash mode
+    for (hash, paths) in small_dups.into_iter().chain(content_dups) {
+        insert_group(&mut dups, hash, paths);
+    }
+    if opts.include_empty && empty.len() > 1 {
+        dups.insert(Checksum::empty(), empty);
+    }
+    stats.hashing_time = started_at.elapsed();
+    stats.groups = dups.len();
+    Ok(dups)
+}
+
+/// Finds paths that differ only by case, which would refer to the
+/// same file on case-insensitive filesystems
+///
+/// Returns groups of such paths (in sorted order), irrespective of
+/// their contents.
+fn case_conflicts(paths: &[PathBuf]) -> Vec<Vec<&Path>> {
+    let mut by_lowercase: HashMap<String, Vec<&Path>> = HashMap::new();
+    for path in paths {
+        let key = path.to_string_lossy().to_lowercase();
+        by_lowercase.entry(key).or_default().push(path);
     }
+    let mut conflicts = by_lowercase
+        .into_values()
+        .filter(|ps| ps.len() > 1)
+        .map(|mut ps| {
+            ps.sort();
+            ps
+        })
+        .collect::<Vec<Vec<&Path>>>();
+    conflicts.sort();
+    conflicts
+}
+
+/// Excludes the paths that have been modified after `since` i.e.
+/// while the scan was in progress, logging a warning for each of
+/// them. Groups left with fewer than two paths are removed.
+///
+/// Paths whose mtime can't be read are also excluded, as they may
+/// have been deleted or replaced in the meanwhile.
+fn exclude_modified(
+    dups: HashMap<Checksum, Vec<PathBuf>>,
+    since: SystemTime,
+) -> HashMap<Checksum, Vec<PathBuf>> {
+    dups.into_iter()
+        .map(|(checksum, paths)| {
+            let paths = paths
+                .into_iter()
+                .filter(|p| match p.metadata().and_then(|m| m.modified()) {
+                    Ok(mtime) if mtime <= since => true,
+                    _ => {
+                        warn!("Skipping file modified during the scan: {}", p.display());
+                        false
+                    }
+                })
+                .collect::<Vec<PathBuf>>();
+            (checksum, paths)
+        })
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect()
 }
 
+/// Scans the rootdir for duplicates and returns them grouped by
+/// checksum, along with the stats collected while scanning
 pub fn scan(
     rootdir: &Path,
-    excludes: Option<&HashSet<PathBuf>>,
-    quick: &bool,
-) -> io::Result<HashMap<Checksum, Vec<PathBuf>>> {
-    let paths = traverse_bfs(rootdir, excludes)?;
+    opts: &ScanOptions,
+    cache: Option<&HashCache>,
+) -> io::Result<(HashMap<Checksum, Vec<PathBuf>>, ScanStats)> {
+    scan_rootdirs(&[rootdir.to_path_buf()], opts, cache)
+}
+
+/// Same as `scan` but for multiple rootdirs, which are traversed one
+/// after the other. The files found in all of them are grouped
+/// together, so that duplicates across the rootdirs are found too.
+///
+/// The rootdirs are expected to not overlap i.e. none of them must be
+/// located inside another.
+pub fn scan_rootdirs(
+    rootdirs: &[PathBuf],
+    opts: &ScanOptions,
+    cache: Option<&HashCache>,
+) -> io::Result<(HashMap<Checksum, Vec<PathBuf>>, ScanStats)> {
+    let started_at = SystemTime::now();
+    let mut paths = Vec::new();
+    for rootdir in rootdirs {
+        paths.extend(traverse_bfs(rootdir, opts)?);
+    }
+    if opts.case_insensitive_paths {
+        // These are only reported and don't affect the duplicates
+        // found by comparing contents
+        for conflict in case_conflicts(&paths) {
+            warn!(
+                "Paths differing only by case (may clash on case-insensitive filesystems): {}",
+                conflict
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+        }
+    }
+    let mut stats = ScanStats {
+        files_traversed: paths.len(),
+        ..Default::default()
+    };
     let path_list = paths.iter().map(|p| p.as_ref()).collect::<Vec<&Path>>();
-    let duplicates = group_duplicates(rootdir, &path_list, quick)?
+    let duplicates = group_duplicates(rootdirs, &path_list, opts, cache, &mut stats)?
         .into_iter()
         // `group_duplicates` internally deals with Path references
         // and hence returns `Vec<&Path>`. So here we need to create
@@ -171,5 +1157,1007 @@ pub fn scan(
         // function
         .map(|(d, ps)| (d, ps.into_iter().map(|p| p.to_path_buf()).collect()))
         .collect::<HashMap<Checksum, Vec<PathBuf>>>();
-    Ok(duplicates)
+    let duplicates = if opts.skip_modified {
+        let dups = exclude_modified(duplicates, started_at);
+        stats.groups = dups.len();
+        dups
+    } else {
+        duplicates
+    };
+    Ok((duplicates, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
+
+    #[test]
+    #[serial]
+    fn test_partition_by_bytes() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR);
+        let contents = ["ONE", "TWO", "ONE", "TWO", "ONE"];
+        let paths = contents
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let p = data_dir.join(format!("{i}.txt"));
+                fs::write(&p, c).unwrap();
+                p
+            })
+            .collect::<Vec<PathBuf>>();
+        let missing = data_dir.join("missing.txt");
+        let mut input = paths.iter().map(|p| p.as_path()).collect::<Vec<&Path>>();
+        input.push(&missing);
+
+        let partitions = partition_by_bytes(input);
+        assert_eq!(2, partitions.len());
+        assert_eq!(
+            vec![paths[0].as_path(), paths[2].as_path(), paths[4].as_path()],
+            partitions[0]
+        );
+        assert_eq!(vec![paths[1].as_path(), paths[3].as_path()], partitions[1]);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_traverse_bfs() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        for p in ["1.txt", "foo/2.txt", "foo/bar/3.txt", "cat/4.txt"] {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, p).unwrap();
+        }
+        std::os::unix::fs::symlink(data_dir.join("foo"), data_dir.join("foo_link")).unwrap();
+        let opts = ScanOptions {
+            excludes: Some(Excludes::new(&data_dir, &["cat".to_owned()]).unwrap()),
+            ..Default::default()
+        };
+
+        let mut paths = traverse_bfs(&data_dir, &opts).unwrap();
+        paths.sort();
+        assert_eq!(
+            vec![
+                data_dir.join("1.txt"),
+                data_dir.join("foo/2.txt"),
+                data_dir.join("foo/bar/3.txt"),
+            ],
+            paths
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_baseline_checksum() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let path = Path::new(TEST_DATA_DIR).join("1.txt");
+        fs::write(&path, "ONE").unwrap();
+        let entries = HashMap::from([(path.clone(), (42, 3))]);
+        let hour = std::time::Duration::from_secs(3600);
+
+        // File not modified since the baseline was generated
+        let baseline = Baseline::new(SystemTime::now() + hour, entries.clone());
+        assert!(baseline.checksum(&path) == Some(Checksum::new(42)));
+        assert!(baseline.checksum(Path::new("2.txt")).is_none());
+
+        // File modified after the baseline was generated
+        let baseline = Baseline::new(SystemTime::now() - hour, entries);
+        assert!(baseline.checksum(&path).is_none());
+
+        // File having a different size than the recorded one, even
+        // though its mtime is older
+        let entries = HashMap::from([(path.clone(), (42, 4))]);
+        let baseline = Baseline::new(SystemTime::now() + hour, entries);
+        assert!(baseline.checksum(&path).is_none());
+
+        // File replaced by another one having an older mtime
+        // (e.g. `cp -p`) after the baseline was generated
+        #[cfg(unix)]
+        {
+            let entries = HashMap::from([(path.clone(), (42, 3))]);
+            let baseline = Baseline::new(SystemTime::now() - hour, entries);
+            fs::write(&path, "TWO").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - hour - hour)
+                .unwrap();
+            assert!(baseline.checksum(&path).is_none());
+        }
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_traverse_bfs_follow_symlinks() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let rootdir = data_dir.join("root");
+        for p in ["root/foo/1.txt", "outside/2.txt"] {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, p).unwrap();
+        }
+        // Symlink to a dir inside the rootdir
+        std::os::unix::fs::symlink("../foo", rootdir.join("foo/self")).unwrap();
+        // Symlink to an ancestor, which would cause an infinite loop
+        std::os::unix::fs::symlink(&rootdir, rootdir.join("foo/loop")).unwrap();
+        // Symlink to a dir outside the rootdir
+        std::os::unix::fs::symlink(data_dir.join("outside"), rootdir.join("outside")).unwrap();
+        // Symlinked dir which is not visited via any other path
+        fs::create_dir_all(data_dir.join("root/.hidden/bar")).unwrap();
+        fs::write(data_dir.join("root/.hidden/bar/3.txt"), "3").unwrap();
+        std::os::unix::fs::symlink(".hidden/bar", rootdir.join("bar")).unwrap();
+
+        let opts = ScanOptions {
+            follow_symlinks: true,
+            excludes: Some(Excludes::new(&rootdir, &[".hidden".to_owned()]).unwrap()),
+            ..Default::default()
+        };
+        let mut paths = traverse_bfs(&rootdir, &opts).unwrap();
+        paths.sort();
+        assert_eq!(
+            vec![rootdir.join("bar/3.txt"), rootdir.join("foo/1.txt")],
+            paths
+        );
+
+        // Without the flag, symlinked dirs are not descended into
+        let opts = ScanOptions {
+            excludes: Some(Excludes::new(&rootdir, &[".hidden".to_owned()]).unwrap()),
+            ..Default::default()
+        };
+        let paths = traverse_bfs(&rootdir, &opts).unwrap();
+        assert_eq!(vec![rootdir.join("foo/1.txt")], paths);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_traverse_bfs_max_depth() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let files = ["0.txt", "a/1.txt", "a/b/2.txt", "a/b/c/3.txt", "x/1.txt"];
+        for p in files {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, p).unwrap();
+        }
+
+        let traverse = |max_depth: Option<usize>| {
+            let opts = ScanOptions {
+                max_depth,
+                ..Default::default()
+            };
+            let mut paths = traverse_bfs(&data_dir, &opts).unwrap();
+            paths.sort();
+            paths
+        };
+        let expected = |n: usize| {
+            files[..n]
+                .iter()
+                .map(|p| data_dir.join(p))
+                .collect::<Vec<PathBuf>>()
+        };
+
+        assert_eq!(expected(1), traverse(Some(0)));
+        let mut exp = expected(2);
+        exp.push(data_dir.join("x/1.txt"));
+        assert_eq!(exp, traverse(Some(1)));
+        let mut exp = expected(3);
+        exp.push(data_dir.join("x/1.txt"));
+        assert_eq!(exp, traverse(Some(2)));
+        assert_eq!(expected(5), traverse(Some(3)));
+        assert_eq!(expected(5), traverse(None));
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_traverse_bfs_glob_excludes() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let files = [
+            "1.txt",
+            "1.tmp",
+            "foo/2.txt",
+            "foo/2.tmp",
+            "foo/node_modules/3.txt",
+            "node_modules/4.txt",
+            "cat/5.txt",
+        ];
+        for p in files {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, p).unwrap();
+        }
+        let patterns = ["cat", "*.tmp", "**/node_modules/**"].map(String::from);
+        let opts = ScanOptions {
+            excludes: Some(Excludes::new(&data_dir, &patterns).unwrap()),
+            ..Default::default()
+        };
+
+        let mut paths = traverse_bfs(&data_dir, &opts).unwrap();
+        paths.sort();
+        assert_eq!(
+            vec![data_dir.join("1.txt"), data_dir.join("foo/2.txt")],
+            paths
+        );
+
+        // Invalid glob patterns result in error
+        assert!(Excludes::new(&data_dir, &["foo/[a".to_owned()]).is_err());
+
+        // Absolute paths are used as they are, whereas the ones not
+        // inside the rootdir are ignored
+        let patterns = [
+            data_dir.join("cat").display().to_string(),
+            data_dir.join("foo/../1.txt").display().to_string(),
+            "/outside".to_owned(),
+            "../outside".to_owned(),
+        ];
+        let excludes = Excludes::new(&data_dir, &patterns).unwrap();
+        assert_eq!(2, excludes.paths.len());
+        let opts = ScanOptions {
+            excludes: Some(excludes),
+            ..Default::default()
+        };
+        let mut paths = traverse_bfs(&data_dir, &opts).unwrap();
+        paths.sort();
+        assert_eq!(7 - 2, paths.len());
+        assert!(!paths.contains(&data_dir.join("1.txt")));
+        assert!(!paths.contains(&data_dir.join("cat/5.txt")));
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_traverse_bfs_respect_gitignore() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let files = [
+            ("1.txt", "1"),
+            ("1.log", "1"),
+            ("target/2.txt", "2"),
+            ("foo/3.log", "3"),
+            ("foo/keep.log", "keep"),
+            ("foo/bar/4.txt", "4"),
+            ("foo/bar/5.txt", "5"),
+            (".gitignore", "*.log\ntarget/\n"),
+            ("foo/.gitignore", "!keep.log\n/bar/4.txt\n"),
+        ];
+        for (p, contents) in files {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+
+        let opts = ScanOptions {
+            respect_gitignore: true,
+            ..Default::default()
+        };
+        let mut paths = traverse_bfs(&data_dir, &opts).unwrap();
+        paths.sort();
+        assert_eq!(
+            vec![
+                data_dir.join(".gitignore"),
+                data_dir.join("1.txt"),
+                data_dir.join("foo/.gitignore"),
+                data_dir.join("foo/bar/5.txt"),
+                data_dir.join("foo/keep.log"),
+            ],
+            paths
+        );
+
+        // Without the flag, gitignore files are not considered
+        let paths = traverse_bfs(&data_dir, &ScanOptions::default()).unwrap();
+        assert_eq!(files.len(), paths.len());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_scan_hardlinks() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::write(data_dir.join("a.txt"), "ONE").unwrap();
+        fs::hard_link(data_dir.join("a.txt"), data_dir.join("b.txt")).unwrap();
+        fs::write(data_dir.join("c.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("d.txt"), "TWO").unwrap();
+        fs::hard_link(data_dir.join("d.txt"), data_dir.join("e.txt")).unwrap();
+
+        let groups = |opts: &ScanOptions| {
+            let mut groups = scan(&data_dir, opts, None)
+                .unwrap()
+                .0
+                .into_values()
+                .map(|mut paths| {
+                    paths.sort();
+                    paths
+                })
+                .collect::<Vec<Vec<PathBuf>>>();
+            groups.sort();
+            groups
+        };
+
+        // Only one of the hardlinks is considered by default
+        assert_eq!(
+            vec![vec![data_dir.join("a.txt"), data_dir.join("c.txt")]],
+            groups(&ScanOptions::default())
+        );
+
+        let opts = ScanOptions {
+            keep_hardlinks: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![
+                vec![
+                    data_dir.join("a.txt"),
+                    data_dir.join("b.txt"),
+                    data_dir.join("c.txt")
+                ],
+                vec![data_dir.join("d.txt"), data_dir.join("e.txt")],
+            ],
+            groups(&opts)
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_traverse_bfs_extensions() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let files = [
+            "1.jpg",
+            "2.JPG",
+            "3.Png",
+            "4.txt",
+            "jpg",
+            "foo.jpg/5.jpg",
+            "foo.jpg/6",
+            "cat/7.png",
+        ];
+        for p in files {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, p).unwrap();
+        }
+        let opts = ScanOptions {
+            extensions: Some(HashSet::from(["jpg".to_owned(), "png".to_owned()])),
+            excludes: Some(Excludes::new(&data_dir, &["cat".to_owned()]).unwrap()),
+            ..Default::default()
+        };
+
+        let mut paths = traverse_bfs(&data_dir, &opts).unwrap();
+        paths.sort();
+        assert_eq!(
+            vec![
+                data_dir.join("1.jpg"),
+                data_dir.join("2.JPG"),
+                data_dir.join("3.Png"),
+                data_dir.join("foo.jpg/5.jpg"),
+            ],
+            paths
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_traverse_bfs_skip_hidden() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        // The rootdir itself is hidden
+        let rootdir = data_dir.join(".root");
+        let files = [
+            "1.txt",
+            ".DS_Store",
+            ".cache/2.txt",
+            "foo/.hidden.txt",
+            "foo/3.txt",
+            "cat/4.txt",
+        ];
+        for p in files {
+            let path = rootdir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, p).unwrap();
+        }
+        let opts = ScanOptions {
+            skip_hidden: true,
+            excludes: Some(Excludes::new(&rootdir, &["cat".to_owned()]).unwrap()),
+            ..Default::default()
+        };
+
+        let mut paths = traverse_bfs(&rootdir, &opts).unwrap();
+        paths.sort();
+        assert_eq!(
+            vec![rootdir.join("1.txt"), rootdir.join("foo/3.txt")],
+            paths
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_scan_permission_denied() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        // Permissions are not enforced for the root user, in which
+        // case there's nothing to test. A newly created dir is owned
+        // by the effective user of the process.
+        if data_dir.metadata().unwrap().uid() == 0 {
+            eprintln!("Skipping test_scan_permission_denied as it's run as root");
+            fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+            return;
+        }
+        let contents = "X".repeat(PREFIX_HASH_LEN as usize + 1);
+        for p in ["1.txt", "foo/2.txt", "locked/3.txt", "4.txt"] {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &contents).unwrap();
+        }
+        let set_mode = |p: &str, mode: u32| {
+            fs::set_permissions(data_dir.join(p), fs::Permissions::from_mode(mode)).unwrap()
+        };
+        set_mode("locked", 0o000);
+        set_mode("4.txt", 0o000);
+
+        let (dups, _) = scan(&data_dir, &ScanOptions::default(), None).unwrap();
+        assert_eq!(1, dups.len());
+        let mut paths = dups.into_values().next().unwrap();
+        paths.sort();
+        assert_eq!(
+            vec![data_dir.join("1.txt"), data_dir.join("foo/2.txt")],
+            paths
+        );
+
+        set_mode("locked", 0o755);
+        set_mode("4.txt", 0o644);
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_scan_stats() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::write(data_dir.join("1.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("2.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("3.txt"), "TWO").unwrap();
+        fs::write(data_dir.join("4.txt"), "FOUR").unwrap();
+
+        let (dups, stats) = scan(&data_dir, &ScanOptions::default(), None).unwrap();
+        assert_eq!(1, dups.len());
+        assert_eq!(4, stats.files_traversed);
+        // 4.txt has a distinct size
+        assert_eq!(3, stats.size_candidates);
+        assert_eq!(2, stats.xxh3_grouped);
+        assert_eq!(1, stats.groups);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_confirm_dups_collision() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR);
+        let contents = ["ONE", "TWO", "ONE"];
+        let paths = contents
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let p = data_dir.join(format!("{i}.txt"));
+                fs::write(&p, c).unwrap();
+                p
+            })
+            .collect::<Vec<PathBuf>>();
+        for verification in [
+            Verification::Hash(VerificationHash::Sha256),
+            Verification::Bytes,
+        ] {
+            // An xxh3 collision is simulated by grouping all the files
+            // under the same checksum
+            let mut dups = HashMap::new();
+            dups.insert(
+                Checksum::new(42),
+                paths.iter().map(|p| p.as_path()).collect::<Vec<&Path>>(),
+            );
+            let res = confirm_dups(dups, verification, ContentHash::Raw, None).unwrap();
+            assert_eq!(1, res.len());
+            assert_eq!(
+                vec![paths[0].as_path(), paths[2].as_path()],
+                *res.get(&Checksum::new(42)).unwrap()
+            );
+        }
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_confirm_dups_collision_multiple_sets() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR);
+        let contents = ["ONE", "TWO", "ONE", "TWO"];
+        let paths = contents
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let p = data_dir.join(format!("{i}.txt"));
+                fs::write(&p, c).unwrap();
+                p
+            })
+            .collect::<Vec<PathBuf>>();
+        // Two separate pairs of duplicates having the same xxh3 hash
+        let mut dups = HashMap::new();
+        dups.insert(
+            Checksum::new(42),
+            paths.iter().map(|p| p.as_path()).collect::<Vec<&Path>>(),
+        );
+        let res = confirm_dups(
+            dups,
+            Verification::Hash(VerificationHash::Sha256),
+            ContentHash::Raw,
+            None,
+        )
+        .unwrap();
+        assert_eq!(2, res.len());
+        assert_eq!(
+            vec![paths[0].as_path(), paths[2].as_path()],
+            *res.get(&Checksum::new(42)).unwrap()
+        );
+        assert_eq!(
+            vec![paths[1].as_path(), paths[3].as_path()],
+            *res.get(&Checksum::new(42).with_subgroup(1)).unwrap()
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_confirm_dups_by_bytes_multiple_sets() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR);
+        let contents = ["TWO", "ONE", "TWO", "THREE", "ONE", "ONE"];
+        let paths = contents
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let p = data_dir.join(format!("{i}.txt"));
+                fs::write(&p, c).unwrap();
+                p
+            })
+            .collect::<Vec<PathBuf>>();
+        let mut dups = HashMap::new();
+        dups.insert(
+            Checksum::new(42),
+            paths.iter().map(|p| p.as_path()).collect::<Vec<&Path>>(),
+        );
+        let res = confirm_dups(dups, Verification::Bytes, ContentHash::Raw, None).unwrap();
+        // Every set having more than one file is reported, the largest
+        // one being keyed by the checksum itself
+        assert_eq!(2, res.len());
+        assert_eq!(
+            vec![paths[1].as_path(), paths[4].as_path(), paths[5].as_path()],
+            *res.get(&Checksum::new(42)).unwrap()
+        );
+        assert_eq!(
+            vec![paths[0].as_path(), paths[2].as_path()],
+            *res.get(&Checksum::new(42).with_subgroup(1)).unwrap()
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    fn test_insert_group() {
+        let mut groups = HashMap::new();
+        insert_group(
+            &mut groups,
+            Checksum::new(42),
+            vec![Path::new("a"), Path::new("b")],
+        );
+        insert_group(
+            &mut groups,
+            Checksum::new(7),
+            vec![Path::new("c"), Path::new("d")],
+        );
+        // Small files having the same xxh3 hash but different contents
+        // are grouped separately, so both sets get reported
+        insert_group(
+            &mut groups,
+            Checksum::new(42),
+            vec![Path::new("e"), Path::new("f")],
+        );
+        insert_group(
+            &mut groups,
+            Checksum::new(42),
+            vec![Path::new("g"), Path::new("h")],
+        );
+        assert_eq!(4, groups.len());
+        assert_eq!(
+            vec![Path::new("e"), Path::new("f")],
+            *groups.get(&Checksum::new(42).with_subgroup(1)).unwrap()
+        );
+        assert_eq!(
+            vec![Path::new("g"), Path::new("h")],
+            *groups.get(&Checksum::new(42).with_subgroup(2)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_case_conflicts() {
+        let paths = vec![
+            PathBuf::from("/a/photo.jpg"),
+            PathBuf::from("/a/Photo.JPG"),
+            PathBuf::from("/a/other.jpg"),
+            PathBuf::from("/B/1.txt"),
+            PathBuf::from("/b/1.txt"),
+            PathBuf::from("/c/1.txt"),
+        ];
+        assert_eq!(
+            vec![
+                vec![Path::new("/B/1.txt"), Path::new("/b/1.txt")],
+                vec![Path::new("/a/Photo.JPG"), Path::new("/a/photo.jpg")],
+            ],
+            case_conflicts(&paths)
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_scan_small_and_large_files() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let small = "x".repeat(SMALL_FILE_LEN as usize);
+        let large = "x".repeat(SMALL_FILE_LEN as usize + 1);
+        let large_diff = format!("{}y", "x".repeat(SMALL_FILE_LEN as usize));
+        fs::write(data_dir.join("s1.txt"), &small).unwrap();
+        fs::write(data_dir.join("s2.txt"), &small).unwrap();
+        fs::write(data_dir.join("s3.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("s4.txt"), "TWO").unwrap();
+        fs::write(data_dir.join("l1.txt"), &large).unwrap();
+        fs::write(data_dir.join("l2.txt"), &large).unwrap();
+        fs::write(data_dir.join("l3.txt"), &large_diff).unwrap();
+
+        let (dups, stats) = scan(&data_dir, &ScanOptions::default(), None).unwrap();
+        let mut groups = dups
+            .into_iter()
+            .map(|(checksum, mut paths)| {
+                paths.sort();
+                // Checksums are the same irrespective of how the
+                // duplicates are found
+                assert!(checksum == Checksum::of_file(&paths[0]).unwrap());
+                paths
+            })
+            .collect::<Vec<Vec<PathBuf>>>();
+        groups.sort();
+        assert_eq!(
+            vec![
+                vec![data_dir.join("l1.txt"), data_dir.join("l2.txt")],
+                vec![data_dir.join("s1.txt"), data_dir.join("s2.txt")],
+            ],
+            groups
+        );
+        assert_eq!(2, stats.groups);
+        assert_eq!(4, stats.xxh3_grouped);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_exclude_modified() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let paths = ["1.txt", "2.txt", "3.txt", "4.txt", "5.txt"]
+            .iter()
+            .map(|p| data_dir.join(p))
+            .collect::<Vec<PathBuf>>();
+        for p in paths.iter() {
+            fs::write(p, "ONE").unwrap();
+        }
+        let since = SystemTime::now() + Duration::from_secs(60);
+        // 2.txt and 4.txt get modified during the scan
+        for p in [&paths[1], &paths[3]] {
+            let f = fs::File::options().write(true).open(p).unwrap();
+            f.set_modified(since + Duration::from_secs(1)).unwrap();
+        }
+        let dups = HashMap::from([
+            (
+                Checksum::new(1),
+                vec![paths[0].clone(), paths[1].clone(), paths[2].clone()],
+            ),
+            (Checksum::new(2), vec![paths[3].clone(), paths[4].clone()]),
+        ]);
+        let dups = exclude_modified(dups, since);
+        assert_eq!(1, dups.len());
+        assert_eq!(
+            &vec![paths[0].clone(), paths[2].clone()],
+            dups.get(&Checksum::new(1)).unwrap()
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_scan_rootdirs() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let rootdirs = vec![data_dir.join("a"), data_dir.join("b")];
+        for d in ["a/skip", "b/skip", "c"] {
+            fs::create_dir_all(data_dir.join(d)).unwrap();
+        }
+        for p in [
+            "a/1.txt",
+            "b/1.txt",
+            "a/skip/1.txt",
+            "b/skip/1.txt",
+            "c/1.txt",
+        ] {
+            fs::write(data_dir.join(p), "ONE").unwrap();
+        }
+        fs::write(data_dir.join("a/2.txt"), "TWO").unwrap();
+        // Symlinks across the rootdirs are valid, whereas the ones to
+        // outside them are not
+        fileutil::symlink(&data_dir.join("a/2.txt"), &data_dir.join("b/2.txt")).unwrap();
+        fileutil::symlink(&data_dir.join("c/1.txt"), &data_dir.join("b/3.txt")).unwrap();
+
+        let opts = ScanOptions {
+            excludes: Some(Excludes::of_rootdirs(&rootdirs, &["skip".to_owned()]).unwrap()),
+            keep_hardlinks: true,
+            ..Default::default()
+        };
+        let (dups, stats) = scan_rootdirs(&rootdirs, &opts, None).unwrap();
+        let mut groups = dups
+            .into_values()
+            .map(|mut paths| {
+                paths.sort();
+                paths
+            })
+            .collect::<Vec<Vec<PathBuf>>>();
+        groups.sort();
+        assert_eq!(
+            vec![
+                vec![data_dir.join("a/1.txt"), data_dir.join("b/1.txt")],
+                vec![data_dir.join("a/2.txt"), data_dir.join("b/2.txt")],
+            ],
+            groups
+        );
+        assert_eq!(5, stats.files_traversed);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_ignore_file() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let rootdirs = vec![data_dir.join("a"), data_dir.join("b")];
+        for p in [
+            "a/1.txt",
+            "a/2.tmp",
+            "a/cat/3.txt",
+            "b/1.txt",
+            "b/2.tmp",
+            "b/cat/3.txt",
+        ] {
+            let path = data_dir.join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, p).unwrap();
+        }
+        fs::write(
+            rootdirs[0].join(IGNORE_FILE),
+            "# Temp files\n*.tmp\n\n  cat  \n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(vec!["*.tmp".to_owned(), "cat".to_owned()]),
+            read_ignore_file(&rootdirs[0]).unwrap()
+        );
+        assert_eq!(None, read_ignore_file(&rootdirs[1]).unwrap());
+
+        // Patterns in the ignore file are combined with the other
+        // exclusions, but only apply to its own rootdir
+        let mut excludes = Excludes::of_rootdirs(&rootdirs, &["1.txt".to_owned()]).unwrap();
+        let patterns = read_ignore_file(&rootdirs[0]).unwrap().unwrap();
+        excludes.add_ignore_file(&rootdirs[0], &patterns).unwrap();
+        let opts = ScanOptions {
+            excludes: Some(excludes),
+            ..Default::default()
+        };
+        let mut paths = traverse_bfs(&rootdirs[0], &opts).unwrap();
+        paths.extend(traverse_bfs(&rootdirs[1], &opts).unwrap());
+        paths.sort();
+        assert_eq!(
+            vec![data_dir.join("b/2.tmp"), data_dir.join("b/cat/3.txt"),],
+            paths
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_scan_content_hash_images() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+
+        // JPEGs that differ only in the (differently sized) EXIF
+        // segment
+        let jpeg = |exif: &[u8]| {
+            let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 2 + exif.len() as u8];
+            bytes.extend_from_slice(exif);
+            bytes.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x03, 0x01, 0xAB, 0xFF, 0xD9]);
+            bytes
+        };
+        fs::write(data_dir.join("1.jpg"), jpeg(b"2023:12:12")).unwrap();
+        fs::write(data_dir.join("2.jpg"), jpeg(b"2024:01:01 10:00")).unwrap();
+        fs::write(data_dir.join("3.txt"), "THREE").unwrap();
+        fs::write(data_dir.join("4.txt"), "THREE").unwrap();
+
+        let rootdirs = vec![data_dir.clone()];
+        let group_sizes_verified_with = |content_hash: ContentHash, verification: Verification| {
+            let opts = ScanOptions {
+                content_hash,
+                verification,
+                ..Default::default()
+            };
+            let (dups, _) = scan_rootdirs(&rootdirs, &opts, None).unwrap();
+            let mut sizes = dups.values().map(|ps| ps.len()).collect::<Vec<usize>>();
+            sizes.sort();
+            sizes
+        };
+        let group_sizes = |content_hash: ContentHash| {
+            group_sizes_verified_with(content_hash, Verification::Hash(VerificationHash::Blake3))
+        };
+        assert_eq!(vec![2], group_sizes(ContentHash::Raw));
+        // The image data is confirmed to be the same, irrespective of
+        // the verification method
+        assert_eq!(vec![2, 2], group_sizes(ContentHash::Images));
+        assert_eq!(
+            vec![2, 2],
+            group_sizes_verified_with(ContentHash::Images, Verification::Bytes)
+        );
+
+        // Images whose data differs are partitioned separately
+        fs::write(data_dir.join("5.jpg"), b"not a jpeg").unwrap();
+        let paths = ["1.jpg", "2.jpg", "5.jpg"].map(|p| data_dir.join(p));
+        for verification in [
+            Verification::Hash(VerificationHash::Sha256),
+            Verification::Bytes,
+        ] {
+            assert_eq!(
+                vec![vec![&paths[0], &paths[1]], vec![&paths[2]]],
+                partition_by_content(
+                    paths.iter().map(|p| p.as_path()).collect(),
+                    verification,
+                    ContentHash::Images
+                )
+            );
+        }
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_scan_empty_files() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        for p in ["1.txt", "2.txt", "3.txt"] {
+            fs::write(data_dir.join(p), "").unwrap();
+        }
+        fs::write(data_dir.join("4.txt"), "FOUR").unwrap();
+        fs::write(data_dir.join("5.txt"), "FOUR").unwrap();
+
+        let rootdirs = vec![data_dir.clone()];
+        let (dups, _) = scan_rootdirs(&rootdirs, &ScanOptions::default(), None).unwrap();
+        assert_eq!(1, dups.len());
+        assert!(!dups.contains_key(&Checksum::empty()));
+
+        let opts = ScanOptions {
+            include_empty: true,
+            ..Default::default()
+        };
+        let (dups, _) = scan_rootdirs(&rootdirs, &opts, None).unwrap();
+        assert_eq!(2, dups.len());
+        let mut empty = dups.get(&Checksum::empty()).unwrap().clone();
+        empty.sort();
+        assert_eq!(
+            vec![
+                data_dir.join("1.txt"),
+                data_dir.join("2.txt"),
+                data_dir.join("3.txt")
+            ],
+            empty
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_scan_empty_files_hardlinks() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::write(data_dir.join("1.txt"), "").unwrap();
+        fs::hard_link(data_dir.join("1.txt"), data_dir.join("2.txt")).unwrap();
+        fs::write(data_dir.join("3.txt"), "").unwrap();
+        fs::write(data_dir.join("4.jpg"), "").unwrap();
+
+        // Hardlinks to the same empty file are collapsed into one
+        let rootdirs = vec![data_dir.clone()];
+        let opts = ScanOptions {
+            include_empty: true,
+            content_hash: ContentHash::Images,
+            ..Default::default()
+        };
+        let (dups, _) = scan_rootdirs(&rootdirs, &opts, None).unwrap();
+        assert_eq!(1, dups.len());
+        let mut empty = dups.get(&Checksum::empty()).unwrap().clone();
+        empty.sort();
+        assert_eq!(
+            vec![
+                data_dir.join("1.txt"),
+                data_dir.join("3.txt"),
+                data_dir.join("4.jpg")
+            ],
+            empty
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
 }
@@ -0,0 +1,88 @@
+use super::textformat::sorted_groups;
+use super::Snapshot;
+use crate::fileutil::encode_path;
+
+/// Renders the snapshot as one line per file containing the checksum
+/// and the absolute path separated by a space
+///
+/// Files in the same group are rendered on consecutive lines, hence
+/// the output can be processed with tools such as `sort` and `uniq`.
+/// There are no op keywords, metadata or help lines. Note that this
+/// format is only meant for exporting the duplicates and can't be
+/// parsed back into a snapshot.
+///
+/// Paths are encoded using `fileutil::encode_path` as they may not be
+/// valid UTF-8.
+pub fn render(snap: &Snapshot) -> Vec<String> {
+    let mut lines = vec![];
+    for (ck, fps) in sorted_groups(&snap.duplicates) {
+        for fp in fps {
+            lines.push(format!("{} {}", ck, encode_path(&fp.path)));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::textformat;
+
+    #[test]
+    fn test_render() {
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "[937219074347857651]",
+            "keep bar/1 2.txt",
+            "delete 1.txt",
+        ];
+        let lines = input.iter().map(|s| String::from(*s)).collect();
+        let snap = textformat::parse(lines).unwrap();
+        assert_eq!(
+            vec![
+                "0d01abc8533976f3 /foo/bar/1 2.txt",
+                "0d01abc8533976f3 /foo/1.txt",
+            ],
+            render(&snap)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_render_non_utf8_path() {
+        use crate::hash::Checksum;
+        use crate::snapshot::{FileOp, FilePath};
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::PathBuf;
+
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+        ];
+        let lines = input.iter().map(|s| String::from(*s)).collect();
+        let mut snap = textformat::parse(lines).unwrap();
+        snap.duplicates.insert(
+            Checksum::new(1),
+            vec![
+                FilePath {
+                    path: PathBuf::from("/foo/1.txt"),
+                    op: FileOp::Keep,
+                },
+                FilePath {
+                    path: PathBuf::from(OsStr::from_bytes(b"/foo/caf\xe9.txt")),
+                    op: FileOp::Delete,
+                },
+            ],
+        );
+        assert_eq!(
+            vec![
+                "0000000000000001 /foo/1.txt",
+                "0000000000000001 /foo/caf\\xe9.txt",
+            ],
+            render(&snap)
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use super::textformat::sorted_groups;
+use super::{entry_path, Snapshot};
+use crate::hash::Checksum;
+
+const HEADER: &str = "group_index,checksum,size,path,op,label";
+
+/// Label of the group of empty (zero-length) files
+const EMPTY_FILES_LABEL: &str = "empty files";
+
+/// Quotes the field if it contains any of the characters that have a
+/// special meaning in CSV (as per RFC 4180). Double quotes inside
+/// the field are escaped by doubling them.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Renders the snapshot as CSV with one row per file
+///
+/// Note that the CSV format is only meant for the purpose of
+/// reviewing the duplicates (e.g. in a spreadsheet) and can't be
+/// parsed back into a snapshot.
+pub fn render(snap: &Snapshot) -> Vec<String> {
+    if snap.duplicates.is_empty() {
+        return vec![];
+    }
+    let mut rows = vec![HEADER.to_owned()];
+    for (i, (ck, fps)) in sorted_groups(&snap.duplicates).into_iter().enumerate() {
+        let label = if *ck == Checksum::empty() {
+            EMPTY_FILES_LABEL
+        } else {
+            ""
+        };
+        for fp in fps {
+            // Size is left empty if the file metadata can't be read
+            let size = fp.size().map(|s| s.to_string()).unwrap_or_default();
+            let path = entry_path(&fp.path, &snap.rootdir).display().to_string();
+            rows.push(format!(
+                "{},{},{},{},{},{}",
+                i + 1,
+                ck,
+                size,
+                quote(&path),
+                fp.op.keyword(),
+                label
+            ));
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::textformat;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!("foo/1.txt", quote("foo/1.txt"));
+        assert_eq!("\"foo/1,2.txt\"", quote("foo/1,2.txt"));
+        assert_eq!("\"foo/\"\"1\"\".txt\"", quote("foo/\"1\".txt"));
+    }
+
+    #[test]
+    fn test_render() {
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "[937219074347857651]",
+            "keep bar/1,2.txt",
+            "delete 1.txt",
+        ];
+        let lines = input.iter().map(|s| String::from(*s)).collect();
+        let snap = textformat::parse(lines).unwrap();
+        // Sizes are empty as the files don't exist
+        assert_eq!(
+            vec![
+                "group_index,checksum,size,path,op,label",
+                "1,0d01abc8533976f3,,\"bar/1,2.txt\",keep,",
+                "1,0d01abc8533976f3,,1.txt,delete,",
+            ],
+            render(&snap)
+        );
+    }
+
+    #[test]
+    fn test_render_empty_files() {
+        let input = [
+            "#! Root Directory: /foo".to_owned(),
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530".to_owned(),
+            "#! Checksum Format: hex".to_owned(),
+            "".to_owned(),
+            format!("[{}]", Checksum::empty()),
+            "keep 1.txt".to_owned(),
+            "delete 2.txt".to_owned(),
+        ];
+        let snap = textformat::parse(input.to_vec()).unwrap();
+        assert_eq!(
+            vec![
+                "group_index,checksum,size,path,op,label".to_owned(),
+                format!("1,{},,1.txt,keep,empty files", Checksum::empty()),
+                format!("1,{},,2.txt,delete,empty files", Checksum::empty()),
+            ],
+            render(&snap)
+        );
+    }
+}
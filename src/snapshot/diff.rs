@@ -0,0 +1,208 @@
+use super::textformat::render_filepath;
+use super::{entry_path, FilePath, Snapshot};
+use crate::hash::Checksum;
+use log::warn;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub enum GroupStatus {
+    // Group is present only in the new snapshot
+    Added,
+    // Group is present only in the old snapshot
+    Removed,
+    // Group is present in both, but with different paths or ops
+    Changed,
+}
+
+/// Differences between the two snapshots for a single group of
+/// duplicates, identified by its checksum
+#[derive(Debug, PartialEq)]
+pub struct GroupDiff {
+    pub checksum: Checksum,
+    pub status: GroupStatus,
+    // Paths (along with their ops) that are only in the old snapshot
+    pub removed: Vec<String>,
+    // Paths (along with their ops) that are only in the new snapshot
+    pub added: Vec<String>,
+}
+
+impl GroupDiff {
+    /// Renders the group diff as lines of a plain diff i.e. paths
+    /// prefixed with `-` and `+`. A path whose op has changed appears
+    /// on both sides.
+    pub fn render(&self) -> Vec<String> {
+        let header = format!("[{}]", self.checksum);
+        let header = match self.status {
+            GroupStatus::Added => format!("{} (new group)", header),
+            GroupStatus::Removed => format!("{} (removed group)", header),
+            GroupStatus::Changed => header,
+        };
+        let mut lines = vec![header];
+        lines.extend(self.removed.iter().map(|l| format!("- {}", l)));
+        lines.extend(self.added.iter().map(|l| format!("+ {}", l)));
+        lines
+    }
+}
+
+/// Returns the rendered lines of all the paths in the group keyed by
+/// the path relative to the rootdir
+fn group_lines(filepaths: &[FilePath], rootdir: &Path) -> BTreeMap<PathBuf, String> {
+    filepaths
+        .iter()
+        .map(|fp| {
+            let key = entry_path(&fp.path, rootdir);
+            (key, render_filepath(fp, rootdir))
+        })
+        .collect()
+}
+
+/// Finds differences between the two snapshots, returning only the
+/// groups that differ, sorted by checksum
+///
+/// Paths are compared relative to the rootdir of the respective
+/// snapshots, so that snapshots of the same dir taken at different
+/// locations can be compared. In case the rootdirs differ, a warning
+/// is logged.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<GroupDiff> {
+    if old.rootdirs() != new.rootdirs() {
+        warn!(
+            "Snapshots have different root directories: {} and {}. Paths will be compared relative to them",
+            old.rootdir.display(),
+            new.rootdir.display()
+        );
+    }
+    let checksums = old
+        .duplicates
+        .keys()
+        .chain(new.duplicates.keys())
+        .collect::<BTreeSet<&Checksum>>();
+    let mut result = Vec::new();
+    for checksum in checksums {
+        let old_lines = old
+            .duplicates
+            .get(checksum)
+            .map(|fps| group_lines(fps, &old.rootdir));
+        let new_lines = new
+            .duplicates
+            .get(checksum)
+            .map(|fps| group_lines(fps, &new.rootdir));
+        let status = match (&old_lines, &new_lines) {
+            (Some(_), Some(_)) => GroupStatus::Changed,
+            (Some(_), None) => GroupStatus::Removed,
+            (None, _) => GroupStatus::Added,
+        };
+        let old_lines = old_lines.unwrap_or_default();
+        let new_lines = new_lines.unwrap_or_default();
+        let removed = old_lines
+            .iter()
+            .filter(|(path, line)| new_lines.get(*path) != Some(*line))
+            .map(|(_, line)| line.clone())
+            .collect::<Vec<String>>();
+        let added = new_lines
+            .iter()
+            .filter(|(path, line)| old_lines.get(*path) != Some(*line))
+            .map(|(_, line)| line.clone())
+            .collect::<Vec<String>>();
+        if !removed.is_empty() || !added.is_empty() {
+            result.push(GroupDiff {
+                checksum: checksum.clone(),
+                status,
+                removed,
+                added,
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::textformat::parse;
+
+    fn snapshot(rootdir: &str, lines: &[&str]) -> Snapshot {
+        let mut input = vec![
+            format!("#! Root Directory: {}", rootdir),
+            "#! Generated at: Sun, 24 Dec 2023 13:07:06 +0530".to_owned(),
+        ];
+        input.extend(lines.iter().map(|s| s.to_string()));
+        parse(input).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = snapshot(
+            "/foo",
+            &[
+                "[1]",
+                "keep a.txt",
+                "keep b.txt",
+                "[2]",
+                "keep c.txt",
+                "delete d.txt",
+                "[3]",
+                "keep e.txt",
+                "keep f.txt",
+            ],
+        );
+        let new = snapshot(
+            "/foo",
+            &[
+                "[1]",
+                "keep a.txt",
+                "symlink b.txt",
+                "[2]",
+                "keep c.txt",
+                "delete d.txt",
+                "[4]",
+                "keep g.txt",
+                "delete h.txt",
+            ],
+        );
+        let result = diff(&old, &new);
+        assert_eq!(
+            vec![
+                GroupDiff {
+                    checksum: Checksum::new(1),
+                    status: GroupStatus::Changed,
+                    removed: vec!["keep b.txt".to_owned()],
+                    added: vec!["symlink b.txt".to_owned()],
+                },
+                GroupDiff {
+                    checksum: Checksum::new(3),
+                    status: GroupStatus::Removed,
+                    removed: vec!["keep e.txt".to_owned(), "keep f.txt".to_owned()],
+                    added: vec![],
+                },
+                GroupDiff {
+                    checksum: Checksum::new(4),
+                    status: GroupStatus::Added,
+                    removed: vec![],
+                    added: vec!["keep g.txt".to_owned(), "delete h.txt".to_owned()],
+                },
+            ],
+            result
+        );
+        assert_eq!(
+            vec!["[0000000000000001]", "- keep b.txt", "+ symlink b.txt"],
+            result[0].render()
+        );
+        assert_eq!(
+            vec![
+                "[0000000000000003] (removed group)",
+                "- keep e.txt",
+                "- keep f.txt"
+            ],
+            result[1].render()
+        );
+
+        // Snapshots with different rootdirs are compared using
+        // relative paths
+        let new = snapshot("/bar", &["[1]", "keep a.txt", "keep b.txt"]);
+        let result = diff(&old, &new);
+        assert_eq!(2, result.len());
+        assert_eq!(GroupStatus::Removed, result[0].status);
+        assert_eq!(GroupStatus::Removed, result[1].status);
+    }
+}
@@ -0,0 +1,314 @@
+use super::textformat::sorted_groups;
+use super::{entry_path, resolve_entry_path, FileOp, FilePath, Snapshot};
+use crate::error::AppError;
+use crate::fileutil::{decode_path, encode_path};
+use crate::hash::{Checksum, ChecksumFormat, ContentHash, VerificationHash};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct JsonPath {
+    // Path relative to the rootdir, or absolute if it's under one of
+    // the extra rootdirs
+    path: String,
+    op: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dest: Option<String>,
+}
+
+impl JsonPath {
+    fn new(filepath: &FilePath, rootdir: &Path) -> Self {
+        let path = encode_path(&entry_path(&filepath.path, rootdir));
+        let op = filepath.op.keyword().to_owned();
+        let (source, dest) = match &filepath.op {
+            FileOp::Symlink { source } | FileOp::Hardlink { source } => {
+                (source.as_deref().map(encode_path), None)
+            }
+            FileOp::Move { dest } => (None, Some(encode_path(dest))),
+            FileOp::Keep | FileOp::Delete | FileOp::Trash => (None, None),
+        };
+        Self {
+            path,
+            op,
+            source,
+            dest,
+        }
+    }
+
+    fn filepath(&self, rootdirs: &[&Path]) -> Result<FilePath, AppError> {
+        let path = resolve_entry_path(&decode_path(&self.path), rootdirs)?;
+        let extra = self.source.as_ref().or(self.dest.as_ref());
+        let op =
+            FileOp::decode(&self.op, extra.map(|s| s.as_str())).ok_or(AppError::SnapshotParsing)?;
+        Ok(FilePath { path, op })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct JsonGroup {
+    // Checksum is serialized as a string as the max value of u64 is
+    // beyond the range of integers that can be represented exactly
+    // in some JSON implementations
+    checksum: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    allow_full_deletion: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_size: Option<u64>,
+    // Labels the group of empty (zero-length) files. It's only for
+    // the reader and is ignored when parsing.
+    #[serde(default, skip_serializing_if = "is_false")]
+    empty_files: bool,
+    paths: Vec<JsonPath>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct JsonSnapshot {
+    // Encoded using `fileutil::encode_path` as the path may not be
+    // valid unicode
+    rootdir: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_rootdirs: Vec<String>,
+    // Timestamp in RFC 3339 format
+    generated_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verify_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum_algorithm: Option<String>,
+    // Absent in snapshots generated by older versions of the tool, in
+    // which case the checksums are in decimal form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum_format: Option<String>,
+    #[serde(default)]
+    quick_mode: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    // Metadata fields not recognized by the tool
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+    groups: Vec<JsonGroup>,
+}
+
+/// Returns whether the input is (most likely) a snapshot in JSON
+/// format, which is the case if the first non-blank character is `{`
+pub fn is_json(str_lines: &[String]) -> bool {
+    str_lines
+        .iter()
+        .find_map(|s| s.trim_start().chars().next())
+        .is_some_and(|c| c == '{')
+}
+
+pub fn render(snap: &Snapshot) -> Vec<String> {
+    let groups = sorted_groups(&snap.duplicates)
+        .into_iter()
+        .map(|(ck, fps)| JsonGroup {
+            checksum: ck.to_string(),
+            allow_full_deletion: snap.full_deletion_groups.contains(ck),
+            file_size: snap.file_sizes.get(ck).copied(),
+            empty_files: *ck == Checksum::empty(),
+            paths: fps
+                .iter()
+                .map(|fp| JsonPath::new(fp, &snap.rootdir))
+                .collect(),
+        })
+        .collect();
+    let json_snap = JsonSnapshot {
+        rootdir: encode_path(&snap.rootdir),
+        extra_rootdirs: snap.extra_rootdirs.iter().map(|d| encode_path(d)).collect(),
+        generated_at: snap.generated_at.to_rfc3339(),
+        verify_hash: snap.verify_hash.map(|vh| vh.name().to_owned()),
+        checksum_algorithm: Some(snap.checksum_algo.clone()),
+        checksum_format: Some(ChecksumFormat::Hex.name().to_owned()),
+        quick_mode: snap.quick_mode,
+        content_hash: (snap.content_hash != ContentHash::Raw)
+            .then(|| snap.content_hash.name().to_owned()),
+        metadata: snap.custom_metadata.clone(),
+        groups,
+    };
+    // Serialization of the above structs can't fail
+    let output = serde_json::to_string_pretty(&json_snap).unwrap();
+    output.lines().map(|s| s.to_owned()).collect()
+}
+
+pub fn parse(str_lines: Vec<String>) -> Result<Snapshot, AppError> {
+    let json_snap: JsonSnapshot =
+        serde_json::from_str(&str_lines.join("\n")).map_err(|_| AppError::SnapshotParsing)?;
+    let generated_at = DateTime::parse_from_rfc3339(&json_snap.generated_at)
+        .map_err(|_| AppError::SnapshotParsing)?;
+    let verify_hash = json_snap
+        .verify_hash
+        .map(|vh| VerificationHash::parse(&vh).map_err(|_| AppError::SnapshotParsing))
+        .transpose()?;
+    let content_hash = json_snap
+        .content_hash
+        .map(|ch| ContentHash::parse(&ch).map_err(|_| AppError::SnapshotParsing))
+        .transpose()?
+        .unwrap_or(ContentHash::Raw);
+    let checksum_format = json_snap
+        .checksum_format
+        .map(|cf| ChecksumFormat::parse(&cf).map_err(|_| AppError::SnapshotParsing))
+        .transpose()?
+        .unwrap_or(ChecksumFormat::Decimal);
+    if json_snap.rootdir.is_empty() {
+        return Err(AppError::SnapshotMetadata(
+            "Root directory is required and can't be empty".to_owned(),
+        ));
+    }
+    let rootdir = decode_path(&json_snap.rootdir);
+    let extra_rootdirs = json_snap
+        .extra_rootdirs
+        .iter()
+        .map(|d| decode_path(d))
+        .collect::<Vec<PathBuf>>();
+    let mut rootdirs = vec![rootdir.as_path()];
+    rootdirs.extend(extra_rootdirs.iter().map(|d| d.as_path()));
+    let mut duplicates: HashMap<Checksum, Vec<FilePath>> = HashMap::new();
+    let mut full_deletion_groups: HashSet<Checksum> = HashSet::new();
+    let mut file_sizes: HashMap<Checksum, u64> = HashMap::new();
+    for group in json_snap.groups {
+        let checksum = Checksum::parse(&group.checksum, checksum_format)
+            .map_err(|_| AppError::SnapshotParsing)?;
+        if group.allow_full_deletion {
+            full_deletion_groups.insert(checksum.clone());
+        }
+        if let Some(size) = group.file_size {
+            file_sizes.insert(checksum.clone(), size);
+        }
+        let filepaths = group
+            .paths
+            .iter()
+            .map(|p| p.filepath(&rootdirs))
+            .collect::<Result<Vec<FilePath>, AppError>>()?;
+        duplicates.entry(checksum).or_default().extend(filepaths);
+    }
+    Ok(Snapshot {
+        rootdir,
+        extra_rootdirs,
+        generated_at,
+        verify_hash,
+        checksum_algo: json_snap
+            .checksum_algorithm
+            .unwrap_or_else(|| Checksum::ALGORITHM.to_owned()),
+        quick_mode: json_snap.quick_mode,
+        content_hash,
+        duplicates,
+        full_deletion_groups,
+        file_sizes,
+        custom_metadata: json_snap.metadata,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::textformat;
+
+    #[test]
+    fn test_is_json() {
+        assert!(is_json(&["".to_owned(), "  {".to_owned()]));
+        assert!(!is_json(&["#! Root Directory: /foo".to_owned()]));
+        assert!(!is_json(&[]));
+    }
+
+    #[test]
+    fn test_render_parse_roundtrip() {
+        let input = vec![
+            "#! Root Directory: /foo",
+            "#! Generated at: Sun, 24 Dec 2023 13:07:06 +0530",
+            "#! Verification Hash: blake3",
+            "",
+            "[937219074347857651]",
+            "keep bar/1.txt",
+            "symlink bar/2.txt -> 1.txt",
+            "hardlink bar/3.txt",
+            "move bar/4.txt -> /quarantine/4.txt",
+            "delete bar/5.txt",
+            "trash bar/6.txt",
+        ]
+        .into_iter()
+        .map(|s| s.to_owned())
+        .collect::<Vec<String>>();
+        let snap = textformat::parse(input).unwrap();
+
+        let output = render(&snap);
+        let json: serde_json::Value = serde_json::from_str(&output.join("\n")).unwrap();
+        assert_eq!("/foo", json["rootdir"]);
+        assert_eq!("2023-12-24T13:07:06+05:30", json["generated_at"]);
+        assert_eq!("blake3", json["verify_hash"]);
+        // Checksum algorithm defaults to xxh3_64 for older snapshots
+        assert_eq!("xxh3_64", json["checksum_algorithm"]);
+        assert_eq!("hex", json["checksum_format"]);
+        let paths = &json["groups"][0]["paths"];
+        assert_eq!("0d01abc8533976f3", json["groups"][0]["checksum"]);
+        assert_eq!(
+            serde_json::json!({"path": "bar/2.txt", "op": "symlink", "source": "1.txt"}),
+            paths[1]
+        );
+        assert_eq!(
+            serde_json::json!({"path": "bar/4.txt", "op": "move", "dest": "/quarantine/4.txt"}),
+            paths[3]
+        );
+
+        let parsed = parse(output).unwrap();
+        assert_eq!(snap.rootdir, parsed.rootdir);
+        assert_eq!(snap.generated_at, parsed.generated_at);
+        assert_eq!(snap.verify_hash, parsed.verify_hash);
+        assert_eq!(snap.quick_mode, parsed.quick_mode);
+        assert!(snap.duplicates == parsed.duplicates);
+    }
+
+    #[test]
+    fn test_render_empty_files() {
+        let input = [
+            "#! Root Directory: /foo".to_owned(),
+            "#! Generated at: Sun, 24 Dec 2023 13:07:06 +0530".to_owned(),
+            "#! Checksum Format: hex".to_owned(),
+            "".to_owned(),
+            format!("[{}]", Checksum::empty()),
+            "keep 1.txt".to_owned(),
+            "delete 2.txt".to_owned(),
+            "".to_owned(),
+            "[0d01abc8533976f3]".to_owned(),
+            "keep 3.txt".to_owned(),
+            "delete 4.txt".to_owned(),
+        ];
+        let snap = textformat::parse(input.to_vec()).unwrap();
+        let output = render(&snap);
+        let json: serde_json::Value = serde_json::from_str(&output.join("\n")).unwrap();
+        let groups = json["groups"].as_array().unwrap();
+        let empty = groups
+            .iter()
+            .find(|g| g["checksum"] == Checksum::empty().to_string())
+            .unwrap();
+        assert_eq!(true, empty["empty_files"]);
+        let other = groups
+            .iter()
+            .find(|g| g["checksum"] == "0d01abc8533976f3")
+            .unwrap();
+        assert!(other.get("empty_files").is_none());
+
+        let parsed = parse(output).unwrap();
+        assert!(snap.duplicates == parsed.duplicates);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let parse_str = |s: &str| parse(vec![s.to_owned()]);
+        assert!(parse_str("{").is_err());
+        assert!(
+            parse_str(r#"{"rootdir": "/foo", "generated_at": "yesterday", "groups": []}"#).is_err()
+        );
+        assert!(parse_str(
+            r#"{"rootdir": "/foo", "generated_at": "2023-12-24T13:07:06+05:30",
+                "groups": [{"checksum": "1", "paths": [{"path": "1.txt", "op": "move"}]}]}"#
+        )
+        .is_err());
+    }
+}
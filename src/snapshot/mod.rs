@@ -1,18 +1,65 @@
+use crate::cache::HashCache;
 use crate::error::AppError;
 use crate::executor::Action;
-use crate::hash::Checksum;
-use crate::scanner::scan;
+use crate::fileutil;
+use crate::hash::{Checksum, ContentHash, VerificationHash};
+use crate::scanner::{scan_rootdirs, Baseline, ScanOptions, ScanStats, Verification};
 use chrono::{DateTime, FixedOffset, Local};
+use clap::ValueEnum;
 use size::Size;
-use std::collections::{HashMap, HashSet};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io;
 use std::path::{Path, PathBuf};
 
+pub mod checksumsformat;
+pub mod csvformat;
+pub mod diff;
+pub mod jsonformat;
 pub mod textformat;
 pub mod validation;
 
+/// Returns the path of the file as recorded in the snapshot i.e.
+/// relative to the `rootdir` if it's located under it, else absolute
+/// (which is the case for files under the extra rootdirs)
+///
+/// The fallback is deliberate and not an error, as the absolute paths
+/// get resolved against the extra rootdirs when the snapshot is
+/// parsed (see `resolve_entry_path`). A path that's under none of the
+/// rootdirs is rejected at that point.
+pub(crate) fn entry_path(path: &Path, rootdir: &Path) -> PathBuf {
+    fileutil::normalize_path(path, true, rootdir).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Resolves the path of a file recorded in the snapshot to an absolute
+/// path. A relative path is relative to the primary rootdir whereas an
+/// absolute path must be located under one of the `rootdirs`.
+pub(crate) fn resolve_entry_path(path: &Path, rootdirs: &[&Path]) -> Result<PathBuf, AppError> {
+    let mut result = fileutil::normalize_path(path, false, rootdirs[0]);
+    if path.is_absolute() {
+        for rootdir in &rootdirs[1..] {
+            if result.is_ok() {
+                break;
+            }
+            result = fileutil::normalize_path(path, false, rootdir);
+        }
+    }
+    result
+}
+
+/// Parses the snapshot from input lines in either of the text or JSON
+/// formats
+pub fn parse(str_lines: Vec<String>) -> Result<Snapshot, AppError> {
+    if jsonformat::is_json(&str_lines) {
+        jsonformat::parse(str_lines)
+    } else {
+        textformat::parse(str_lines)
+    }
+}
+
+/// Action to be taken on a path in the snapshot
 #[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
-enum FileOp {
+pub enum FileOp {
     Keep,
     Symlink {
         // The `PathBuf` may be absolute or relative. We should never
@@ -21,7 +68,18 @@ enum FileOp {
         // user has specified it in the input snapshot file.
         source: Option<PathBuf>,
     },
+    Hardlink {
+        // Similar to symlink, the `PathBuf` may be absolute or
+        // relative (to the parent of the target path)
+        source: Option<PathBuf>,
+    },
+    Move {
+        // Destination path of the file. If relative, it's considered
+        // relative to the rootdir.
+        dest: PathBuf,
+    },
     Delete,
+    Trash,
 }
 
 impl FileOp {
@@ -29,9 +87,16 @@ impl FileOp {
         match keyword {
             "keep" => Some(Self::Keep),
             "symlink" => Some(Self::Symlink {
-                source: extra.map(PathBuf::from),
+                source: extra.map(fileutil::decode_path),
+            }),
+            "hardlink" => Some(Self::Hardlink {
+                source: extra.map(fileutil::decode_path),
+            }),
+            "move" => extra.map(|d| Self::Move {
+                dest: fileutil::decode_path(d),
             }),
             "delete" => Some(Self::Delete),
+            "trash" => Some(Self::Trash),
             // @TODO: Throw an error here
             _ => None,
         }
@@ -41,11 +106,16 @@ impl FileOp {
         match self {
             Self::Keep => "keep",
             Self::Symlink { source: _ } => "symlink",
+            Self::Hardlink { source: _ } => "hardlink",
+            Self::Move { dest: _ } => "move",
             Self::Delete => "delete",
+            Self::Trash => "trash",
         }
     }
 }
 
+/// Absolute path of a file in a group of duplicates along with the
+/// op to be performed on it
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FilePath {
     path: PathBuf,
@@ -56,9 +126,13 @@ impl FilePath {
     fn new(path: PathBuf) -> FilePath {
         let op = if path.is_symlink() {
             // @NOTE: Here we're not handling the case where
-            // `canonicalize` returns an Err
+            // `read_link` returns an Err. The source is normalized so
+            // that it round-trips consistently through the snapshot.
             FileOp::Symlink {
-                source: path.read_link().ok(),
+                source: path
+                    .read_link()
+                    .ok()
+                    .map(|src| fileutil::normalize_link_source(&path, &src)),
             }
         } else {
             FileOp::Keep
@@ -66,6 +140,14 @@ impl FilePath {
         FilePath { path, op }
     }
 
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn op(&self) -> &FileOp {
+        &self.op
+    }
+
     fn size(&self) -> io::Result<u64> {
         let metadata = self.path.metadata()?;
         Ok(metadata.len())
@@ -90,42 +172,293 @@ fn find_keeper(filepaths: &[FilePath]) -> Option<&FilePath> {
         .and_then(|k| filepaths.iter().find(|fp| fp.path == k.path))
 }
 
+/// Rule for choosing the keeper of every group when generating the
+/// snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeeperRule {
+    ShortestPath,
+    LongestPath,
+    // Based on the modification time
+    Oldest,
+    Newest,
+    // Path with the least no. of components
+    Shallowest,
+}
+
+/// Op with which the duplicates other than the keeper get marked when
+/// generating the snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DefaultOp {
+    Keep,
+    Symlink,
+    Delete,
+}
+
+impl DefaultOp {
+    fn file_op(&self) -> FileOp {
+        match self {
+            Self::Keep => FileOp::Keep,
+            // Source is implicitly the keeper of the group
+            Self::Symlink => FileOp::Symlink { source: None },
+            Self::Delete => FileOp::Delete,
+        }
+    }
+}
+
+/// Chooses the keeper out of the filepaths marked as 'keep' as per
+/// the `prefer` dirs and the `rule`
+///
+/// A filepath under a preferred dir wins over the ones that aren't,
+/// with the dirs that come earlier in `prefer` taking precedence over
+/// the later ones. The `rule` is applied to the filepaths that are
+/// equally preferred. If no rule is specified, the keeper is found
+/// the same way as `find_keeper` does. Ties are broken by comparing
+/// the paths, so that the same path is chosen every time. Returns
+/// `None` if no filepath is marked as 'keep' e.g. when all of them
+/// are symlinks.
+fn choose_keeper<'a>(
+    filepaths: &'a [FilePath],
+    rule: Option<KeeperRule>,
+    prefer: &[PathBuf],
+) -> io::Result<Option<&'a FilePath>> {
+    if rule.is_none() && prefer.is_empty() {
+        return Ok(find_keeper(filepaths));
+    }
+    let mut candidates = Vec::with_capacity(filepaths.len());
+    for fp in filepaths.iter().filter(|fp| fp.op == FileOp::Keep) {
+        let mtime = match rule {
+            Some(KeeperRule::Oldest | KeeperRule::Newest) => Some(fp.path.metadata()?.modified()?),
+            _ => None,
+        };
+        let rank = prefer
+            .iter()
+            .position(|dir| fp.path.starts_with(dir))
+            .unwrap_or(prefer.len());
+        candidates.push((fp, rank, mtime));
+    }
+    candidates.sort_by(|(a, a_rank, a_mtime), (b, b_rank, b_mtime)| {
+        let ordering = match rule {
+            Some(KeeperRule::ShortestPath) => {
+                a.path.as_os_str().len().cmp(&b.path.as_os_str().len())
+            }
+            Some(KeeperRule::LongestPath) => {
+                b.path.as_os_str().len().cmp(&a.path.as_os_str().len())
+            }
+            Some(KeeperRule::Oldest) => a_mtime.cmp(b_mtime),
+            Some(KeeperRule::Newest) => b_mtime.cmp(a_mtime),
+            Some(KeeperRule::Shallowest) => a
+                .path
+                .components()
+                .count()
+                .cmp(&b.path.components().count()),
+            None => Ordering::Equal,
+        };
+        a_rank
+            .cmp(b_rank)
+            .then(ordering)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    Ok(candidates.first().map(|(fp, _, _)| *fp))
+}
+
+/// Marks all files in the group marked as 'keep', except the one at
+/// `keeper_path`, with the `op`. Files that are already symlinks
+/// retain their existing op.
+fn mark_group(filepaths: &mut [FilePath], keeper_path: &Path, op: DefaultOp) {
+    for fp in filepaths
+        .iter_mut()
+        .filter(|fp| fp.op == FileOp::Keep && fp.path != keeper_path)
+    {
+        fp.op = op.file_op();
+    }
+}
+
 /// Checks whether all filepaths in a duplicate group are marked for
-/// deletion
+/// deletion (or trashing)
 fn are_all_deletions(filepaths: &[FilePath]) -> bool {
     filepaths
         .iter()
-        .all(|filepath| filepath.op == FileOp::Delete)
+        .all(|filepath| filepath.op == FileOp::Delete || filepath.op == FileOp::Trash)
 }
 
 /// Returns if the group is already de-duped by checking whether there
-/// is only one path marked Keep and the rest marked Symlink
+/// is only one path marked Keep and the rest marked Symlink (or
+/// Hardlink)
 fn is_group_deduped(filepaths: &[FilePath]) -> bool {
     let mut num_keeps = 0;
     for filepath in filepaths {
         match filepath.op {
             FileOp::Keep => num_keeps += 1,
-            FileOp::Delete => return false,
-            FileOp::Symlink { source: _ } => {}
+            FileOp::Delete | FileOp::Trash | FileOp::Move { dest: _ } => return false,
+            FileOp::Symlink { source: _ } | FileOp::Hardlink { source: _ } => {}
         }
     }
     num_keeps == 1
 }
 
+/// Returns if the group doesn't need any further action i.e. it's
+/// already de-duped, or there's at most one distinct file among the
+/// paths marked Keep (the others being hardlinks to it or missing)
+fn is_group_done(filepaths: &[FilePath]) -> bool {
+    if is_group_deduped(filepaths) {
+        return true;
+    }
+    let mut num_files = 0;
+    let mut file_ids = HashSet::new();
+    for filepath in filepaths.iter().filter(|fp| fp.op == FileOp::Keep) {
+        // Missing files are ignored. Files whose id can't be
+        // determined are counted as distinct.
+        match fileutil::file_id(&filepath.path) {
+            Ok(Some(id)) if !file_ids.insert(id) => {}
+            Ok(_) => num_files += 1,
+            Err(_) => {}
+        }
+    }
+    num_files <= 1
+}
+
+/// Returns the space (in bytes) that can be freed up by deduplicating
+/// the files in the group marked as 'keep'
+fn group_freeable_space(filepaths: &[FilePath]) -> io::Result<u64> {
+    // Paths that are hardlinks to the same file share the content on
+    // disk, hence they are counted only once. Files whose id can't be
+    // determined are counted as distinct.
+    let mut num_files = 0_u64;
+    let mut file_ids = HashSet::new();
+    for fp in filepaths.iter().filter(|fp| fp.op == FileOp::Keep) {
+        match fileutil::file_id(&fp.path)? {
+            Some(id) if !file_ids.insert(id) => {}
+            _ => num_files += 1,
+        }
+    }
+    match find_keeper(filepaths) {
+        Some(keeper) => Ok(keeper.size()? * num_files.saturating_sub(1)),
+        None => Ok(0),
+    }
+}
+
 pub struct Snapshot {
     pub rootdir: PathBuf,
+    // Rootdirs other than `rootdir` in case multiple of them were
+    // scanned together. Paths under these are recorded as absolute
+    // paths in the snapshot.
+    pub extra_rootdirs: Vec<PathBuf>,
     generated_at: DateTime<FixedOffset>,
+    // Algorithm used for confirming the duplicates. It will be `None`
+    // if the snapshot was generated in quick mode, by comparing bytes
+    // or by an older version of the tool
+    verify_hash: Option<VerificationHash>,
+    // Algorithm used for computing the checksums of the groups.
+    // Snapshots generated by older versions of the tool don't record
+    // it, in which case it's assumed to be `Checksum::ALGORITHM`
+    checksum_algo: String,
+    // Whether the duplicates were found in quick mode, in which case
+    // they are only known to have the same xxh3 checksum
+    quick_mode: bool,
+    // Which contents of the files were considered for computing the
+    // checksums. Snapshots generated by older versions of the tool
+    // don't record it, in which case it's assumed to be raw
+    content_hash: ContentHash,
     duplicates: HashMap<Checksum, Vec<FilePath>>,
+    // Groups in which all files are allowed to be deleted, even if
+    // `--allow-full-deletion` is not specified
+    full_deletion_groups: HashSet<Checksum>,
+    // Size of the files in the groups at the time of generation,
+    // which is recorded so that the checksums can be reused (see
+    // `baseline`). Groups of files not hashed raw don't have it, as
+    // their sizes may differ.
+    file_sizes: HashMap<Checksum, u64>,
+    // Metadata fields not recognized by the tool (e.g. added by
+    // other tools or by the user), which are retained as it is so
+    // that they aren't lost when the snapshot is rendered again
+    custom_metadata: BTreeMap<String, String>,
 }
 
+/// No. of hex chars in the group id
+const GROUP_ID_LEN: usize = 12;
+
 impl Snapshot {
+    /// Returns the groups of duplicates keyed by their checksums
+    pub fn duplicates(&self) -> &HashMap<Checksum, Vec<FilePath>> {
+        &self.duplicates
+    }
+
+    pub fn generated_at(&self) -> &DateTime<FixedOffset> {
+        &self.generated_at
+    }
+
+    /// Returns a short identifier for the group of duplicates having
+    /// the `checksum`
+    ///
+    /// It's the first 12 chars of the (zero padded) hex form of the
+    /// checksum. As the checksum is derived from the contents of the
+    /// files, the id remains the same across scans and can be used
+    /// for referring to the group from other tools. The subgroup, if
+    /// any, is appended to it.
+    pub fn group_id(checksum: &Checksum) -> String {
+        let hex = format!("{:016x}", checksum.value());
+        match checksum.subgroup() {
+            0 => hex[..GROUP_ID_LEN].to_owned(),
+            n => format!("{}-{}", &hex[..GROUP_ID_LEN], n),
+        }
+    }
+
+    /// Returns all the rootdirs of the snapshot, starting with the
+    /// primary one
+    pub fn rootdirs(&self) -> Vec<&Path> {
+        let mut rootdirs = vec![self.rootdir.as_path()];
+        rootdirs.extend(self.extra_rootdirs.iter().map(|d| d.as_path()));
+        rootdirs
+    }
+
+    /// Returns the dir relative to which the paths are displayed and
+    /// backed up when executing the actions
+    ///
+    /// It's the rootdir itself unless there are multiple rootdirs, in
+    /// which case it's their common ancestor. Note that the common
+    /// ancestor may be as high up as `/` (e.g. for rootdirs on
+    /// different mount points), in which case the backup dir mirrors
+    /// the absolute paths of the files and `undo` must be run with
+    /// the same ancestor as the rootdir.
+    pub fn base_dir(&self) -> PathBuf {
+        fileutil::common_ancestor(&self.rootdirs())
+    }
+
+    /// Scans the rootdir and generates the snapshot of the duplicates
+    /// found, returning it along with the stats collected while
+    /// scanning
     pub fn of_rootdir(
         rootdir: &Path,
-        excludes: Option<&HashSet<PathBuf>>,
-        quick: &bool,
+        opts: &ScanOptions,
+        skip_deduped: &bool,
+        skip_done: &bool,
+        cache: Option<&HashCache>,
+    ) -> io::Result<(Snapshot, ScanStats)> {
+        Self::of_rootdirs(
+            &[rootdir.to_path_buf()],
+            opts,
+            skip_deduped,
+            skip_done,
+            cache,
+        )
+    }
+
+    /// Same as `of_rootdir` but scans multiple rootdirs together, so
+    /// that duplicates across them are found. The first one is
+    /// considered as the primary rootdir.
+    pub fn of_rootdirs(
+        rootdirs: &[PathBuf],
+        opts: &ScanOptions,
         skip_deduped: &bool,
-    ) -> io::Result<Snapshot> {
-        let duplicates = scan(rootdir, excludes, quick)?
+        skip_done: &bool,
+        cache: Option<&HashCache>,
+    ) -> io::Result<(Snapshot, ScanStats)> {
+        // The snapshot is considered to be generated when the scan
+        // starts, so that the files modified while it's in progress
+        // are found to be stale during validation
+        let generated_at = Local::now().fixed_offset();
+        let (duplicates, mut stats) = scan_rootdirs(rootdirs, opts, cache)?;
+        let duplicates = duplicates
             .into_iter()
             .map(|(checksum, paths)| {
                 (
@@ -136,35 +469,199 @@ impl Snapshot {
                         .collect::<Vec<FilePath>>(),
                 )
             })
+            .filter(|(_, group)| group.len() >= opts.min_copies)
+            .collect::<HashMap<Checksum, Vec<FilePath>>>();
+        let num_groups = duplicates.len();
+        let duplicates = duplicates
+            .into_iter()
             .filter(|(_, group)| !(*skip_deduped && is_group_deduped(group)))
+            .filter(|(_, group)| !(*skip_done && is_group_done(group)))
             .collect::<HashMap<Checksum, Vec<FilePath>>>();
+        stats.groups_hidden = num_groups - duplicates.len();
+        let file_sizes = duplicates
+            .iter()
+            .filter(|(_, group)| !opts.content_hash.applies_to(&group[0].path))
+            .filter_map(|(checksum, group)| {
+                let size = group[0].path.metadata().ok()?.len();
+                Some((checksum.clone(), size))
+            })
+            .collect::<HashMap<Checksum, u64>>();
         let snap = Snapshot {
-            rootdir: rootdir.to_path_buf(),
-            generated_at: Local::now().fixed_offset(),
+            rootdir: rootdirs[0].to_path_buf(),
+            extra_rootdirs: rootdirs[1..].to_vec(),
+            generated_at,
+            verify_hash: match opts.verification {
+                Verification::Hash(vh) if !opts.quick => Some(vh),
+                _ => None,
+            },
+            checksum_algo: Checksum::ALGORITHM.to_owned(),
+            quick_mode: opts.quick,
+            content_hash: opts.content_hash,
             duplicates,
+            full_deletion_groups: HashSet::new(),
+            file_sizes,
+            custom_metadata: BTreeMap::new(),
         };
-        Ok(snap)
+        Ok((snap, stats))
     }
 
-    pub fn validate(&self, is_full_deletion_allowed: &bool) -> Result<Vec<Action>, AppError> {
-        validation::validate(self, is_full_deletion_allowed).map_err(AppError::SnapshotValidation)
+    /// Marks the keeper of every group, chosen as per the `prefer`
+    /// dirs and the `rule`, as 'keep' and the rest of the files with
+    /// the `op`
+    ///
+    /// The `prefer` dirs may be relative to the rootdir. Files that
+    /// are already symlinks retain their existing op.
+    pub fn mark_duplicates(
+        &mut self,
+        rule: Option<KeeperRule>,
+        prefer: &[PathBuf],
+        op: DefaultOp,
+    ) -> io::Result<()> {
+        let prefer = prefer
+            .iter()
+            .map(|dir| fileutil::lexical_normalize(&self.rootdir.join(dir)))
+            .collect::<Vec<PathBuf>>();
+        for filepaths in self.duplicates.values_mut() {
+            let keeper_path = match choose_keeper(filepaths, rule, &prefer)? {
+                Some(fp) => fp.path.clone(),
+                None => continue,
+            };
+            mark_group(filepaths, &keeper_path, op);
+        }
+        Ok(())
+    }
+
+    /// Returns checksum, freeable space (in bytes) and the paths
+    /// marked as 'keep' (i.e. the candidates for keeper) of every
+    /// group, in the same order in which the groups are rendered
+    pub fn keeper_candidates(&self) -> io::Result<Vec<(Checksum, u64, Vec<PathBuf>)>> {
+        let mut result = Vec::with_capacity(self.duplicates.len());
+        for (checksum, filepaths) in textformat::sorted_groups(&self.duplicates) {
+            let paths = filepaths
+                .iter()
+                .filter(|fp| fp.op == FileOp::Keep)
+                .map(|fp| fp.path.clone())
+                .collect();
+            let space = group_freeable_space(filepaths)?;
+            result.push((checksum.clone(), space, paths));
+        }
+        Ok(result)
+    }
+
+    /// Marks the file at `keeper_path` as 'keep' and the rest of the
+    /// files in the group identified by `checksum` with the `op`
+    pub fn resolve_group(&mut self, checksum: &Checksum, keeper_path: &Path, op: DefaultOp) {
+        if let Some(filepaths) = self.duplicates.get_mut(checksum) {
+            mark_group(filepaths, keeper_path, op);
+        }
+    }
+
+    pub fn validate(
+        &self,
+        is_full_deletion_allowed: &bool,
+        is_mtime_ignored: &bool,
+    ) -> Result<Vec<Action<'_>>, AppError> {
+        validation::validate(self, is_full_deletion_allowed, is_mtime_ignored)
+            .map_err(AppError::SnapshotValidation)
+    }
+
+    /// Validates the snapshot, skipping the groups that fail
+    /// validation. See `validation::validate_skipping_invalid`.
+    pub fn validate_skipping_invalid(
+        &self,
+        is_full_deletion_allowed: &bool,
+        is_mtime_ignored: &bool,
+    ) -> Result<(Vec<Action<'_>>, validation::SkippedGroups<'_>), AppError> {
+        validation::validate_skipping_invalid(self, is_full_deletion_allowed, is_mtime_ignored)
+            .map_err(AppError::SnapshotValidation)
+    }
+
+    /// Retains only the groups in which at least one of the paths
+    /// contains any of the `substrs`, returning the no. of groups
+    /// that are left out
+    ///
+    /// As the groups are retained entirely, the validation of the
+    /// filtered snapshot still ensures that every group has a keeper.
+    pub fn retain_groups_matching(&mut self, substrs: &[String]) -> usize {
+        let num_groups = self.duplicates.len();
+        self.duplicates.retain(|_, filepaths| {
+            filepaths.iter().any(|fp| {
+                let path = fp.path.to_string_lossy();
+                substrs.iter().any(|s| path.contains(s.as_str()))
+            })
+        });
+        let duplicates = &self.duplicates;
+        self.full_deletion_groups
+            .retain(|checksum| duplicates.contains_key(checksum));
+        num_groups - self.duplicates.len()
+    }
+
+    /// Verifies every path in the snapshot, returning all the problems
+    /// found. See `validation::verify`.
+    pub fn verify(
+        &self,
+        is_full_deletion_allowed: &bool,
+        is_mtime_ignored: &bool,
+    ) -> Result<validation::Problems<'_>, AppError> {
+        validation::verify(self, is_full_deletion_allowed, is_mtime_ignored)
+            .map_err(AppError::SnapshotValidation)
+    }
+
+    /// Returns checksums (along with the sizes) of all the paths in
+    /// the snapshot that can be used as baseline for scanning the
+    /// rootdir again
+    ///
+    /// Groups whose file size is not recorded (e.g. in snapshots
+    /// generated by older versions of the tool) are left out, as the
+    /// files can't be checked for changes reliably without it.
+    pub fn baseline(&self) -> Baseline {
+        let entries = self
+            .duplicates
+            .iter()
+            .filter_map(|(checksum, filepaths)| {
+                let size = *self.file_sizes.get(checksum)?;
+                Some((checksum, filepaths, size))
+            })
+            .flat_map(|(checksum, filepaths, size)| {
+                filepaths
+                    .iter()
+                    // Checksums of the files not hashed raw can't be
+                    // reused as the content hash mode may differ
+                    .filter(|fp| !self.content_hash.applies_to(&fp.path))
+                    .map(move |fp| (fp.path.clone(), (checksum.value(), size)))
+            })
+            .collect::<HashMap<PathBuf, (u64, u64)>>();
+        Baseline::new(self.generated_at.into(), entries)
     }
 
     pub fn freeable_space(&self) -> io::Result<Size> {
         let mut total = 0_u64;
         for filepaths in self.duplicates.values() {
-            let num_keep = filepaths.iter().filter(|fp| fp.op == FileOp::Keep).count();
-            if let Some(keeper) = find_keeper(filepaths) {
-                total += keeper.size()? * (num_keep - 1) as u64;
-            }
+            total += group_freeable_space(filepaths)?;
         }
         Ok(Size::from_bytes(total))
     }
+
+    /// Returns checksum, no. of paths and freeable space (in bytes)
+    /// of every group, in descending order of the freeable space
+    pub fn freeable_space_by_group(&self) -> io::Result<Vec<(&Checksum, usize, u64)>> {
+        let mut result = Vec::with_capacity(self.duplicates.len());
+        for (checksum, filepaths) in self.duplicates.iter() {
+            let space = group_freeable_space(filepaths)?;
+            result.push((checksum, filepaths.len(), space));
+        }
+        result.sort_by_key(|(_, _, space)| Reverse(*space));
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serial_test::serial;
+    use std::fs;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
 
     #[test]
     fn test_find_keeper() {
@@ -245,4 +742,433 @@ mod tests {
         }];
         assert!(is_group_deduped(&g));
     }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_freeable_space_hardlinks() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::write(data_dir.join("a.txt"), "ONE").unwrap();
+        fs::hard_link(data_dir.join("a.txt"), data_dir.join("b.txt")).unwrap();
+        fs::write(data_dir.join("c.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("d.txt"), "ONE").unwrap();
+
+        let opts = ScanOptions {
+            keep_hardlinks: true,
+            ..Default::default()
+        };
+        let (snap, _) = Snapshot::of_rootdir(&data_dir, &opts, &false, &false, None).unwrap();
+        // Out of the 4 paths, 3 are distinct files of which 2 can be
+        // deleted
+        assert_eq!(Size::from_bytes(6), snap.freeable_space().unwrap());
+        let by_group = snap.freeable_space_by_group().unwrap();
+        assert_eq!(1, by_group.len());
+        assert_eq!((4, 6), (by_group[0].1, by_group[0].2));
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_is_group_done() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::write(data_dir.join("a.txt"), "ONE").unwrap();
+        fs::hard_link(data_dir.join("a.txt"), data_dir.join("b.txt")).unwrap();
+        fs::write(data_dir.join("c.txt"), "ONE").unwrap();
+        let fp = |p: &str, op: FileOp| FilePath {
+            path: data_dir.join(p),
+            op,
+        };
+        let symlink = || FileOp::Symlink { source: None };
+
+        // Already de-duped
+        assert!(is_group_done(&[
+            fp("a.txt", FileOp::Keep),
+            fp("d.txt", symlink())
+        ]));
+        // Hardlinks to the same file
+        assert!(is_group_done(&[
+            fp("a.txt", FileOp::Keep),
+            fp("b.txt", FileOp::Keep),
+            fp("d.txt", symlink()),
+        ]));
+        // Missing file
+        assert!(is_group_done(&[
+            fp("a.txt", FileOp::Keep),
+            fp("e.txt", FileOp::Keep)
+        ]));
+        // Distinct files
+        assert!(!is_group_done(&[
+            fp("a.txt", FileOp::Keep),
+            fp("b.txt", FileOp::Keep),
+            fp("c.txt", FileOp::Keep),
+        ]));
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_mark_duplicates() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::create_dir_all(data_dir.join("foo/bar")).unwrap();
+        fs::write(data_dir.join("foo/bar/a.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("foo/bb.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("c.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("dddd.txt"), "ONE").unwrap();
+        fileutil::symlink(&data_dir.join("c.txt"), &data_dir.join("e.txt")).unwrap();
+
+        let ops = |rule: Option<KeeperRule>, op: DefaultOp| {
+            let (mut snap, _) =
+                Snapshot::of_rootdir(&data_dir, &ScanOptions::default(), &false, &false, None)
+                    .unwrap();
+            snap.mark_duplicates(rule, &[], op).unwrap();
+            let mut fps = snap.duplicates.into_values().next().unwrap();
+            fps.sort_by(|a, b| a.path.cmp(&b.path));
+            fps.into_iter()
+                .map(|fp| fp.op.keyword().to_owned())
+                .collect::<Vec<String>>()
+        };
+
+        // Paths in sorted order: c.txt, dddd.txt, e.txt, foo/bar/a.txt,
+        // foo/bb.txt
+        assert_eq!(
+            vec!["keep", "symlink", "symlink", "symlink", "symlink"],
+            ops(Some(KeeperRule::ShortestPath), DefaultOp::Symlink)
+        );
+        // Without a rule, the lexicographically first path is kept
+        assert_eq!(
+            vec!["keep", "delete", "symlink", "delete", "delete"],
+            ops(None, DefaultOp::Delete)
+        );
+        assert_eq!(
+            vec!["keep", "keep", "symlink", "keep", "keep"],
+            ops(None, DefaultOp::Keep)
+        );
+        assert_eq!(
+            vec!["delete", "delete", "symlink", "keep", "delete"],
+            ops(Some(KeeperRule::LongestPath), DefaultOp::Delete)
+        );
+        assert_eq!(
+            vec!["keep", "delete", "symlink", "delete", "delete"],
+            ops(Some(KeeperRule::Shallowest), DefaultOp::Delete)
+        );
+
+        let mtime = |p: &str, secs: u64| {
+            let f = fs::File::options()
+                .write(true)
+                .open(data_dir.join(p))
+                .unwrap();
+            f.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+                .unwrap();
+        };
+        mtime("c.txt", 3000);
+        mtime("dddd.txt", 1000);
+        mtime("foo/bar/a.txt", 4000);
+        mtime("foo/bb.txt", 2000);
+        assert_eq!(
+            vec!["symlink", "keep", "symlink", "symlink", "symlink"],
+            ops(Some(KeeperRule::Oldest), DefaultOp::Symlink)
+        );
+        assert_eq!(
+            vec!["symlink", "symlink", "symlink", "keep", "symlink"],
+            ops(Some(KeeperRule::Newest), DefaultOp::Symlink)
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_resolve_group() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::write(data_dir.join("a.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("b.txt"), "ONE").unwrap();
+        fs::write(data_dir.join("c.txt"), "ONE").unwrap();
+        fileutil::symlink(&data_dir.join("a.txt"), &data_dir.join("d.txt")).unwrap();
+
+        let (mut snap, _) =
+            Snapshot::of_rootdir(&data_dir, &ScanOptions::default(), &false, &false, None).unwrap();
+        let mut candidates = snap.keeper_candidates().unwrap();
+        assert_eq!(1, candidates.len());
+        let (checksum, space, mut paths) = candidates.pop().unwrap();
+        assert_eq!(6, space);
+        // Symlinks are not candidates for keeper
+        paths.sort();
+        assert_eq!(
+            vec![
+                data_dir.join("a.txt"),
+                data_dir.join("b.txt"),
+                data_dir.join("c.txt")
+            ],
+            paths
+        );
+
+        snap.resolve_group(&checksum, &data_dir.join("b.txt"), DefaultOp::Delete);
+        let mut fps = snap.duplicates.remove(&checksum).unwrap();
+        fps.sort_by(|a, b| a.path.cmp(&b.path));
+        let ops = fps.iter().map(|fp| fp.op.keyword()).collect::<Vec<&str>>();
+        assert_eq!(vec!["delete", "keep", "delete", "symlink"], ops);
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_of_rootdir_min_copies() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        for p in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(data_dir.join(p), "ONE").unwrap();
+        }
+        for p in ["d.txt", "e.txt"] {
+            fs::write(data_dir.join(p), "TWO").unwrap();
+        }
+
+        let (snap, _) =
+            Snapshot::of_rootdir(&data_dir, &ScanOptions::default(), &false, &false, None).unwrap();
+        assert_eq!(2, snap.duplicates.len());
+
+        let opts = ScanOptions {
+            min_copies: 3,
+            ..Default::default()
+        };
+        let (snap, _) = Snapshot::of_rootdir(&data_dir, &opts, &false, &false, None).unwrap();
+        assert_eq!(1, snap.duplicates.len());
+        assert_eq!(3, snap.duplicates.values().next().unwrap().len());
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    fn test_group_id() {
+        assert_eq!(
+            "0d01a2b3c4d5",
+            Snapshot::group_id(&Checksum::new(0x0d01a2b3c4d5e6f7))
+        );
+        assert_eq!("000000000000", Snapshot::group_id(&Checksum::new(42)));
+        assert_eq!(
+            Snapshot::group_id(&Checksum::new(937219074347857651)),
+            Snapshot::group_id(&Checksum::new(937219074347857651))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_mark_duplicates_prefer() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        for d in ["copies", "originals/old", "backups"] {
+            fs::create_dir_all(data_dir.join(d)).unwrap();
+        }
+        for p in [
+            "a.txt",
+            "copies/a.txt",
+            "originals/old/aaaa.txt",
+            "originals/old/a.txt",
+            "backups/a.txt",
+        ] {
+            fs::write(data_dir.join(p), "ONE").unwrap();
+        }
+
+        let keeper = |rule: Option<KeeperRule>, prefer: &[&str]| {
+            let (mut snap, _) =
+                Snapshot::of_rootdir(&data_dir, &ScanOptions::default(), &false, &false, None)
+                    .unwrap();
+            let prefer = prefer.iter().map(PathBuf::from).collect::<Vec<PathBuf>>();
+            snap.mark_duplicates(rule, &prefer, DefaultOp::Delete)
+                .unwrap();
+            let fps = snap.duplicates.into_values().next().unwrap();
+            let keepers = fps
+                .into_iter()
+                .filter(|fp| fp.op == FileOp::Keep)
+                .map(|fp| fp.path)
+                .collect::<Vec<PathBuf>>();
+            assert_eq!(1, keepers.len());
+            keepers[0].strip_prefix(&data_dir).unwrap().to_path_buf()
+        };
+
+        assert_eq!(PathBuf::from("a.txt"), keeper(None, &[]));
+        // Ties are broken by the rule or else by the path
+        assert_eq!(
+            PathBuf::from("originals/old/a.txt"),
+            keeper(None, &["originals"])
+        );
+        assert_eq!(
+            PathBuf::from("originals/old/aaaa.txt"),
+            keeper(Some(KeeperRule::LongestPath), &["originals/"])
+        );
+        // Earlier dirs take precedence
+        assert_eq!(
+            PathBuf::from("backups/a.txt"),
+            keeper(None, &["backups", "originals"])
+        );
+        assert_eq!(
+            PathBuf::from("originals/old/a.txt"),
+            keeper(None, &["nonexistent", "originals", "backups"])
+        );
+        // Absolute paths
+        assert_eq!(
+            PathBuf::from("copies/a.txt"),
+            keeper(None, &[data_dir.join("copies").to_str().unwrap()])
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_filepath_new_symlink_source() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        fs::create_dir_all(data_dir.join("bar")).unwrap();
+        fs::write(data_dir.join("a.txt"), "ONE").unwrap();
+        let links = [
+            (
+                "bar/1.txt",
+                PathBuf::from("../a.txt"),
+                PathBuf::from("../a.txt"),
+            ),
+            (
+                "bar/2.txt",
+                PathBuf::from("./../bar/../a.txt"),
+                PathBuf::from("../a.txt"),
+            ),
+            ("3.txt", PathBuf::from("./a.txt"), PathBuf::from("a.txt")),
+            (
+                "bar/4.txt",
+                data_dir.join("bar/../a.txt"),
+                data_dir.join("a.txt"),
+            ),
+        ];
+        for (link, source, expected) in links.iter() {
+            let path = data_dir.join(link);
+            fileutil::symlink(source, &path).unwrap();
+            let fp = FilePath::new(path);
+            assert_eq!(
+                FileOp::Symlink {
+                    source: Some(expected.clone())
+                },
+                fp.op
+            );
+        }
+
+        // The sources round-trip through the text format and the
+        // symlinks are found to be no-ops on validation
+        let (snap, _) =
+            Snapshot::of_rootdir(&data_dir, &ScanOptions::default(), &false, &false, None).unwrap();
+        let snap = textformat::parse(textformat::render(&snap)).unwrap();
+        let actions = snap.validate(&false, &false).unwrap();
+        assert_eq!(5, actions.len());
+        assert!(actions
+            .iter()
+            .all(|a| matches!(a, Action::Keep(_) | Action::Symlink { is_no_op: true, .. })));
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_of_rootdirs() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let rootdirs = vec![data_dir.join("lib1"), data_dir.join("lib2")];
+        for d in rootdirs.iter() {
+            fs::create_dir_all(d.join("foo")).unwrap();
+            fs::write(d.join("foo/1.txt"), "ONE").unwrap();
+        }
+
+        let (mut snap, _) =
+            Snapshot::of_rootdirs(&rootdirs, &ScanOptions::default(), &false, &false, None)
+                .unwrap();
+        assert_eq!(rootdirs[0], snap.rootdir);
+        assert_eq!(rootdirs[1..], snap.extra_rootdirs);
+        assert_eq!(data_dir, snap.base_dir());
+        snap.mark_duplicates(None, &[], DefaultOp::Delete).unwrap();
+
+        // Paths under the extra rootdir are rendered as absolute paths
+        let lines = textformat::render(&snap);
+        let roots = lines
+            .iter()
+            .filter(|l| l.starts_with("#! Root Directory"))
+            .count();
+        assert_eq!(2, roots);
+        assert!(lines.contains(&"keep foo/1.txt".to_owned()));
+        assert!(lines.contains(&format!(
+            "delete {}",
+            rootdirs[1].join("foo/1.txt").display()
+        )));
+
+        for parsed in [
+            textformat::parse(lines).unwrap(),
+            jsonformat::parse(jsonformat::render(&snap)).unwrap(),
+        ] {
+            assert_eq!(snap.rootdirs(), parsed.rootdirs());
+            let actions = parsed.validate(&false, &false).unwrap();
+            assert_eq!(2, actions.len());
+            assert!(actions.iter().any(
+                |a| matches!(a, Action::Delete { path, .. } if path.starts_with(&rootdirs[1]))
+            ));
+        }
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+
+    #[test]
+    fn test_retain_groups_matching() {
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "#! Allow Full Deletion: true",
+            "[1]",
+            "delete photos/1.jpg",
+            "delete backup/1.jpg",
+            "",
+            "[2]",
+            "keep music/2.mp3",
+            "delete backup/2.mp3",
+            "",
+            "[3]",
+            "keep docs/3.txt",
+            "delete docs/old/3.txt",
+        ];
+        let parse = || textformat::parse(input.iter().map(|s| s.to_string()).collect()).unwrap();
+
+        let mut snap = parse();
+        assert_eq!(1, snap.retain_groups_matching(&["backup/".to_owned()]));
+        let mut checksums = snap
+            .duplicates()
+            .keys()
+            .map(|c| c.value())
+            .collect::<Vec<u64>>();
+        checksums.sort();
+        assert_eq!(vec![1, 2], checksums);
+        assert_eq!(1, snap.full_deletion_groups.len());
+
+        let mut snap = parse();
+        let substrs = ["music".to_owned(), "docs/old".to_owned()];
+        assert_eq!(1, snap.retain_groups_matching(&substrs));
+        assert!(snap.full_deletion_groups.is_empty());
+
+        let mut snap = parse();
+        assert_eq!(3, snap.retain_groups_matching(&["videos".to_owned()]));
+        assert!(snap.duplicates().is_empty());
+    }
 }
@@ -1,10 +1,12 @@
-use super::{find_keeper, FileOp, FilePath, Snapshot};
+use super::{entry_path, find_keeper, resolve_entry_path, FileOp, FilePath, Snapshot};
 use crate::error::AppError;
-use crate::fileutil::normalize_path;
-use crate::hash::Checksum;
-use chrono::{DateTime, FixedOffset};
+use crate::fileutil::{decode_path, encode_path};
+use crate::hash::{Checksum, ChecksumFormat, ContentHash, VerificationHash};
+use chrono::{DateTime, FixedOffset, Local};
 use regex::Regex;
-use std::collections::HashMap;
+use size::Size;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Eq, PartialEq)]
@@ -94,7 +96,12 @@ impl Line {
                 Ok(Self::Checksum(hash))
             }
             Some(_) => {
-                let re = Regex::new(r"^(keep|symlink|delete)\s(.+)$").unwrap();
+                // Trailing comment (if any) is ignored
+                let cleaned = match cleaned.split_once(ANNOTATION_DELIM) {
+                    Some((line, _)) => line.trim_end(),
+                    None => cleaned,
+                };
+                let re = Regex::new(r"^(keep|symlink|hardlink|move|delete|trash)\s(.+)$").unwrap();
                 let caps = re.captures(cleaned).ok_or(AppError::SnapshotParsing)?;
                 let op = caps
                     .get(1)
@@ -106,7 +113,7 @@ impl Line {
                     .ok_or(AppError::SnapshotParsing)?
                     .as_str()
                     .to_owned();
-                if op == "symlink" {
+                if op == "symlink" || op == "hardlink" || op == "move" {
                     let parts: Vec<&str> = path
                         .split("->")
                         .map(|s| s.trim())
@@ -121,7 +128,9 @@ impl Line {
                             delim: Some(String::from("->")),
                             extra: Some(src),
                         })
-                    } else if parts.len() == 1 {
+                    } else if parts.len() == 1 && op != "move" {
+                        // Note that the destination path is mandatory
+                        // in case of move
                         let target = String::from(parts[0]);
                         Ok(Self::PathInfo {
                             op,
@@ -149,19 +158,14 @@ impl Line {
     // `FilePath` instance
     fn pathinfo(filepath: &FilePath, rootdir: &Path) -> Self {
         // The `path` field in `Self::PathInfo` must be a relative
-        // path, so we first compute that using the rootdir
-        let path = normalize_path(&filepath.path, true, rootdir)
-            // assuming that `rootdir` is an ancestor of the path
-            .unwrap()
-            .to_str()
-            // assuming that path is a valid unicode
-            .unwrap()
-            .to_owned();
+        // path (unless it's under one of the extra rootdirs), so we
+        // first compute that using the rootdir
+        let path = encode_path(&entry_path(&filepath.path, rootdir));
         let op = filepath.op.keyword().to_owned();
         match &filepath.op {
-            FileOp::Symlink { source } => {
+            FileOp::Symlink { source } | FileOp::Hardlink { source } => {
                 let delim = Some(String::from("->"));
-                let extra = source.as_ref().map(|s| s.display().to_string());
+                let extra = source.as_deref().map(encode_path);
                 Line::PathInfo {
                     path,
                     op,
@@ -169,7 +173,13 @@ impl Line {
                     extra,
                 }
             }
-            FileOp::Keep | FileOp::Delete => Line::PathInfo {
+            FileOp::Move { dest } => Line::PathInfo {
+                path,
+                op,
+                delim: Some(String::from("->")),
+                extra: Some(encode_path(dest)),
+            },
+            FileOp::Keep | FileOp::Delete | FileOp::Trash => Line::PathInfo {
                 path,
                 op,
                 delim: None,
@@ -179,10 +189,17 @@ impl Line {
     }
 }
 
-/// Sort entries in the duplicate groups hashmap by size
+/// Renders the path along with its op in the same way as it appears
+/// in the text snapshot e.g. `symlink foo/1.txt -> ../1.txt`
+pub(super) fn render_filepath(filepath: &FilePath, rootdir: &Path) -> String {
+    Line::pathinfo(filepath, rootdir).encode()
+}
+
+/// Sort entries in the duplicate groups hashmap by size (descending)
+/// and then by checksum
 ///
 /// Note that it returns a vector of tuples
-fn sorted_groups(
+pub(super) fn sorted_groups(
     duplicates: &HashMap<Checksum, Vec<FilePath>>,
 ) -> Vec<(&Checksum, &Vec<FilePath>)> {
     let mut dups = duplicates
@@ -192,29 +209,61 @@ fn sorted_groups(
             (x.0, x.1, size)
         })
         .collect::<Vec<(&Checksum, &Vec<FilePath>, u64)>>();
-    dups.sort_by(|a, b| b.2.cmp(&a.2));
+    // Ties are broken by the checksum so that groups of the same size
+    // are rendered in the same order across runs
+    dups.sort_by_key(|x| (Reverse(x.2), x.0));
     dups.iter()
         .map(|x| (x.0, x.1))
         .collect::<Vec<(&Checksum, &Vec<FilePath>)>>()
 }
 
-fn render_lines(snap: &Snapshot) -> Vec<Line> {
+/// Returns the space that can be reclaimed by keeping only one of the
+/// files in the group, if the size of the files can be determined
+fn reclaimable_space(filepaths: &[FilePath]) -> Option<u64> {
+    let size = find_keeper(filepaths)?.size().ok()?;
+    Some(size * (filepaths.len() as u64 - 1))
+}
+
+/// Separator between a path line and the trailing comment annotating
+/// it. As tabs in paths are escaped (see `fileutil::encode_path`),
+/// the tab can't be confused with a part of the path.
+const ANNOTATION_DELIM: &str = "\t#";
+
+/// Returns the annotation of the file i.e. its size and the date on
+/// which it was last modified, if its metadata can be read
+fn annotation(filepath: &FilePath) -> Option<String> {
+    let metadata = filepath.path.metadata().ok()?;
+    let mtime: DateTime<Local> = metadata.modified().ok()?.into();
+    Some(format!(
+        "{}, {}",
+        Size::from_bytes(metadata.len()),
+        mtime.format("%Y-%m-%d")
+    ))
+}
+
+/// Returns the lines of the snapshot along with the annotations of
+/// the path lines (keyed by their indices) if `annotate` is true
+fn render_lines(snap: &Snapshot, opts: &RenderOptions) -> (Vec<Line>, HashMap<usize, String>) {
+    let mut annotations: HashMap<usize, String> = HashMap::new();
+
     // When there are no duplicates, there is nothing to return. The
     // caller code may check for an empty return value and log a
     // user friendly message
     if snap.duplicates.is_empty() {
-        return vec![];
+        return (vec![], annotations);
     }
 
     // @TODO: Can we calculate the no. of lines roughly and initialize
     // a vector with that capacity?
     let mut lines: Vec<Line> = Vec::new();
 
-    // Add root dir as metadata
-    lines.push(Line::MetaData {
-        key: "Root Directory".to_string(),
-        val: snap.rootdir.display().to_string(),
-    });
+    // Add root dir(s) as metadata. The primary rootdir comes first
+    for rootdir in snap.rootdirs() {
+        lines.push(Line::MetaData {
+            key: "Root Directory".to_string(),
+            val: encode_path(rootdir),
+        });
+    }
 
     // Add time of generation as metadata
     lines.push(Line::MetaData {
@@ -222,26 +271,114 @@ fn render_lines(snap: &Snapshot) -> Vec<Line> {
         val: snap.generated_at.to_rfc2822(),
     });
 
+    // Add the algorithm used for computing the checksums as metadata
+    lines.push(Line::MetaData {
+        key: "Checksum Algorithm".to_string(),
+        val: snap.checksum_algo.clone(),
+    });
+
+    // Add the form in which the checksums are written as metadata
+    lines.push(Line::MetaData {
+        key: "Checksum Format".to_string(),
+        val: ChecksumFormat::Hex.name().to_string(),
+    });
+
+    // Add the algorithm used for confirming duplicates as metadata
+    if let Some(vh) = &snap.verify_hash {
+        lines.push(Line::MetaData {
+            key: "Verification Hash".to_string(),
+            val: vh.name().to_string(),
+        });
+    }
+
+    // Add whether the duplicates were found in quick mode as metadata
+    lines.push(Line::MetaData {
+        key: "Quick Mode".to_string(),
+        val: snap.quick_mode.to_string(),
+    });
+
+    // Add the content hash mode as metadata, unless it's the default
+    if snap.content_hash != ContentHash::Raw {
+        lines.push(Line::MetaData {
+            key: "Content Hash".to_string(),
+            val: snap.content_hash.name().to_string(),
+        });
+    }
+
+    // Add the metadata fields not recognized by the tool as it is
+    for (key, val) in &snap.custom_metadata {
+        lines.push(Line::MetaData {
+            key: key.to_owned(),
+            val: val.to_owned(),
+        });
+    }
+
     // Add a blank line before dumping the filepath groupings
     lines.push(Line::Blank);
 
-    for (ck, vs) in sorted_groups(&snap.duplicates) {
+    // Groups are numbered in the same (size-descending) order in
+    // which they are rendered
+    let num_groups = snap.duplicates.len();
+    for (i, (ck, vs)) in sorted_groups(&snap.duplicates).into_iter().enumerate() {
+        lines.push(Line::MetaData {
+            key: "Group".to_string(),
+            val: format!("{} of {}", i + 1, num_groups),
+        });
+        if let Some(space) = reclaimable_space(vs) {
+            lines.push(Line::MetaData {
+                key: "Reclaimable".to_string(),
+                val: Size::from_bytes(space).to_string(),
+            });
+        }
+        if let Some(size) = snap.file_sizes.get(ck) {
+            lines.push(Line::MetaData {
+                key: "File Size".to_string(),
+                val: size.to_string(),
+            });
+        }
+        if snap.full_deletion_groups.contains(ck) {
+            lines.push(Line::MetaData {
+                key: "Allow Full Deletion".to_string(),
+                val: "true".to_string(),
+            });
+        }
+        if *ck == Checksum::empty() {
+            lines.push(Line::Comment("Empty files (zero bytes)".to_string()));
+        }
         lines.push(Line::Checksum(format!("{}", ck)));
         for v in vs {
+            if let Some(a) = opts.annotate.then(|| annotation(v)).flatten() {
+                annotations.insert(lines.len(), a);
+            }
             lines.push(Line::pathinfo(v, &snap.rootdir));
         }
         lines.push(Line::Blank);
     }
 
+    if !opts.help {
+        // The blank line after the last group is only needed for
+        // separating it from the help section
+        lines.pop();
+        return (lines, annotations);
+    }
+
     let help = vec![
         "Reference:",
         "keep <target> = keep the target path as it is",
         "delete <target> = delete the target path",
+        "trash <target> = move the target path to the system trash",
         "symlink <target> [-> <src>] = Replace target with a symlink",
         ".       If 'src' is specified, it can either be an absolute or",
         ".       relative (to 'target'). Else one of the duplicates marked",
         ".       as 'keep' will be considered. If 'src' is not specified,",
         ".       a relative symlink will be created.",
+        "hardlink <target> [-> <src>] = Replace target with a hardlink",
+        ".       'src' is interpreted the same way as for symlink. Both",
+        ".       the paths must be on the same filesystem.",
+        "move <target> -> <dest> = Move target to the dest path, which",
+        ".       can either be absolute or relative (to the root dir)",
+        "#! Allow Full Deletion: true = Allow all files in the group",
+        ".       to be deleted, if added right before its checksum",
         "",
         "This section is a comment and will be ignored by the tool",
     ];
@@ -250,39 +387,123 @@ fn render_lines(snap: &Snapshot) -> Vec<Line> {
         lines.push(Line::Comment(help_line.to_string()));
     }
 
-    lines
+    (lines, annotations)
 }
 
-pub fn render(snap: &Snapshot) -> Vec<String> {
-    let lines = render_lines(snap);
+/// Options for rendering the snapshot in text format
+pub struct RenderOptions {
+    /// Whether to follow every path line with a trailing comment
+    /// containing the size and the modification date of the file
+    /// e.g. `keep foo/1.txt\t# 12.3 MiB, 2023-05-01`. Such comments
+    /// are ignored when parsing the snapshot.
+    pub annotate: bool,
+    /// Whether to include the reference of the ops as comments at the
+    /// end
+    pub help: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            annotate: false,
+            help: true,
+        }
+    }
+}
+
+pub fn render_with(snap: &Snapshot, opts: &RenderOptions) -> Vec<String> {
+    let (lines, annotations) = render_lines(snap, opts);
     let mut result: Vec<String> = Vec::with_capacity(lines.len());
-    for line in lines.iter() {
-        result.push(line.encode());
+    for (i, line) in lines.iter().enumerate() {
+        match annotations.get(&i) {
+            Some(a) => result.push(format!("{}{} {}", line.encode(), ANNOTATION_DELIM, a)),
+            None => result.push(line.encode()),
+        }
     }
     result
 }
 
+pub fn render(snap: &Snapshot) -> Vec<String> {
+    render_with(snap, &RenderOptions::default())
+}
+
+/// Error message returned when the snapshot doesn't specify the root
+/// dir before the file entries
+pub(super) const MISSING_ROOTDIR_MSG: &str =
+    "Root Directory metadata is required and must precede file entries";
+
 pub fn parse(str_lines: Vec<String>) -> Result<Snapshot, AppError> {
     let lines = str_lines.iter().map(|s| Line::decode(s.as_str()));
-    let mut rootdir: Option<PathBuf> = None;
+    // The first rootdir is the primary one
+    let mut rootdirs: Vec<PathBuf> = Vec::new();
     let mut generated_at: Option<DateTime<FixedOffset>> = None;
-    let mut curr_group: Option<u64> = None;
+    let mut verify_hash: Option<VerificationHash> = None;
+    let mut checksum_algo: Option<String> = None;
+    // Snapshots generated by older versions of the tool don't record
+    // it, in which case the checksums are in decimal form
+    let mut checksum_format = ChecksumFormat::Decimal;
+    // Snapshots generated by older versions of the tool don't record
+    // it, in which case it's assumed to be false
+    let mut quick_mode = false;
+    let mut content_hash = ContentHash::Raw;
+    let mut curr_group: Option<Checksum> = None;
     let mut duplicates: HashMap<Checksum, Vec<FilePath>> = HashMap::new();
+    // Whether the metadata allowing full deletion of the next group
+    // has been found
+    let mut allow_full_deletion = false;
+    let mut full_deletion_groups: HashSet<Checksum> = HashSet::new();
+    // File size of the group, applicable to the next checksum line
+    let mut file_size: Option<u64> = None;
+    let mut file_sizes: HashMap<Checksum, u64> = HashMap::new();
+    let mut custom_metadata: BTreeMap<String, String> = BTreeMap::new();
     for line in lines {
         match &line {
             Ok(Line::Comment(_)) => continue,
             Ok(Line::Blank) => continue,
             Ok(Line::MetaData { key, val }) => {
                 if key == "Root Directory" {
-                    rootdir = Some(PathBuf::from(val));
+                    rootdirs.push(decode_path(val));
                 } else if key == "Generated at" {
                     generated_at = Some(DateTime::parse_from_rfc2822(val).unwrap());
+                } else if key == "Verification Hash" {
+                    verify_hash =
+                        Some(VerificationHash::parse(val).map_err(|_| AppError::SnapshotParsing)?);
+                } else if key == "Checksum Algorithm" {
+                    checksum_algo = Some(val.to_owned());
+                } else if key == "Checksum Format" {
+                    checksum_format =
+                        ChecksumFormat::parse(val).map_err(|_| AppError::SnapshotParsing)?;
+                } else if key == "Quick Mode" {
+                    quick_mode = val == "true";
+                } else if key == "Content Hash" {
+                    content_hash =
+                        ContentHash::parse(val).map_err(|_| AppError::SnapshotParsing)?;
+                } else if key == "Allow Full Deletion" {
+                    allow_full_deletion = val == "true";
+                } else if key == "File Size" {
+                    file_size = Some(val.parse::<u64>().map_err(|_| AppError::SnapshotParsing)?);
+                } else if key != "Group" && key != "Reclaimable" {
+                    // Group and Reclaimable are informational and
+                    // are derived again when rendering
+                    custom_metadata.insert(key.to_owned(), val.to_owned());
                 }
             }
             Ok(Line::Checksum(hash)) => {
-                let parsed_checksum =
-                    Checksum::parse(hash.as_str()).map_err(|_| AppError::SnapshotParsing)?;
-                curr_group = Some(parsed_checksum.value())
+                // Paths in the groups are relative to the rootdir, so
+                // it must be known before processing them
+                if rootdirs.is_empty() {
+                    return Err(AppError::SnapshotMetadata(MISSING_ROOTDIR_MSG.to_owned()));
+                }
+                let parsed_checksum = Checksum::parse(hash.as_str(), checksum_format)
+                    .map_err(|_| AppError::SnapshotParsing)?;
+                if allow_full_deletion {
+                    full_deletion_groups.insert(parsed_checksum.clone());
+                    allow_full_deletion = false;
+                }
+                if let Some(size) = file_size.take() {
+                    file_sizes.insert(parsed_checksum.clone(), size);
+                }
+                curr_group = Some(parsed_checksum);
             }
             Ok(Line::PathInfo {
                 path,
@@ -290,14 +511,20 @@ pub fn parse(str_lines: Vec<String>) -> Result<Snapshot, AppError> {
                 delim: _,
                 extra,
             }) => {
-                let group = Checksum::new(curr_group.unwrap());
-                // `clone` is called below because `ok_or` causes a move
-                let base_dir = rootdir.clone().ok_or(AppError::SnapshotParsing)?;
-                let path = PathBuf::from(path);
-                let abs_path = normalize_path(&path, false, &base_dir)?;
+                // A path line without a preceding checksum line is
+                // invalid
+                let group = curr_group.clone().ok_or(AppError::SnapshotParsing)?;
+                // The rootdirs are checked to be non-empty when the
+                // checksum line is processed
+                let path = decode_path(path);
+                let abs_path = resolve_entry_path(
+                    &path,
+                    &rootdirs.iter().map(|d| d.as_path()).collect::<Vec<&Path>>(),
+                )?;
                 let filepath = FilePath {
                     path: abs_path,
-                    op: FileOp::decode(op.as_str(), extra.as_ref().map(|s| s.as_str())).unwrap(),
+                    op: FileOp::decode(op.as_str(), extra.as_ref().map(|s| s.as_str()))
+                        .ok_or(AppError::SnapshotParsing)?,
                 };
                 if let Some(fps) = duplicates.get_mut(&group) {
                     fps.push(filepath);
@@ -308,45 +535,60 @@ pub fn parse(str_lines: Vec<String>) -> Result<Snapshot, AppError> {
             Err(_) => return Err(AppError::SnapshotParsing),
         }
     }
+    if rootdirs.is_empty() {
+        return Err(AppError::SnapshotMetadata(MISSING_ROOTDIR_MSG.to_owned()));
+    }
+    let extra_rootdirs = rootdirs.split_off(1);
     Ok(Snapshot {
-        rootdir: rootdir.ok_or(AppError::SnapshotParsing)?,
+        rootdir: rootdirs.remove(0),
+        extra_rootdirs,
         generated_at: generated_at.ok_or(AppError::SnapshotParsing)?,
+        verify_hash,
+        checksum_algo: checksum_algo.unwrap_or_else(|| Checksum::ALGORITHM.to_owned()),
+        quick_mode,
+        content_hash,
         duplicates,
+        full_deletion_groups,
+        file_sizes,
+        custom_metadata,
     })
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
 
     use super::*;
+    use serial_test::serial;
+    use std::fs;
 
     // Tests for Line enum methods
 
     #[test]
     fn test_line_decode_blank() {
-        let x = Line::decode(&"".to_owned());
+        let x = Line::decode("");
         assert!(x.is_ok());
         assert_eq!(Line::Blank, x.unwrap());
 
-        let x = Line::decode(&"  ".to_owned());
+        let x = Line::decode("  ");
         assert!(x.is_ok());
         assert_eq!(Line::Blank, x.unwrap());
     }
 
     #[test]
     fn test_line_decode_comment() {
-        let x = Line::decode(&"# This is a comment".to_owned());
+        let x = Line::decode("# This is a comment");
         assert!(x.is_ok());
         assert_eq!(Line::Comment("This is a comment".to_owned()), x.unwrap());
 
-        let x = Line::decode(&"# ".to_owned());
+        let x = Line::decode("# ");
         assert!(x.is_ok());
         assert_eq!(Line::Comment("".to_owned()), x.unwrap());
     }
 
     #[test]
     fn test_line_decode_metadata() {
-        let x = Line::decode(&"#! Root Directory: /path/to/rootdir".to_owned());
+        let x = Line::decode("#! Root Directory: /path/to/rootdir");
         assert!(x.is_ok());
         assert_eq!(
             Line::MetaData {
@@ -357,7 +599,7 @@ mod tests {
         );
 
         // Without space after colon
-        let x = Line::decode(&"#! Root Directory:/path/to/rootdir".to_owned());
+        let x = Line::decode("#! Root Directory:/path/to/rootdir");
         assert!(x.is_ok());
         assert_eq!(
             Line::MetaData {
@@ -368,7 +610,7 @@ mod tests {
         );
 
         // Without space after exclamation
-        let x = Line::decode(&"#!Root Directory:/path/to/rootdir".to_owned());
+        let x = Line::decode("#!Root Directory:/path/to/rootdir");
         assert!(x.is_ok());
         assert_eq!(
             Line::MetaData {
@@ -379,7 +621,7 @@ mod tests {
         );
 
         // Unrecognized metadata
-        let x = Line::decode(&"#! Foo: bar".to_owned());
+        let x = Line::decode("#! Foo: bar");
         assert!(x.is_ok());
         assert_eq!(
             Line::MetaData {
@@ -390,19 +632,19 @@ mod tests {
         );
 
         // When `#!` prefix is incorrectly used
-        match Line::decode(&"#!".to_owned()) {
+        match Line::decode("#!") {
             Err(AppError::SnapshotParsing) => assert!(true),
             Err(_) => assert!(false),
             Ok(_) => assert!(false),
         }
 
-        match Line::decode(&"#! Just a comment by mistake".to_owned()) {
+        match Line::decode("#! Just a comment by mistake") {
             Err(AppError::SnapshotParsing) => assert!(true),
             Err(_) => assert!(false),
             Ok(_) => assert!(false),
         }
 
-        match Line::decode(&"#! Empty metadata: ".to_owned()) {
+        match Line::decode("#! Empty metadata: ") {
             Err(AppError::SnapshotParsing) => assert!(true),
             Err(_) => assert!(false),
             Ok(_) => assert!(false),
@@ -411,7 +653,7 @@ mod tests {
 
     #[test]
     fn test_line_decode_checksum() {
-        let x = Line::decode(&"[fd2dd43f6cd0565ed876ca1ac2dfc708]".to_owned());
+        let x = Line::decode("[fd2dd43f6cd0565ed876ca1ac2dfc708]");
         match x {
             Ok(Line::Checksum(d)) => {
                 assert_eq!("fd2dd43f6cd0565ed876ca1ac2dfc708".to_owned(), d);
@@ -423,7 +665,7 @@ mod tests {
     #[test]
     fn test_line_decode_pathinfo() {
         // keep
-        let x = Line::decode(&"keep /foo/bar/1.txt".to_owned());
+        let x = Line::decode("keep /foo/bar/1.txt");
         assert!(x.is_ok());
         assert_eq!(
             Line::PathInfo {
@@ -436,7 +678,7 @@ mod tests {
         );
 
         // symlink
-        let y = Line::decode(&"symlink /foo/bar/1.txt".to_owned());
+        let y = Line::decode("symlink /foo/bar/1.txt");
         assert!(y.is_ok());
         assert_eq!(
             Line::PathInfo {
@@ -448,7 +690,7 @@ mod tests {
             y.unwrap()
         );
 
-        let y = Line::decode(&"symlink /foo/bar/1.txt -> /foo/cat/1.txt".to_owned());
+        let y = Line::decode("symlink /foo/bar/1.txt -> /foo/cat/1.txt");
         assert!(y.is_ok());
         assert_eq!(
             Line::PathInfo {
@@ -460,7 +702,7 @@ mod tests {
             y.unwrap()
         );
 
-        let y = Line::decode(&"symlink /foo/bar/1.txt ->".to_owned());
+        let y = Line::decode("symlink /foo/bar/1.txt ->");
         assert!(y.is_ok());
         assert_eq!(
             Line::PathInfo {
@@ -472,14 +714,47 @@ mod tests {
             y.unwrap()
         );
 
-        match Line::decode(&"symlink /foo/bar/1.txt -> /cat/1.txt -> /dog/2.txt".to_owned()) {
+        match Line::decode("symlink /foo/bar/1.txt -> /cat/1.txt -> /dog/2.txt") {
+            Err(AppError::SnapshotParsing) => assert!(true),
+            Err(_) => assert!(false),
+            Ok(_) => assert!(false),
+        }
+
+        // hardlink
+        let h = Line::decode("hardlink /foo/bar/1.txt -> ../cat/1.txt");
+        assert!(h.is_ok());
+        assert_eq!(
+            Line::PathInfo {
+                path: "/foo/bar/1.txt".to_owned(),
+                op: "hardlink".to_owned(),
+                delim: Some("->".to_owned()),
+                extra: Some("../cat/1.txt".to_owned()),
+            },
+            h.unwrap()
+        );
+
+        // move
+        let m = Line::decode("move /foo/bar/1.txt -> /quarantine/1.txt");
+        assert!(m.is_ok());
+        assert_eq!(
+            Line::PathInfo {
+                path: "/foo/bar/1.txt".to_owned(),
+                op: "move".to_owned(),
+                delim: Some("->".to_owned()),
+                extra: Some("/quarantine/1.txt".to_owned()),
+            },
+            m.unwrap()
+        );
+
+        // move without dest
+        match Line::decode("move /foo/bar/1.txt") {
             Err(AppError::SnapshotParsing) => assert!(true),
             Err(_) => assert!(false),
             Ok(_) => assert!(false),
         }
 
         // delete
-        let z = Line::decode(&"delete /foo/bar/1.txt".to_owned());
+        let z = Line::decode("delete /foo/bar/1.txt");
         assert!(z.is_ok());
         assert_eq!(
             Line::PathInfo {
@@ -491,8 +766,21 @@ mod tests {
             z.unwrap()
         );
 
+        // trash
+        let t = Line::decode("trash /foo/bar/1.txt");
+        assert!(t.is_ok());
+        assert_eq!(
+            Line::PathInfo {
+                path: "/foo/bar/1.txt".to_owned(),
+                op: "trash".to_owned(),
+                delim: None,
+                extra: None,
+            },
+            t.unwrap()
+        );
+
         // with unknown marker
-        match Line::decode(&"create /foo/bar/1.txt".to_owned()) {
+        match Line::decode("create /foo/bar/1.txt") {
             Err(AppError::SnapshotParsing) => assert!(true),
             Err(_) => assert!(false),
             Ok(_) => assert!(false),
@@ -534,6 +822,39 @@ mod tests {
             line
         );
 
+        // Hardlink with extra
+        let t = PathBuf::from("/base_dir/bar/1.txt");
+        let s = PathBuf::from("/base_dir/foo/1.txt");
+        let op = FileOp::Hardlink { source: Some(s) };
+        let fp = FilePath { path: t, op };
+        let line = Line::pathinfo(&fp, &rootdir);
+        assert_eq!(
+            Line::PathInfo {
+                path: "bar/1.txt".to_owned(),
+                op: "hardlink".to_owned(),
+                delim: Some("->".to_owned()),
+                extra: Some("/base_dir/foo/1.txt".to_owned()),
+            },
+            line
+        );
+
+        // Move
+        let t = PathBuf::from("/base_dir/bar/1.txt");
+        let op = FileOp::Move {
+            dest: PathBuf::from("/quarantine/bar/1.txt"),
+        };
+        let fp = FilePath { path: t, op };
+        let line = Line::pathinfo(&fp, &rootdir);
+        assert_eq!(
+            Line::PathInfo {
+                path: "bar/1.txt".to_owned(),
+                op: "move".to_owned(),
+                delim: Some("->".to_owned()),
+                extra: Some("/quarantine/bar/1.txt".to_owned()),
+            },
+            line
+        );
+
         // Keep
         let path = PathBuf::from("/base_dir/foo/1.txt");
         let op = FileOp::Keep;
@@ -572,12 +893,17 @@ mod tests {
         let input = vec![
             "#! Root Directory: /foo",
             "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "#! Checksum Algorithm: xxh3_64",
+            "#! Verification Hash: blake3",
+            "#! Quick Mode: true",
             "",
+            "#! Group: 1 of 2",
             "[937219074347857651]",
             "symlink /foo/bar/1.txt",
             "keep /foo/1.txt",
             "delete /foo/bar/1_copy.txt",
             "",
+            "#! Group: 2 of 2",
             "[8183168229739997842]",
             "keep /foo/2.txt",
             "symlink /foo/bar/2.txt",
@@ -585,8 +911,11 @@ mod tests {
         let lines = input.iter().map(|s| String::from(*s)).collect();
         let snap: Snapshot = parse(lines).unwrap();
         assert_eq!(PathBuf::from("/foo"), snap.rootdir);
+        assert_eq!(Some(VerificationHash::Blake3), snap.verify_hash);
+        assert_eq!("xxh3_64", snap.checksum_algo);
+        assert!(snap.quick_mode);
 
-        let d1 = Checksum::parse("937219074347857651").unwrap();
+        let d1 = Checksum::parse("937219074347857651", ChecksumFormat::Decimal).unwrap();
         if let Some(fps) = snap.duplicates.get(&d1) {
             assert_eq!(3, fps.len());
             // 1st filepath
@@ -610,9 +939,389 @@ mod tests {
             assert!(false);
         }
 
-        let d2 = Checksum::parse("8183168229739997842").unwrap();
+        let d2 = Checksum::parse("8183168229739997842", ChecksumFormat::Decimal).unwrap();
         if let Some(fps) = snap.duplicates.get(&d2) {
             assert_eq!(2, fps.len());
         }
     }
+
+    #[test]
+    fn test_render_group_numbers() {
+        let input = vec![
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "[937219074347857651]",
+            "keep /foo/1.txt",
+            "delete /foo/bar/1.txt",
+            "",
+            "[8183168229739997842]",
+            "keep /foo/2.txt",
+            "symlink /foo/bar/2.txt",
+        ];
+        let lines = input.iter().map(|s| String::from(*s)).collect();
+        let snap: Snapshot = parse(lines).unwrap();
+        let output = render(&snap);
+        let group_lines = output
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.starts_with("#! Group:"))
+            .collect::<Vec<(usize, &String)>>();
+        assert_eq!(2, group_lines.len());
+        assert_eq!("#! Group: 1 of 2", group_lines[0].1);
+        assert_eq!("#! Group: 2 of 2", group_lines[1].1);
+        // Every group number is followed by the checksum line (as the
+        // files don't exist, reclaimable space is not rendered)
+        for (i, _) in group_lines {
+            assert!(output[i + 1].starts_with('['));
+        }
+    }
+
+    #[test]
+    fn test_parse_render_allow_full_deletion() {
+        let input = vec![
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "#! Allow Full Deletion: true",
+            "[937219074347857651]",
+            "delete /foo/1.toc",
+            "delete /foo/bar/1.toc",
+            "",
+            "[8183168229739997842]",
+            "keep /foo/2.txt",
+            "delete /foo/bar/2.txt",
+        ];
+        let lines = input.iter().map(|s| String::from(*s)).collect();
+        let snap: Snapshot = parse(lines).unwrap();
+        assert_eq!(1, snap.full_deletion_groups.len());
+        assert!(snap
+            .full_deletion_groups
+            .contains(&Checksum::parse("937219074347857651", ChecksumFormat::Decimal).unwrap()));
+
+        let output = render(&snap);
+        let i = output
+            .iter()
+            .position(|line| line == "#! Allow Full Deletion: true")
+            .unwrap();
+        assert_eq!("[0d01abc8533976f3]", output[i + 1]);
+    }
+
+    #[test]
+    fn test_parse_checksum_format() {
+        let parse_with = |metadata: Option<&str>, checksum: &str| {
+            let mut input = vec![
+                "#! Root Directory: /foo".to_owned(),
+                "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530".to_owned(),
+            ];
+            input.extend(metadata.map(|m| m.to_owned()));
+            input.extend(["".to_owned(), format!("[{}]", checksum)]);
+            input.extend(["keep 1.txt".to_owned(), "delete 2.txt".to_owned()]);
+            parse(input)
+        };
+        // Checksums are in decimal form in older snapshots, even if
+        // they happen to have 16 digits
+        let snap = parse_with(None, "1234567890123456").unwrap();
+        assert!(snap
+            .duplicates
+            .contains_key(&Checksum::new(1234567890123456)));
+        assert!(parse_with(None, "0d01abc8533976f3").is_err());
+
+        let snap = parse_with(Some("#! Checksum Format: hex"), "1234567890123456").unwrap();
+        assert!(snap
+            .duplicates
+            .contains_key(&Checksum::new(0x1234567890123456)));
+        assert!(parse_with(Some("#! Checksum Format: hex"), "937219074347857651").is_err());
+        assert!(parse_with(Some("#! Checksum Format: octal"), "1").is_err());
+
+        // Rendered snapshots record the format
+        let output = render(&parse_with(None, "937219074347857651").unwrap());
+        assert!(output.contains(&"#! Checksum Format: hex".to_owned()));
+        assert!(output.contains(&"[0d01abc8533976f3]".to_owned()));
+    }
+
+    #[test]
+    #[serial]
+    fn test_render_reclaimable_space() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        for p in ["1.txt", "2.txt", "3.txt", "a.txt", "b.txt"] {
+            let contents = if p.starts_with(char::is_numeric) {
+                "ONE"
+            } else {
+                "TWO TWO"
+            };
+            fs::write(data_dir.join(p), contents).unwrap();
+        }
+        let input = vec![
+            format!("#! Root Directory: {}", data_dir.display()),
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530".to_owned(),
+            "".to_owned(),
+            "[937219074347857651]".to_owned(),
+            "keep 1.txt".to_owned(),
+            "keep 2.txt".to_owned(),
+            "delete 3.txt".to_owned(),
+            "".to_owned(),
+            "[8183168229739997842]".to_owned(),
+            "keep a.txt".to_owned(),
+            "keep b.txt".to_owned(),
+        ];
+        let snap: Snapshot = parse(input).unwrap();
+        let output = render(&snap);
+        let reclaimable_lines = output
+            .iter()
+            .filter(|line| line.starts_with("#! Reclaimable:"))
+            .collect::<Vec<&String>>();
+        // Groups are sorted by size of the files
+        assert_eq!(
+            vec!["#! Reclaimable: 7 bytes", "#! Reclaimable: 6 bytes"],
+            reclaimable_lines
+        );
+        // The metadata is ignored when parsing
+        let parsed = parse(output).unwrap();
+        assert!(snap.duplicates == parsed.duplicates);
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_parse_render_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let rootdir = PathBuf::from("/foo");
+        let path = rootdir.join(OsStr::from_bytes(b"caf\xe9.txt"));
+        let source = PathBuf::from(OsStr::from_bytes(b"../\xff/1.txt"));
+        let fp = FilePath {
+            path: path.clone(),
+            op: FileOp::Symlink {
+                source: Some(source.clone()),
+            },
+        };
+        // Rendering doesn't panic and the invalid bytes are escaped
+        assert_eq!(
+            Line::PathInfo {
+                path: "caf\\xe9.txt".to_owned(),
+                op: "symlink".to_owned(),
+                delim: Some("->".to_owned()),
+                extra: Some("../\\xff/1.txt".to_owned()),
+            },
+            Line::pathinfo(&fp, &rootdir)
+        );
+
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "[937219074347857651]",
+            "keep 1.txt",
+            "symlink caf\\xe9.txt -> ../\\xff/1.txt",
+        ];
+        let snap = parse(input.iter().map(|s| String::from(*s)).collect()).unwrap();
+        let filepaths = snap
+            .duplicates
+            .get(&Checksum::parse("937219074347857651", ChecksumFormat::Decimal).unwrap())
+            .unwrap();
+        assert_eq!(path, filepaths[1].path);
+        match &filepaths[1].op {
+            FileOp::Symlink { source: Some(s) } => assert_eq!(&source, s),
+            _ => assert!(false),
+        }
+
+        // The path survives a round trip
+        let parsed = parse(render(&snap)).unwrap();
+        assert!(snap.duplicates == parsed.duplicates);
+    }
+
+    #[test]
+    fn test_sorted_groups_ties() {
+        let input = vec![
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "[30]",
+            "keep /foo/1.txt",
+            "delete /foo/bar/1.txt",
+            "[10]",
+            "keep /foo/2.txt",
+            "delete /foo/bar/2.txt",
+            "[20]",
+            "keep /foo/3.txt",
+            "delete /foo/bar/3.txt",
+        ];
+        let lines = input.iter().map(|s| String::from(*s)).collect();
+        let snap: Snapshot = parse(lines).unwrap();
+        // As the files don't exist, the sizes of all groups are
+        // considered equal and they are sorted by checksum instead
+        let checksums = sorted_groups(&snap.duplicates)
+            .iter()
+            .map(|(c, _)| c.value())
+            .collect::<Vec<u64>>();
+        assert_eq!(vec![10, 20, 30], checksums);
+    }
+
+    #[test]
+    fn test_parse_missing_rootdir() {
+        let input = [
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "[937219074347857651]",
+            "keep bar/1.txt",
+            "delete 1.txt",
+        ];
+        match parse(input.iter().map(|s| s.to_string()).collect()) {
+            Err(AppError::SnapshotMetadata(msg)) => assert_eq!(MISSING_ROOTDIR_MSG, msg),
+            Err(_) => assert!(false),
+            Ok(_) => assert!(false),
+        }
+
+        // Rootdir specified after the file entries
+        let input = [
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "[937219074347857651]",
+            "keep bar/1.txt",
+            "delete 1.txt",
+            "#! Root Directory: /foo",
+        ];
+        match parse(input.iter().map(|s| s.to_string()).collect()) {
+            Err(AppError::SnapshotMetadata(msg)) => assert_eq!(MISSING_ROOTDIR_MSG, msg),
+            Err(_) => assert!(false),
+            Ok(_) => assert!(false),
+        }
+
+        // Snapshot without any file entries
+        let input = ["#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530"];
+        match parse(input.iter().map(|s| s.to_string()).collect()) {
+            Err(AppError::SnapshotMetadata(msg)) => assert_eq!(MISSING_ROOTDIR_MSG, msg),
+            Err(_) => assert!(false),
+            Ok(_) => assert!(false),
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_render_annotated() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").unwrap();
+        let rootdir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        fs::write(rootdir.join("1.txt"), "ONE").unwrap();
+        fs::write(rootdir.join("a\tb.txt"), "ONE").unwrap();
+        let input = [
+            format!("#! Root Directory: {}", rootdir.display()),
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530".to_owned(),
+            "".to_owned(),
+            "[937219074347857651]".to_owned(),
+            "keep 1.txt".to_owned(),
+            "symlink a\\x09b.txt".to_owned(),
+            "delete 2.txt".to_owned(),
+        ];
+        let snap = parse(input.to_vec()).unwrap();
+        let opts = RenderOptions {
+            annotate: true,
+            ..Default::default()
+        };
+        let lines = render_with(&snap, &opts);
+        let today = Local::now().format("%Y-%m-%d");
+        assert!(lines.contains(&format!("keep 1.txt\t# 3 bytes, {}", today)));
+        assert!(lines.contains(&format!("symlink a\\x09b.txt\t# 3 bytes, {}", today)));
+        // Files whose metadata can't be read are not annotated
+        assert!(lines.contains(&"delete 2.txt".to_owned()));
+
+        // Annotations are ignored when parsing
+        let parsed = parse(lines).unwrap();
+        assert_eq!(render(&snap), render(&parsed));
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    fn test_render_without_help() {
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "",
+            "[937219074347857651]",
+            "keep /foo/1.txt",
+            "delete /foo/bar/1.txt",
+        ];
+        let lines = input.iter().map(|s| String::from(*s)).collect();
+        let snap: Snapshot = parse(lines).unwrap();
+        let opts = RenderOptions {
+            help: false,
+            ..Default::default()
+        };
+        let output = render_with(&snap, &opts);
+        assert_eq!(Some(&"delete bar/1.txt".to_owned()), output.last());
+        assert!(!output.iter().any(|l| l.starts_with("# ")));
+        // Footer is rendered by default
+        assert_eq!(render(&snap)[..output.len()], output[..],);
+        assert!(render(&snap).contains(&"# Reference:".to_owned()));
+        assert_eq!(render(&snap), render(&parse(output).unwrap()));
+    }
+
+    #[test]
+    fn test_parse_render_file_size() {
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "#! Checksum Algorithm: xxh3_64",
+            "#! Checksum Format: hex",
+            "#! Quick Mode: false",
+            "",
+            "#! Group: 1 of 2",
+            "#! File Size: 1024",
+            "[0000000000000001]",
+            "keep a.txt",
+            "delete b.txt",
+            "",
+            "#! Group: 2 of 2",
+            "[0000000000000002]",
+            "keep c.txt",
+            "delete d.txt",
+        ];
+        let snap = parse(input.iter().map(|s| s.to_string()).collect()).unwrap();
+        assert_eq!(Some(&1024), snap.file_sizes.get(&Checksum::new(1)));
+        // File size is not recorded in older snapshots
+        assert!(!snap.file_sizes.contains_key(&Checksum::new(2)));
+
+        let output = render_with(
+            &snap,
+            &RenderOptions {
+                annotate: false,
+                help: false,
+            },
+        );
+        assert_eq!(input.to_vec(), output);
+    }
+
+    #[test]
+    fn test_parse_render_custom_metadata() {
+        let input = [
+            "#! Root Directory: /foo",
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530",
+            "#! Checksum Algorithm: xxh3_64",
+            "#! Checksum Format: hex",
+            "#! Quick Mode: false",
+            "#! Project: foo",
+            "",
+            "#! Group: 1 of 1",
+            "[0000000000000001]",
+            "keep a.txt",
+            "delete b.txt",
+        ];
+        let snap = parse(input.iter().map(|s| s.to_string()).collect()).unwrap();
+        assert_eq!(1, snap.custom_metadata.len());
+        assert_eq!(Some(&"foo".to_owned()), snap.custom_metadata.get("Project"));
+
+        let output = render_with(
+            &snap,
+            &RenderOptions {
+                annotate: false,
+                help: false,
+            },
+        );
+        assert_eq!(input.to_vec(), output);
+    }
 }
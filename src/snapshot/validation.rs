@@ -1,8 +1,11 @@
 use super::{are_all_deletions, find_keeper, FileOp, FilePath, Snapshot};
 use crate::executor::Action;
 use crate::fileutil;
-use crate::hash::Checksum;
-use log::warn;
+use crate::hash::{Checksum, ContentHash};
+use chrono::{DateTime, FixedOffset, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io;
 use std::path::{Path, PathBuf};
 
@@ -17,12 +20,96 @@ pub enum Error {
         actual: String,
         expected: String,
     },
+    StaleSnapshot {
+        path: String,
+        modified_at: String,
+        generated_at: String,
+    },
+    DuplicatePath {
+        path: String,
+    },
+    ContentMismatch {
+        path: String,
+        other_path: String,
+    },
     Io(io::Error),
 }
 
+impl Error {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::RootDir(_) => "RootDir",
+            Self::OpNotPossible(_) => "OpNotPossible",
+            Self::OpNotAllowed(_) => "OpNotAllowed",
+            Self::CorruptSnapshot(_) => "CorruptSnapshot",
+            Self::ChecksumMismatch { .. } => "ChecksumMismatch",
+            Self::StaleSnapshot { .. } => "StaleSnapshot",
+            Self::DuplicatePath { .. } => "DuplicatePath",
+            Self::ContentMismatch { .. } => "ContentMismatch",
+            Self::Io(_) => "Io",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::RootDir(msg)
+            | Self::OpNotPossible(msg)
+            | Self::OpNotAllowed(msg)
+            | Self::CorruptSnapshot(msg) => msg.clone(),
+            Self::ChecksumMismatch {
+                path,
+                actual,
+                expected,
+            } => format!("Checksum mismatch for {path}: expected {expected}, found {actual}"),
+            Self::StaleSnapshot {
+                path,
+                modified_at,
+                generated_at,
+            } => format!(
+                "File {path} was modified at {modified_at}, after the snapshot was generated at {generated_at}. Please regenerate the snapshot"
+            ),
+            Self::DuplicatePath { path } => {
+                format!("Path {path} appears more than once in the snapshot")
+            }
+            Self::ContentMismatch { path, other_path } => format!(
+                "Contents of {path} and {other_path} differ despite having the same checksum (snapshot was generated in quick mode)"
+            ),
+            Self::Io(e) => e.to_string(),
+        }
+    }
+}
+
+/// Serializable representation of a validation `Error`
+#[derive(Serialize, Debug)]
+pub struct ErrorReport {
+    kind: &'static str,
+    message: String,
+}
+
+impl From<&Error> for ErrorReport {
+    fn from(e: &Error) -> Self {
+        Self {
+            kind: e.kind(),
+            message: e.message(),
+        }
+    }
+}
+
+/// Result of validation in a machine-readable form
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub valid: bool,
+    pub pending: usize,
+    pub errors: Vec<ErrorReport>,
+}
+
 fn validate_rootdir(path: &Path) -> Result<(), Error> {
     match path.try_exists() {
-        Ok(true) => Ok(()),
+        Ok(true) if path.is_dir() => Ok(()),
+        Ok(true) => Err(Error::RootDir(format!(
+            "The rootdir {} is not a directory",
+            path.display()
+        ))),
         Ok(false) => Err(Error::RootDir(format!(
             "The rootdir {} doesn't exist",
             path.display()
@@ -34,6 +121,54 @@ fn validate_rootdir(path: &Path) -> Result<(), Error> {
     }
 }
 
+/// Checks whether the `path` is located under any of the `rootdirs`
+fn within_any_rootdir(rootdirs: &[&Path], path: &Path) -> bool {
+    rootdirs.iter().any(|d| fileutil::within_rootdir(d, path))
+}
+
+/// Validates that every path appears only once in the snapshot, as
+/// otherwise contradictory actions may get executed for the same path
+///
+/// This covers the same path appearing in different groups as well.
+fn validate_unique_paths(snap: &Snapshot) -> Result<(), Error> {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    for filepath in snap.duplicates.values().flatten() {
+        if !seen.insert(fileutil::lexical_normalize(&filepath.path)) {
+            return Err(Error::DuplicatePath {
+                path: filepath.path.display().to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Validates that the file at `path` hasn't been modified after the
+/// snapshot was generated
+///
+/// Symlinks are not checked as they may have been created by a
+/// previous (partial) run of `apply`. Missing paths are also ignored
+/// here and are handled by the op specific validations.
+fn validate_mtime(path: &Path, generated_at: &DateTime<FixedOffset>) -> Result<(), Error> {
+    let metadata = match path.symlink_metadata() {
+        Ok(m) if m.is_file() => m,
+        _ => return Ok(()),
+    };
+    let modified_at: DateTime<Utc> = metadata.modified().map_err(Error::Io)?.into();
+    // The `generated_at` timestamp in the snapshot only has seconds
+    // precision
+    if modified_at.timestamp() > generated_at.timestamp() {
+        Err(Error::StaleSnapshot {
+            path: path.display().to_string(),
+            modified_at: modified_at
+                .with_timezone(generated_at.offset())
+                .to_rfc2822(),
+            generated_at: generated_at.to_rfc2822(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
 fn validate_group(
     hash: &Checksum,
     filepaths: &[FilePath],
@@ -61,9 +196,62 @@ fn validate_group(
     }
 }
 
-fn validate_checksum(path: &Path, expected_hash: &Checksum) -> Result<(), Error> {
-    let computed_hash = Checksum::of_file(&path).map_err(Error::Io)?;
-    if computed_hash == *expected_hash {
+/// Checksums of the files computed while validating a group, keyed by
+/// their canonical paths
+struct Checksums {
+    // Same as the one the snapshot was generated with
+    content_hash: ContentHash,
+    computed: HashMap<PathBuf, Checksum>,
+}
+
+impl Default for Checksums {
+    fn default() -> Self {
+        Self::with_content_hash(ContentHash::Raw)
+    }
+}
+
+impl Checksums {
+    fn with_content_hash(content_hash: ContentHash) -> Self {
+        Self {
+            content_hash,
+            computed: HashMap::new(),
+        }
+    }
+}
+
+/// Returns checksum of the file, computing it only if it's not found
+/// in `checksums`
+///
+/// As canonical paths are used as keys, the keeper and a symlink
+/// source path resolving to it share the same entry. If the path
+/// can't be canonicalized, the checksum is computed without caching
+/// (which would return the same error).
+fn cached_checksum(path: &Path, checksums: &mut Checksums) -> Result<Checksum, Error> {
+    let canonical_path = match path.canonicalize() {
+        Ok(p) => p,
+        Err(_) => return Checksum::of_file_with(&path, checksums.content_hash).map_err(Error::Io),
+    };
+    if let Some(c) = checksums.computed.get(&canonical_path) {
+        return Ok(Checksum::new(c.value()));
+    }
+    let c = Checksum::of_file_with(&canonical_path, checksums.content_hash).map_err(Error::Io)?;
+    checksums
+        .computed
+        .insert(canonical_path, Checksum::new(c.value()));
+    Ok(c)
+}
+
+/// Validates that the checksum of the file matches the expected one
+///
+/// Only the hash values are compared, as the subgroup of the expected
+/// checksum can't be derived from the file contents.
+fn validate_checksum(
+    path: &Path,
+    expected_hash: &Checksum,
+    checksums: &mut Checksums,
+) -> Result<(), Error> {
+    let computed_hash = cached_checksum(path, checksums)?;
+    if computed_hash.value() == expected_hash.value() {
         Ok(())
     } else {
         Err(Error::ChecksumMismatch {
@@ -77,6 +265,7 @@ fn validate_checksum(path: &Path, expected_hash: &Checksum) -> Result<(), Error>
 fn validate_path_to_keep<'a>(
     filepath: &'a FilePath,
     expected_hash: &Checksum,
+    checksums: &mut Checksums,
 ) -> Result<Action<'a>, Error> {
     let path = &filepath.path;
     if path.is_symlink() {
@@ -87,7 +276,7 @@ fn validate_path_to_keep<'a>(
         )))
     } else if path.is_file() {
         // Path is a regular file
-        validate_checksum(&filepath.path, expected_hash)?;
+        validate_checksum(&filepath.path, expected_hash, checksums)?;
         Ok(Action::Keep(&filepath.path))
     } else {
         // Path doesn't exist
@@ -119,9 +308,10 @@ fn verify_symlink_source_hash(
     source: &Path,
     target: &Path,
     target_hash: &Checksum,
+    checksums: &mut Checksums,
 ) -> Result<bool, Error> {
     let src_hash = if source.is_absolute() {
-        Checksum::of_file(&source).map_err(Error::Io)
+        cached_checksum(source, checksums)
     } else {
         let p = target
             .parent()
@@ -129,9 +319,9 @@ fn verify_symlink_source_hash(
             .join(source)
             .canonicalize()
             .map_err(Error::Io)?;
-        Checksum::of_file(&p).map_err(Error::Io)
+        cached_checksum(&p, checksums)
     }?;
-    Ok(src_hash == *target_hash)
+    Ok(src_hash.value() == target_hash.value())
 }
 
 /// Verifies if actual source path and intended source path are the same.
@@ -148,10 +338,11 @@ fn verify_symlink_source_path(
     let is_intended_abs = intended_source.is_absolute();
     let is_actual_abs = actual_source.is_absolute();
     if is_explicit || (is_intended_abs && is_actual_abs) {
-        // Compare the paths directly if,
+        // Compare the (normalized) paths directly if,
         //   - intended is explicitly specified, or
         //   - both are absolute
-        Ok(*intended_source == *actual_source)
+        Ok(fileutil::normalize_link_source(target, intended_source)
+            == fileutil::normalize_link_source(target, actual_source))
     } else if is_intended_abs && !is_actual_abs {
         // If intended is absolute but actual is relative, then
         // convert actual to absolute and compare
@@ -173,22 +364,62 @@ fn verify_symlink_source_path(
 }
 
 fn validate_path_to_symlink<'a>(
+    rootdirs: &[&Path],
     filepath: &'a FilePath,
     source: Option<&'a PathBuf>,
     default_source: &'a PathBuf,
     expected_hash: &Checksum,
+    checksums: &mut Checksums,
 ) -> Result<Action<'a>, Error> {
     let path = &filepath.path;
 
     // Validate checksum of the file against the expected value
-    validate_checksum(path, expected_hash)?;
+    validate_checksum(path, expected_hash, checksums)?;
+
+    // Relative source path is relative to the parent of the symlink
+    // (target) path
+    let src_path = match source {
+        Some(src) => match path.parent() {
+            Some(parent) if src.is_relative() => parent.join(src),
+            _ => src.to_path_buf(),
+        },
+        None => default_source.to_path_buf(),
+    };
 
     // If source path is `Some` which means it's specified by the
-    // user, verify that it's hash matches that of the group. This is
-    // to prevent the user from specifying some other file as the
-    // symlink source path (a common copy-paste mistake).
+    // user, verify that it's located under a rootdir and that it's
+    // hash matches that of the group. This is to prevent the user
+    // from specifying some other file as the symlink source path (a
+    // common copy-paste mistake).
     if let Some(src) = source {
-        if !verify_symlink_source_hash(src, &filepath.path, expected_hash)? {
+        if !within_any_rootdir(rootdirs, &src_path) {
+            return Err(Error::CorruptSnapshot(format!(
+                "Symlink source path {} of {} is external to the rootdir",
+                src.display(),
+                path.display()
+            )));
+        }
+    }
+
+    // The source (keeper) may have been deleted or replaced after
+    // the snapshot was generated, in which case symlinking to it is
+    // not possible.
+    if !src_path.is_file() {
+        let reason = if src_path.exists() {
+            "is not a regular file"
+        } else {
+            "no longer exists"
+        };
+        return Err(Error::OpNotPossible(format!(
+            "Symlink source {} of {} {}",
+            src_path.display(),
+            path.display(),
+            reason
+        )));
+    }
+
+    if let Some(src) = source {
+        if !verify_symlink_source_hash(src, &filepath.path, expected_hash, checksums)? {
             return Err(Error::OpNotPossible(format!(
                 "Hash mismatch for specified symlink source path: {} -> {}",
                 filepath.path.display(),
@@ -250,6 +481,21 @@ fn validate_path_to_symlink<'a>(
             }),
         }
     } else if filepath.path.is_file() {
+        // Symlinks across filesystems work, but may break if the
+        // mount points change (esp. relative ones), hence the user
+        // is warned about it. Note that the same is an error in case
+        // of hardlinks.
+        if let (Ok(Some((path_dev, _))), Ok(Some((src_dev, _)))) =
+            (fileutil::file_id(path), fileutil::file_id(&src_path))
+        {
+            if path_dev != src_dev {
+                warn!(
+                    "Symlink will be across filesystems: {} -> {}",
+                    path.display(),
+                    src_path.display()
+                );
+            }
+        }
         Ok(Action::Symlink {
             path: &filepath.path,
             source: intended_src_path,
@@ -267,20 +513,195 @@ fn validate_path_to_symlink<'a>(
     }
 }
 
-fn validate_path_to_delete<'a>(
+fn validate_path_to_hardlink<'a>(
+    rootdirs: &[&Path],
     filepath: &'a FilePath,
+    source: Option<&PathBuf>,
+    default_source: &Path,
     expected_hash: &Checksum,
+    checksums: &mut Checksums,
 ) -> Result<Action<'a>, Error> {
     let path = &filepath.path;
+
+    // Unlike symlinks, only existing regular files can be replaced
+    // with hardlinks
+    if path.is_symlink() {
+        return Err(Error::OpNotPossible(format!(
+            "Operation 'hardlink' not possible on a symlink: {}",
+            path.display()
+        )));
+    } else if !path.is_file() {
+        return Err(Error::OpNotPossible(format!(
+            "Operation 'hardlink' not possible for non-existing path: {}",
+            path.display()
+        )));
+    }
+
+    // Validate checksum of the file against the expected value
+    validate_checksum(path, expected_hash, checksums)?;
+
+    // A relative source path is resolved in relation to the parent
+    // dir of the target path
+    let intended_src_path = match source {
+        Some(src) if src.is_relative() => path.parent().unwrap().join(src),
+        Some(src) => src.to_path_buf(),
+        None => default_source.to_path_buf(),
+    };
+
+    // Same as in case of symlinks, the source path specified by the
+    // user must be located under a rootdir
+    if let Some(src) = source {
+        if !within_any_rootdir(rootdirs, &intended_src_path) {
+            return Err(Error::CorruptSnapshot(format!(
+                "Hardlink source path {} of {} is external to the rootdir",
+                src.display(),
+                path.display()
+            )));
+        }
+    }
+
+    if !intended_src_path.is_file() {
+        let reason = if intended_src_path.exists() {
+            "is not a regular file"
+        } else {
+            "no longer exists"
+        };
+        return Err(Error::OpNotPossible(format!(
+            "Hardlink source {} of {} {}",
+            intended_src_path.display(),
+            path.display(),
+            reason
+        )));
+    }
+
+    // If source path is specified by the user, verify that it's hash
+    // matches that of the group (same as in case of symlinks)
+    if let Some(src) = source {
+        if !verify_symlink_source_hash(src, path, expected_hash, checksums)? {
+            return Err(Error::OpNotPossible(format!(
+                "Hash mismatch for specified hardlink source path: {} -> {}",
+                path.display(),
+                src.display()
+            )));
+        }
+    }
+
+    if intended_src_path.is_symlink() {
+        return Err(Error::OpNotAllowed(format!(
+            "Source path cannot be a symlink itself: {}",
+            intended_src_path.display()
+        )));
+    }
+
+    let path_id = fileutil::file_id(path).map_err(Error::Io)?;
+    let src_id = fileutil::file_id(&intended_src_path).map_err(Error::Io)?;
+    let is_no_op = match (path_id, src_id) {
+        (Some((path_dev, path_ino)), Some((src_dev, src_ino))) => {
+            if path_dev != src_dev {
+                return Err(Error::OpNotPossible(format!(
+                    "Hardlink not possible across filesystems: {} -> {}",
+                    path.display(),
+                    intended_src_path.display()
+                )));
+            }
+            // If the path is already a hardlink to the source, it's a
+            // no-op
+            path_ino == src_ino
+        }
+        // Can't be determined on this platform, in which case
+        // creating the hardlink fails if it's across filesystems
+        _ => false,
+    };
+    Ok(Action::Hardlink {
+        path,
+        source: intended_src_path,
+        is_no_op,
+    })
+}
+
+fn validate_path_to_move<'a>(
+    rootdir: &Path,
+    filepath: &'a FilePath,
+    dest: &Path,
+    expected_hash: &Checksum,
+    checksums: &mut Checksums,
+) -> Result<Action<'a>, Error> {
+    let path = &filepath.path;
+    let dest = if dest.is_relative() {
+        rootdir.join(dest)
+    } else {
+        dest.to_path_buf()
+    };
+
+    if path.is_symlink() {
+        return Err(Error::OpNotPossible(format!(
+            "Operation 'move' not possible on a symlink: {}",
+            path.display()
+        )));
+    } else if !path.exists() {
+        // If the file has already been moved, it's a no-op
+        if dest.is_file() {
+            validate_checksum(&dest, expected_hash, checksums)?;
+            warn!("Already moved file will be ignored: {}", path.display());
+            return Ok(Action::Move {
+                path,
+                dest,
+                is_no_op: true,
+            });
+        }
+        return Err(Error::OpNotPossible(format!(
+            "Operation 'move' not possible for non-existing path: {}",
+            path.display()
+        )));
+    }
+
+    // Verify that the hash matches
+    validate_checksum(path, expected_hash, checksums)?;
+
+    // Existing files must never be overwritten. Note that
+    // `symlink_metadata` is used so that broken symlinks are also
+    // considered.
+    if dest.symlink_metadata().is_ok() {
+        return Err(Error::OpNotAllowed(format!(
+            "Destination path already exists: {} -> {}",
+            path.display(),
+            dest.display()
+        )));
+    }
+
+    // Missing parent dirs of the dest path will be created, so it's
+    // the nearest existing ancestor that must be a writable dir
+    let is_writable = match dest.ancestors().skip(1).find(|p| p.exists()) {
+        Some(d) => d.is_dir() && !d.metadata().map_err(Error::Io)?.permissions().readonly(),
+        None => false,
+    };
+    if !is_writable {
+        return Err(Error::OpNotPossible(format!(
+            "Destination path is not writable: {}",
+            dest.display()
+        )));
+    }
+
+    Ok(Action::Move {
+        path,
+        dest,
+        is_no_op: false,
+    })
+}
+
+/// Validates a file marked for deletion (or trashing) and returns
+/// whether the operation is a no-op i.e. the file is already deleted
+fn validate_deletion(
+    path: &Path,
+    expected_hash: &Checksum,
+    checksums: &mut Checksums,
+) -> Result<bool, Error> {
     if path.exists() {
         match path.canonicalize() {
             Ok(_) => {
                 // Verify that the hash matches
-                validate_checksum(path, expected_hash)?;
-                Ok(Action::Delete {
-                    path,
-                    is_no_op: false,
-                })
+                validate_checksum(path, expected_hash, checksums)?;
+                Ok(false)
             }
             Err(_) => Err(Error::OpNotAllowed(format!(
                 "Couldn't verify file marked for deletion: {}",
@@ -289,68 +710,477 @@ fn validate_path_to_delete<'a>(
         }
     } else {
         warn!("Already deleted file will be ignored: {}", path.display());
-        Ok(Action::Delete {
-            path,
-            is_no_op: true,
-        })
+        Ok(true)
     }
 }
 
+fn validate_path_to_delete<'a>(
+    filepath: &'a FilePath,
+    expected_hash: &Checksum,
+    checksums: &mut Checksums,
+) -> Result<Action<'a>, Error> {
+    let path = &filepath.path;
+    let is_no_op = validate_deletion(path, expected_hash, checksums)?;
+    Ok(Action::Delete { path, is_no_op })
+}
+
+fn validate_path_to_trash<'a>(
+    filepath: &'a FilePath,
+    expected_hash: &Checksum,
+    checksums: &mut Checksums,
+) -> Result<Action<'a>, Error> {
+    let path = &filepath.path;
+    let is_no_op = validate_deletion(path, expected_hash, checksums)?;
+    Ok(Action::Trash { path, is_no_op })
+}
+
+/// Returns the normalized path of the explicitly specified symlink
+/// or hardlink source of the `filepath`, if any
+fn explicit_link_source(filepath: &FilePath) -> Option<PathBuf> {
+    let src = match &filepath.op {
+        FileOp::Symlink { source: Some(src) } | FileOp::Hardlink { source: Some(src) } => src,
+        _ => return None,
+    };
+    // Relative source path is relative to the parent of the link
+    // (target) path
+    let src_path = match filepath.path.parent() {
+        Some(parent) if src.is_relative() => fileutil::lexical_normalize(&parent.join(src)),
+        _ => fileutil::lexical_normalize(src),
+    };
+    Some(src_path)
+}
+
+/// Validates that the symlink sources across all groups in the
+/// snapshot are not themselves slated to become symlinks, returning
+/// the error for every group in which such a chain or cycle starts
+///
+/// Otherwise the intended symlinks would form a chain or a cycle,
+/// neither of which can be caught by validating the groups
+/// individually. Implicit source paths i.e. the keepers of the groups
+/// are followed too, so that a chain continuing through a path that's
+/// to be symlinked to the keeper of its own group is also caught.
+fn symlink_graph_errors(snap: &Snapshot) -> HashMap<&Checksum, Error> {
+    let mut edges: BTreeMap<PathBuf, (&Checksum, PathBuf)> = BTreeMap::new();
+    for (hash, filepaths) in snap.duplicates.iter() {
+        let keeper = find_keeper(filepaths).map(|k| fileutil::lexical_normalize(&k.path));
+        for filepath in filepaths.iter() {
+            let src = match &filepath.op {
+                FileOp::Symlink { source: Some(_) } => explicit_link_source(filepath),
+                FileOp::Symlink { source: None } => keeper.clone(),
+                _ => None,
+            };
+            if let Some(src) = src {
+                edges.insert(fileutil::lexical_normalize(&filepath.path), (hash, src));
+            }
+        }
+    }
+    let render = |chain: &[&PathBuf]| {
+        chain
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<String>>()
+            .join(" -> ")
+    };
+    let mut errors = HashMap::new();
+    for (path, (hash, src)) in edges.iter() {
+        if errors.contains_key(hash) || !edges.contains_key(src) {
+            continue;
+        }
+        let mut chain = vec![path, src];
+        let mut curr = src;
+        let mut is_cycle = false;
+        while let Some((_, next)) = edges.get(curr) {
+            is_cycle = chain.contains(&next);
+            chain.push(next);
+            if is_cycle {
+                break;
+            }
+            curr = next;
+        }
+        let err = if is_cycle {
+            Error::OpNotAllowed(format!("Symlinks form a cycle: {}", render(&chain)))
+        } else {
+            Error::OpNotAllowed(format!(
+                "Symlink source path {} of {} is itself marked 'symlink': {}",
+                src.display(),
+                path.display(),
+                render(&chain)
+            ))
+        };
+        errors.insert(*hash, err);
+    }
+    errors
+}
+
+/// Validates that the explicitly specified symlink and hardlink source
+/// paths that are part of the group are marked 'keep'
+///
+/// Otherwise the source path would get deleted or replaced with a
+/// symlink itself, leaving a broken (or chained) symlink behind. As
+/// the actions are executed in parallel, a hardlink could similarly
+/// end up not being created or pointing to the replaced source. Note
+/// that implicit source paths are always the keeper of the group.
+fn validate_link_sources(filepaths: &[FilePath]) -> Result<(), Error> {
+    for filepath in filepaths.iter() {
+        let src_path = match explicit_link_source(filepath) {
+            Some(p) => p,
+            None => continue,
+        };
+        let src_filepath = filepaths
+            .iter()
+            .find(|fp| fileutil::lexical_normalize(&fp.path) == src_path);
+        if let Some(fp) = src_filepath {
+            if fp.op != FileOp::Keep {
+                return Err(Error::OpNotAllowed(format!(
+                    "{} source path {} of {} is marked '{}' instead of 'keep'",
+                    if let FileOp::Hardlink { .. } = filepath.op {
+                        "Hardlink"
+                    } else {
+                        "Symlink"
+                    },
+                    fp.path.display(),
+                    filepath.path.display(),
+                    fp.op.keyword()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validates that the contents of all the (regular) files in the group
+/// are exactly the same by comparing them byte-by-byte
+///
+/// This is required only if the snapshot was generated in quick mode,
+/// in which case the files are only known to have the same xxh3
+/// checksum and validating the checksum alone wouldn't catch a hash
+/// collision. Symlinks and missing files are skipped. Files not hashed
+/// raw as per the `content_hash` mode are compared using the contents
+/// that were hashed (see `ContentHash::contents`).
+fn validate_contents(filepaths: &[FilePath], content_hash: ContentHash) -> Result<(), Error> {
+    let mut paths = filepaths
+        .iter()
+        .map(|fp| &fp.path)
+        .filter(|p| !p.is_symlink() && p.is_file());
+    let first = match paths.next() {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    for path in paths {
+        let is_equal = if content_hash.applies_to(first) || content_hash.applies_to(path) {
+            content_hash.contents(first).map_err(Error::Io)?
+                == content_hash.contents(path).map_err(Error::Io)?
+        } else {
+            fileutil::files_equal(first, path).map_err(Error::Io)?
+        };
+        if !is_equal {
+            return Err(Error::ContentMismatch {
+                path: path.display().to_string(),
+                other_path: first.display().to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
 fn validate_path<'a>(
-    rootdir: &Path,
+    rootdirs: &[&Path],
     hash: &Checksum,
     filepath: &'a FilePath,
     keeper: Option<&'a FilePath>,
+    generated_at: Option<&DateTime<FixedOffset>>,
+    checksums: &mut Checksums,
 ) -> Result<Action<'a>, Error> {
     let path = &filepath.path;
 
-    // If the path is external to the rootdir, return an error right
-    // away
-    if !fileutil::within_rootdir(rootdir, path) {
+    // If the path is external to the rootdir(s), return an error
+    // right away
+    if !within_any_rootdir(rootdirs, path) {
         return Err(Error::CorruptSnapshot(format!(
             "Path {} is external to the rootdir",
             path.display()
         )));
     }
 
+    // Unless ignored, the mtime is checked before the op specific
+    // validations. Note that all the groups are validated before any
+    // of the actions get executed, so a file modified in between is
+    // not caught here.
+    if let Some(ts) = generated_at {
+        validate_mtime(path, ts)?;
+    }
+
     let action = match &filepath.op {
-        FileOp::Keep => validate_path_to_keep(filepath, hash)?,
+        FileOp::Keep => validate_path_to_keep(filepath, hash, checksums)?,
         FileOp::Symlink { source } => {
             // Assuming that the call to `validate_group` must have
             // validated that there's at least one 'keep' entry,
             // there's no need to handle None value.
             let keeper_path = &keeper.unwrap().path;
-            validate_path_to_symlink(filepath, source.as_ref(), keeper_path, hash)?
+            validate_path_to_symlink(
+                rootdirs,
+                filepath,
+                source.as_ref(),
+                keeper_path,
+                hash,
+                checksums,
+            )?
+        }
+        FileOp::Hardlink { source } => {
+            // Same as in case of symlink, the keeper is guaranteed to
+            // be present
+            let keeper_path = &keeper.unwrap().path;
+            validate_path_to_hardlink(
+                rootdirs,
+                filepath,
+                source.as_ref(),
+                keeper_path,
+                hash,
+                checksums,
+            )?
         }
-        FileOp::Delete => validate_path_to_delete(filepath, hash)?,
+        // Relative dest paths are relative to the primary rootdir
+        FileOp::Move { dest } => {
+            validate_path_to_move(rootdirs[0], filepath, dest, hash, checksums)?
+        }
+        FileOp::Delete => validate_path_to_delete(filepath, hash, checksums)?,
+        FileOp::Trash => validate_path_to_trash(filepath, hash, checksums)?,
     };
 
     Ok(action)
 }
 
+/// Groups skipped due to validation errors, along with the errors
+pub type SkippedGroups<'a> = Vec<(&'a Checksum, Error)>;
+
+/// Problems found in a single group, along with the path (unless the
+/// problem concerns the group as a whole)
+type GroupProblems<'a> = Vec<(Option<&'a Path>, Error)>;
+
+/// Validates a single group of duplicates and returns the actions
+/// for all the paths in it, or the problems found
+///
+/// A failure of the checks concerning the group as a whole (e.g.
+/// missing keeper) is the only problem reported. Otherwise every path
+/// is validated, stopping at the first invalid one if `fail_fast` is
+/// true, and finally the move dests are validated against the ones
+/// of the groups validated so far (`move_dests`).
+fn validate_duplicate_group<'a>(
+    snap: &'a Snapshot,
+    hash: &Checksum,
+    filepaths: &'a [FilePath],
+    is_full_deletion_allowed: &bool,
+    is_mtime_ignored: &bool,
+    move_dests: &mut HashSet<PathBuf>,
+    fail_fast: bool,
+) -> Result<Vec<Action<'a>>, GroupProblems<'a>> {
+    let keeper = find_keeper(filepaths);
+
+    // Full deletion may also be allowed for individual groups
+    // through the snapshot
+    let is_group_full_deletion_allowed =
+        *is_full_deletion_allowed || snap.full_deletion_groups.contains(hash);
+    validate_group(hash, filepaths, keeper, &is_group_full_deletion_allowed)
+        .and_then(|_| validate_link_sources(filepaths))
+        .and_then(|_| {
+            if snap.quick_mode {
+                validate_contents(filepaths, snap.content_hash)
+            } else {
+                Ok(())
+            }
+        })
+        .map_err(|e| vec![(None, e)])?;
+
+    // Files are hashed at most once per group, even if they are
+    // referred to multiple times e.g. as symlink source paths
+    let mut checksums = Checksums::with_content_hash(snap.content_hash);
+    let mut actions = Vec::with_capacity(filepaths.len());
+    let mut problems: GroupProblems = Vec::new();
+    let generated_at = if *is_mtime_ignored {
+        None
+    } else {
+        Some(&snap.generated_at)
+    };
+    let rootdirs = snap.rootdirs();
+    for filepath in filepaths.iter() {
+        match validate_path(
+            &rootdirs,
+            hash,
+            filepath,
+            keeper,
+            generated_at,
+            &mut checksums,
+        ) {
+            Ok(action) => actions.push(action),
+            Err(e) => {
+                problems.push((Some(&filepath.path), e));
+                if fail_fast {
+                    return Err(problems);
+                }
+            }
+        }
+    }
+    if let Err(e) = validate_move_dests(&actions, move_dests) {
+        problems.push((None, e));
+    }
+    if problems.is_empty() {
+        Ok(actions)
+    } else {
+        Err(problems)
+    }
+}
+
+/// Validates that no two files are to be moved to the same dest
+/// path, as that would result in one of them getting overwritten
+///
+/// The dest paths are added to `move_dests` only if all of them are
+/// unique, so that the actions of a group can be skipped without
+/// affecting the validation of other groups.
+fn validate_move_dests(actions: &[Action], move_dests: &mut HashSet<PathBuf>) -> Result<(), Error> {
+    let mut group_dests = HashSet::new();
+    for action in actions {
+        if let Action::Move { dest, .. } = action {
+            if move_dests.contains(dest) || !group_dests.insert(dest.clone()) {
+                return Err(Error::OpNotAllowed(format!(
+                    "Multiple files to be moved to the same destination: {}",
+                    dest.display()
+                )));
+            }
+        }
+    }
+    move_dests.extend(group_dests);
+    Ok(())
+}
+
 pub fn validate<'a>(
     snap: &'a Snapshot,
     is_full_deletion_allowed: &bool,
+    is_mtime_ignored: &bool,
 ) -> Result<Vec<Action<'a>>, Error> {
-    validate_rootdir(&snap.rootdir)?;
+    validate_groups(snap, is_full_deletion_allowed, is_mtime_ignored, false)
+        .map(|(actions, _)| actions)
+}
+
+/// Same as `validate` except that groups failing validation are
+/// skipped instead of failing the validation of the entire
+/// snapshot. Returns actions of all the valid groups along with the
+/// checksums of the skipped groups and the reason for skipping them.
+///
+/// Errors that concern the snapshot as a whole (e.g. a non-existent
+/// rootdir) still result in failure.
+pub fn validate_skipping_invalid<'a>(
+    snap: &'a Snapshot,
+    is_full_deletion_allowed: &bool,
+    is_mtime_ignored: &bool,
+) -> Result<(Vec<Action<'a>>, SkippedGroups<'a>), Error> {
+    validate_groups(snap, is_full_deletion_allowed, is_mtime_ignored, true)
+}
+
+fn validate_groups<'a>(
+    snap: &'a Snapshot,
+    is_full_deletion_allowed: &bool,
+    is_mtime_ignored: &bool,
+    skip_invalid: bool,
+) -> Result<(Vec<Action<'a>>, SkippedGroups<'a>), Error> {
+    for rootdir in snap.rootdirs() {
+        validate_rootdir(rootdir)?;
+    }
+    validate_unique_paths(snap)?;
+    let mut graph_errors = symlink_graph_errors(snap);
+
+    match &snap.verify_hash {
+        Some(vh) => info!(
+            "Duplicates in the snapshot were confirmed using {}",
+            vh.name()
+        ),
+        None => info!("Duplicates in the snapshot were not confirmed using a verification hash"),
+    }
 
     let mut actions: Vec<Action> = Vec::new();
+    let mut skipped: SkippedGroups = Vec::new();
+    let mut move_dests: HashSet<PathBuf> = HashSet::new();
     for (hash, filepaths) in snap.duplicates.iter() {
-        let keeper = find_keeper(filepaths);
+        let result = match graph_errors.remove(hash) {
+            Some(e) => Err(e),
+            // Only the first problem is of interest here
+            None => validate_duplicate_group(
+                snap,
+                hash,
+                filepaths,
+                is_full_deletion_allowed,
+                is_mtime_ignored,
+                &mut move_dests,
+                true,
+            )
+            .map_err(|mut problems| problems.remove(0).1),
+        };
+        match result {
+            Ok(group_actions) => actions.extend(group_actions),
+            Err(e) if skip_invalid => {
+                info!("Skipping invalid group {}: {}", hash, e.message());
+                skipped.push((hash, e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((actions, skipped))
+}
 
-        validate_group(hash, filepaths, keeper, is_full_deletion_allowed)?;
+/// Problems found while verifying the snapshot, along with the
+/// checksum of the group and the path (unless the problem concerns
+/// the group as a whole)
+pub type Problems<'a> = Vec<(&'a Checksum, Option<&'a Path>, Error)>;
 
-        for filepath in filepaths.iter() {
-            match validate_path(&snap.rootdir, hash, filepath, keeper) {
-                Ok(action) => actions.push(action),
-                Err(e) => return Err(e),
-            }
+/// Verifies every path in the snapshot against the files on disk,
+/// returning all the problems found instead of only the first one
+///
+/// The checks are the same as in `validate`, except that a group
+/// failing the checks concerning the group as a whole (e.g. missing
+/// keeper) is reported once, whereas the paths of the other groups are
+/// checked individually so that every checksum mismatch, missing file
+/// etc. gets reported. Checksums are always computed by reading the
+/// files as the hash cache is never used for validation. Groups are
+/// verified in the order of their checksums.
+///
+/// Errors that concern the snapshot as a whole (e.g. a non-existent
+/// rootdir) still result in failure.
+pub fn verify<'a>(
+    snap: &'a Snapshot,
+    is_full_deletion_allowed: &bool,
+    is_mtime_ignored: &bool,
+) -> Result<Problems<'a>, Error> {
+    for rootdir in snap.rootdirs() {
+        validate_rootdir(rootdir)?;
+    }
+    validate_unique_paths(snap)?;
+    let mut graph_errors = symlink_graph_errors(snap);
+
+    let mut groups = snap.duplicates.iter().collect::<Vec<_>>();
+    groups.sort_by_key(|(hash, _)| hash.value());
+    let mut problems: Problems = Vec::new();
+    let mut move_dests: HashSet<PathBuf> = HashSet::new();
+    for (hash, filepaths) in groups {
+        let result = match graph_errors.remove(hash) {
+            Some(e) => Err(vec![(None, e)]),
+            None => validate_duplicate_group(
+                snap,
+                hash,
+                filepaths,
+                is_full_deletion_allowed,
+                is_mtime_ignored,
+                &mut move_dests,
+                false,
+            ),
+        };
+        if let Err(group_problems) = result {
+            problems.extend(group_problems.into_iter().map(|(path, e)| (hash, path, e)));
         }
     }
-    Ok(actions)
+    Ok(problems)
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use super::*;
     use serial_test::serial;
@@ -488,4 +1318,807 @@ mod tests {
         // teardown
         fs::remove_dir_all(".tmp-test-data").unwrap();
     }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_validate_path_to_hardlink() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        let keeper = data_dir.join("foo/1.txt");
+        fs::create_dir(keeper.parent().unwrap()).unwrap();
+        fs::write(&keeper, "Foo 1").unwrap();
+        fs::write(data_dir.join("2.txt"), "Foo 1").unwrap();
+        fs::hard_link(&keeper, data_dir.join("3.txt")).unwrap();
+        fs::write(data_dir.join("4.txt"), "Cat 1").unwrap();
+        let hash = Checksum::of_file(&keeper).unwrap();
+        let rootdirs = [data_dir.as_path()];
+        let filepath = |p: &str, source: Option<&str>| FilePath {
+            path: data_dir.join(p),
+            op: FileOp::Hardlink {
+                source: source.map(PathBuf::from),
+            },
+        };
+
+        // Regular file to be replaced with hardlink to the keeper
+        let fp = filepath("2.txt", None);
+        match validate_path_to_hardlink(
+            &rootdirs,
+            &fp,
+            None,
+            &keeper,
+            &hash,
+            &mut Checksums::default(),
+        ) {
+            Ok(Action::Hardlink {
+                source, is_no_op, ..
+            }) => {
+                assert_eq!(keeper, source);
+                assert!(!is_no_op);
+            }
+            _ => assert!(false),
+        }
+
+        // Already a hardlink to the (relative) source
+        let fp = filepath("3.txt", Some("foo/1.txt"));
+        let src = PathBuf::from("foo/1.txt");
+        match validate_path_to_hardlink(
+            &rootdirs,
+            &fp,
+            Some(&src),
+            &keeper,
+            &hash,
+            &mut Checksums::default(),
+        ) {
+            Ok(Action::Hardlink {
+                source, is_no_op, ..
+            }) => {
+                assert_eq!(keeper, source);
+                assert!(is_no_op);
+            }
+            _ => assert!(false),
+        }
+
+        // Specified source path having different contents
+        let fp = filepath("2.txt", Some("4.txt"));
+        let src = PathBuf::from("4.txt");
+        match validate_path_to_hardlink(
+            &rootdirs,
+            &fp,
+            Some(&src),
+            &keeper,
+            &hash,
+            &mut Checksums::default(),
+        ) {
+            Err(Error::OpNotPossible(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        // Non-existing path
+        let fp = filepath("5.txt", None);
+        match validate_path_to_hardlink(
+            &rootdirs,
+            &fp,
+            None,
+            &keeper,
+            &hash,
+            &mut Checksums::default(),
+        ) {
+            Err(Error::OpNotPossible(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        // Specified source path that doesn't exist
+        let fp = filepath("2.txt", Some("6.txt"));
+        let src = PathBuf::from("6.txt");
+        match validate_path_to_hardlink(
+            &rootdirs,
+            &fp,
+            Some(&src),
+            &keeper,
+            &hash,
+            &mut Checksums::default(),
+        ) {
+            Err(Error::OpNotPossible(msg)) => assert!(msg.contains("no longer exists")),
+            _ => assert!(false),
+        }
+
+        // Specified source path external to the rootdir
+        let fp = filepath("2.txt", Some("1.txt"));
+        let src = PathBuf::from("1.txt");
+        let rootdirs = [keeper.parent().unwrap()];
+        match validate_path_to_hardlink(
+            &rootdirs,
+            &fp,
+            Some(&src),
+            &keeper,
+            &hash,
+            &mut Checksums::default(),
+        ) {
+            Err(Error::CorruptSnapshot(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_validate_path_to_move() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        let rootdir = data_dir.join("root");
+        fs::create_dir(&rootdir).unwrap();
+        fs::create_dir(data_dir.join("quarantine")).unwrap();
+        fs::write(rootdir.join("1.txt"), "Foo 1").unwrap();
+        fs::write(data_dir.join("quarantine/2.txt"), "Foo 1").unwrap();
+        let hash = Checksum::of_file(&rootdir.join("1.txt")).unwrap();
+        let filepath = |p: &str, dest: &Path| FilePath {
+            path: rootdir.join(p),
+            op: FileOp::Move {
+                dest: dest.to_path_buf(),
+            },
+        };
+
+        // Dest inside a non-existing dir
+        let dest = data_dir.join("quarantine/foo/1.txt");
+        let fp = filepath("1.txt", &dest);
+        match validate_path_to_move(&rootdir, &fp, &dest, &hash, &mut Checksums::default()) {
+            Ok(Action::Move { is_no_op, .. }) => assert!(!is_no_op),
+            _ => assert!(false),
+        }
+
+        // Relative dest that already exists
+        let dest = PathBuf::from("../quarantine/2.txt");
+        let fp = filepath("1.txt", &dest);
+        match validate_path_to_move(&rootdir, &fp, &dest, &hash, &mut Checksums::default()) {
+            Err(Error::OpNotAllowed(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        // File already moved to the dest
+        let dest = data_dir.join("quarantine/2.txt");
+        let fp = filepath("2.txt", &dest);
+        match validate_path_to_move(&rootdir, &fp, &dest, &hash, &mut Checksums::default()) {
+            Ok(Action::Move { is_no_op, .. }) => assert!(is_no_op),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_validate_path_to_trash() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        fs::write(data_dir.join("1.txt"), "Foo 1").unwrap();
+        fs::write(data_dir.join("2.txt"), "Cat 1").unwrap();
+        let hash = Checksum::of_file(&data_dir.join("1.txt")).unwrap();
+        let filepath = |p: &str| FilePath {
+            path: data_dir.join(p),
+            op: FileOp::Trash,
+        };
+
+        match validate_path_to_trash(&filepath("1.txt"), &hash, &mut Checksums::default()) {
+            Ok(Action::Trash { is_no_op, .. }) => assert!(!is_no_op),
+            _ => assert!(false),
+        }
+
+        // Already deleted file
+        match validate_path_to_trash(&filepath("3.txt"), &hash, &mut Checksums::default()) {
+            Ok(Action::Trash { is_no_op, .. }) => assert!(is_no_op),
+            _ => assert!(false),
+        }
+
+        // File with different contents
+        match validate_path_to_trash(&filepath("2.txt"), &hash, &mut Checksums::default()) {
+            Err(Error::ChecksumMismatch { .. }) => assert!(true),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    fn test_error_report() {
+        let e = Error::ChecksumMismatch {
+            path: String::from("foo/1.txt"),
+            actual: String::from("123"),
+            expected: String::from("456"),
+        };
+        let report = Report {
+            valid: false,
+            pending: 0,
+            errors: vec![ErrorReport::from(&e)],
+        };
+        assert_eq!(
+            serde_json::json!({
+                "valid": false,
+                "pending": 0,
+                "errors": [{
+                    "kind": "ChecksumMismatch",
+                    "message": "Checksum mismatch for foo/1.txt: expected 456, found 123"
+                }]
+            }),
+            serde_json::to_value(&report).unwrap()
+        );
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_validate_mtime() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let path = Path::new(".tmp-test-data").join("1.txt");
+        fs::write(&path, "ONE").unwrap();
+        let modified_at: DateTime<Utc> = path.metadata().unwrap().modified().unwrap().into();
+
+        let generated_at = (modified_at + chrono::Duration::seconds(1)).fixed_offset();
+        assert!(validate_mtime(&path, &generated_at).is_ok());
+
+        let generated_at = (modified_at - chrono::Duration::seconds(10)).fixed_offset();
+        match validate_mtime(&path, &generated_at) {
+            Err(Error::StaleSnapshot { path: p, .. }) => {
+                assert_eq!(path.display().to_string(), p)
+            }
+            _ => assert!(false),
+        }
+
+        // Symlinks and missing paths are ignored
+        let link = Path::new(".tmp-test-data").join("2.txt");
+        std::os::unix::fs::symlink("1.txt", &link).unwrap();
+        assert!(validate_mtime(&link, &generated_at).is_ok());
+        let missing = Path::new(".tmp-test-data").join("3.txt");
+        assert!(validate_mtime(&missing, &generated_at).is_ok());
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    fn test_validate_link_sources() {
+        let fp = |p: &str, op: FileOp| FilePath {
+            path: PathBuf::from(p),
+            op,
+        };
+        let symlink = |s: &str| FileOp::Symlink {
+            source: Some(PathBuf::from(s)),
+        };
+
+        // Source marked 'keep' (relative as well as absolute)
+        assert!(validate_link_sources(&[
+            fp("/foo/a.txt", FileOp::Keep),
+            fp("/foo/bar/b.txt", symlink("../a.txt")),
+            fp("/foo/c.txt", symlink("/foo/a.txt")),
+        ])
+        .is_ok());
+
+        // Source outside the group
+        assert!(validate_link_sources(&[
+            fp("/foo/a.txt", FileOp::Keep),
+            fp("/foo/b.txt", symlink("x.txt")),
+        ])
+        .is_ok());
+
+        // Source marked for deletion
+        match validate_link_sources(&[
+            fp("/foo/a.txt", FileOp::Keep),
+            fp("/foo/b.txt", FileOp::Delete),
+            fp("/foo/bar/c.txt", symlink("../b.txt")),
+        ]) {
+            Err(Error::OpNotAllowed(msg)) => {
+                assert!(msg.contains("/foo/b.txt"));
+                assert!(msg.contains("/foo/bar/c.txt"));
+            }
+            _ => assert!(false),
+        }
+
+        // Source that's another symlink
+        match validate_link_sources(&[
+            fp("/foo/a.txt", FileOp::Keep),
+            fp("/foo/b.txt", FileOp::Symlink { source: None }),
+            fp("/foo/c.txt", symlink("b.txt")),
+        ]) {
+            Err(Error::OpNotAllowed(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        // Hardlink sources must be marked 'keep' too
+        let hardlink = |s: &str| FileOp::Hardlink {
+            source: Some(PathBuf::from(s)),
+        };
+        assert!(validate_link_sources(&[
+            fp("/foo/a.txt", FileOp::Keep),
+            fp("/foo/b.txt", hardlink("a.txt")),
+        ])
+        .is_ok());
+        for op in [
+            FileOp::Delete,
+            FileOp::Trash,
+            FileOp::Symlink { source: None },
+            FileOp::Move {
+                dest: PathBuf::from("/quarantine/c.txt"),
+            },
+        ] {
+            match validate_link_sources(&[
+                fp("/foo/a.txt", FileOp::Keep),
+                fp("/foo/b.txt", hardlink("c.txt")),
+                fp("/foo/c.txt", op),
+            ]) {
+                Err(Error::OpNotAllowed(msg)) => {
+                    assert!(msg.starts_with("Hardlink source path /foo/c.txt of /foo/b.txt"))
+                }
+                _ => assert!(false),
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_unique_paths() {
+        let parse = |lines: &[&str]| {
+            let mut input = vec![
+                "#! Root Directory: /foo",
+                "#! Generated at: Sun, 24 Dec 2023 13:07:06 +0530",
+            ];
+            input.extend_from_slice(lines);
+            crate::snapshot::textformat::parse(input.into_iter().map(|s| s.to_owned()).collect())
+                .unwrap()
+        };
+        let snap = parse(&[
+            "[1]",
+            "keep a.txt",
+            "delete b.txt",
+            "[2]",
+            "keep c.txt",
+            "delete d.txt",
+        ]);
+        assert!(validate_unique_paths(&snap).is_ok());
+
+        // Same path in the same group
+        let snap = parse(&["[1]", "keep a.txt", "delete b.txt", "keep ./b.txt"]);
+        match validate_unique_paths(&snap) {
+            Err(Error::DuplicatePath { path }) => assert_eq!("/foo/b.txt", path),
+            _ => assert!(false),
+        }
+
+        // Same path in different groups
+        let snap = parse(&[
+            "[1]",
+            "keep a.txt",
+            "delete b.txt",
+            "[2]",
+            "keep c.txt",
+            "delete a.txt",
+        ]);
+        match validate_unique_paths(&snap) {
+            Err(Error::DuplicatePath { path }) => assert_eq!("/foo/a.txt", path),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_cached_checksum() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        let path = data_dir.join("1.txt");
+        fs::write(&path, "ONE").unwrap();
+        fileutil::symlink(&path, &data_dir.join("2.txt")).unwrap();
+
+        let mut checksums = Checksums::default();
+        let expected = Checksum::of_file(&path).unwrap();
+        assert!(expected == cached_checksum(&path, &mut checksums).unwrap());
+        // Once computed, the file isn't read again, including when
+        // it's referred to through a symlink
+        fs::write(&path, "TWO").unwrap();
+        assert!(expected == cached_checksum(&path, &mut checksums).unwrap());
+        assert!(expected == cached_checksum(&data_dir.join("2.txt"), &mut checksums).unwrap());
+        assert_eq!(1, checksums.computed.len());
+        // Missing files result in an error
+        assert!(cached_checksum(&data_dir.join("3.txt"), &mut checksums).is_err());
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_validate_path_to_symlink_external_source() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        let rootdir = data_dir.join("root");
+        fs::create_dir_all(rootdir.join("foo")).unwrap();
+        let keeper = rootdir.join("1.txt");
+        for p in [&keeper, &rootdir.join("foo/2.txt"), &data_dir.join("3.txt")] {
+            fs::write(p, "Foo 1").unwrap();
+        }
+        let hash = Checksum::of_file(&keeper).unwrap();
+        let validate = |src: &str| {
+            let fp = FilePath {
+                path: rootdir.join("foo/2.txt"),
+                op: FileOp::Symlink {
+                    source: Some(PathBuf::from(src)),
+                },
+            };
+            let source = PathBuf::from(src);
+            validate_path_to_symlink(
+                &[&rootdir],
+                &fp,
+                Some(&source),
+                &keeper,
+                &hash,
+                &mut Checksums::default(),
+            )
+            .map(|_| ())
+        };
+
+        assert!(validate("../1.txt").is_ok());
+        assert!(validate(keeper.to_str().unwrap()).is_ok());
+        match validate("../../3.txt") {
+            Err(Error::CorruptSnapshot(msg)) => assert!(msg.contains("external to the rootdir")),
+            _ => assert!(false),
+        }
+        match validate(data_dir.join("3.txt").to_str().unwrap()) {
+            Err(Error::CorruptSnapshot(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_validate_contents() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        fs::write(data_dir.join("1.txt"), "Foo 1").unwrap();
+        fs::write(data_dir.join("2.txt"), "Foo 1").unwrap();
+        fs::write(data_dir.join("3.txt"), "Foo 2").unwrap();
+        fileutil::symlink(&data_dir.join("3.txt"), &data_dir.join("4.txt")).unwrap();
+        let fp = |p: &str, op: FileOp| FilePath {
+            path: data_dir.join(p),
+            op,
+        };
+
+        // Symlinks and missing files are skipped
+        assert!(validate_contents(
+            &[
+                fp("1.txt", FileOp::Keep),
+                fp("2.txt", FileOp::Delete),
+                fp("4.txt", FileOp::Symlink { source: None }),
+                fp("5.txt", FileOp::Delete),
+            ],
+            ContentHash::Raw
+        )
+        .is_ok());
+
+        // Files having the same checksum (i.e. a collision is
+        // simulated) but different contents
+        match validate_contents(
+            &[fp("1.txt", FileOp::Keep), fp("3.txt", FileOp::Delete)],
+            ContentHash::Raw,
+        ) {
+            Err(Error::ContentMismatch { path, other_path }) => {
+                assert_eq!(data_dir.join("3.txt").display().to_string(), path);
+                assert_eq!(data_dir.join("1.txt").display().to_string(), other_path);
+            }
+            _ => assert!(false),
+        }
+
+        // Images are compared excluding the metadata (a comment
+        // segment here)
+        let jpeg = |data: u8, comment: &[u8]| {
+            let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xFE, 0x00, 2 + comment.len() as u8];
+            bytes.extend_from_slice(comment);
+            bytes.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x03, 0x01, data, 0xFF, 0xD9]);
+            bytes
+        };
+        fs::write(data_dir.join("6.jpg"), jpeg(0xAB, b"x")).unwrap();
+        fs::write(data_dir.join("7.jpg"), jpeg(0xAB, b"yz")).unwrap();
+        fs::write(data_dir.join("8.jpg"), jpeg(0xCD, b"x")).unwrap();
+        assert!(validate_contents(
+            &[fp("6.jpg", FileOp::Keep), fp("7.jpg", FileOp::Delete)],
+            ContentHash::Images
+        )
+        .is_ok());
+        match validate_contents(
+            &[fp("6.jpg", FileOp::Keep), fp("8.jpg", FileOp::Delete)],
+            ContentHash::Images,
+        ) {
+            Err(Error::ContentMismatch { path, .. }) => {
+                assert_eq!(data_dir.join("8.jpg").display().to_string(), path)
+            }
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_validate_skipping_invalid() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        fs::write(data_dir.join("a.txt"), "A").unwrap();
+        fs::write(data_dir.join("b.txt"), "A").unwrap();
+        fs::write(data_dir.join("c.txt"), "C").unwrap();
+        fs::write(data_dir.join("d.txt"), "C").unwrap();
+        let hash_a = Checksum::of_file(&data_dir.join("a.txt")).unwrap();
+        let hash_c = Checksum::of_file(&data_dir.join("c.txt")).unwrap();
+        let parse = |rootdir: &Path| {
+            let input = vec![
+                format!("#! Root Directory: {}", rootdir.display()),
+                "#! Generated at: Sun, 24 Dec 2023 13:07:06 +0530".to_owned(),
+                "#! Checksum Format: hex".to_owned(),
+                format!("[{}]", hash_a),
+                "keep a.txt".to_owned(),
+                "delete b.txt".to_owned(),
+                // Full deletion of the group is not allowed
+                format!("[{}]", hash_c),
+                "delete c.txt".to_owned(),
+                "delete d.txt".to_owned(),
+            ];
+            crate::snapshot::textformat::parse(input).unwrap()
+        };
+
+        let snap = parse(&data_dir);
+        assert!(validate(&snap, &false, &true).is_err());
+        match validate_skipping_invalid(&snap, &false, &true) {
+            Ok((actions, skipped)) => {
+                assert_eq!(2, actions.len());
+                assert_eq!(1, skipped.len());
+                assert!(*skipped[0].0 == hash_c);
+                match skipped[0].1 {
+                    Error::OpNotAllowed(_) => assert!(true),
+                    _ => assert!(false),
+                }
+            }
+            Err(_) => assert!(false),
+        }
+
+        // Errors concerning the entire snapshot are not skipped
+        let snap = parse(&data_dir.join("nonexistent"));
+        match validate_skipping_invalid(&snap, &false, &true) {
+            Err(Error::RootDir(_)) => assert!(true),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_validate_path_to_symlink_missing_source() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create test data dir");
+        let rootdir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        let keeper = rootdir.join("1.txt");
+        let target = rootdir.join("2.txt");
+        for p in [&keeper, &target] {
+            fs::write(p, "Foo 1").unwrap();
+        }
+        fs::create_dir(rootdir.join("3.txt")).unwrap();
+        let hash = Checksum::of_file(&keeper).unwrap();
+        let validate = |src: Option<&str>| {
+            let source = src.map(PathBuf::from);
+            let fp = FilePath {
+                path: target.clone(),
+                op: FileOp::Symlink {
+                    source: source.clone(),
+                },
+            };
+            validate_path_to_symlink(
+                &[&rootdir],
+                &fp,
+                source.as_ref(),
+                &keeper,
+                &hash,
+                &mut Checksums::default(),
+            )
+            .map(|_| ())
+        };
+
+        assert!(validate(None).is_ok());
+        match validate(Some("3.txt")) {
+            Err(Error::OpNotPossible(msg)) => assert!(msg.contains("is not a regular file")),
+            _ => assert!(false),
+        }
+
+        fs::remove_file(&keeper).unwrap();
+        match validate(None) {
+            Err(Error::OpNotPossible(msg)) => {
+                assert!(msg.contains("1.txt"));
+                assert!(msg.contains("no longer exists"));
+            }
+            _ => assert!(false),
+        }
+        match validate(Some("1.txt")) {
+            Err(Error::OpNotPossible(msg)) => assert!(msg.contains("no longer exists")),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    fn test_validate_symlink_graph() {
+        let parse = |lines: &[&str]| {
+            let mut input = vec![
+                "#! Root Directory: /foo",
+                "#! Generated at: Sun, 24 Dec 2023 13:07:06 +0530",
+            ];
+            input.extend_from_slice(lines);
+            crate::snapshot::textformat::parse(input.into_iter().map(|s| s.to_owned()).collect())
+                .unwrap()
+        };
+        let snap = parse(&[
+            "[1]",
+            "keep a.txt",
+            "symlink bar/b.txt -> ../a.txt",
+            "[2]",
+            "keep c.txt",
+            "symlink d.txt",
+        ]);
+        assert!(symlink_graph_errors(&snap).is_empty());
+
+        // Cycle across groups, reported for both the groups
+        let snap = parse(&[
+            "[1]",
+            "keep a.txt",
+            "symlink b.txt -> d.txt",
+            "[2]",
+            "keep c.txt",
+            "symlink d.txt -> b.txt",
+        ]);
+        let mut errors = symlink_graph_errors(&snap);
+        assert_eq!(2, errors.len());
+        match errors.remove(&Checksum::new(1)) {
+            Some(Error::OpNotAllowed(msg)) => {
+                assert_eq!(
+                    "Symlinks form a cycle: /foo/b.txt -> /foo/d.txt -> /foo/b.txt",
+                    msg
+                )
+            }
+            _ => assert!(false),
+        }
+
+        // Chain across groups, reported only for the group in which
+        // it starts
+        let snap = parse(&[
+            "[1]",
+            "keep a.txt",
+            "symlink b.txt -> d.txt",
+            "[2]",
+            "keep c.txt",
+            "symlink d.txt -> c.txt",
+        ]);
+        let mut errors = symlink_graph_errors(&snap);
+        assert_eq!(1, errors.len());
+        match errors.remove(&Checksum::new(1)) {
+            Some(Error::OpNotAllowed(msg)) => {
+                assert!(msg.contains("/foo/b.txt -> /foo/d.txt -> /foo/c.txt"))
+            }
+            _ => assert!(false),
+        }
+
+        // Chain continuing through an implicit source
+        let snap = parse(&[
+            "[1]",
+            "keep a.txt",
+            "symlink b.txt -> d.txt",
+            "[2]",
+            "keep c.txt",
+            "symlink d.txt",
+        ]);
+        match symlink_graph_errors(&snap).remove(&Checksum::new(1)) {
+            Some(Error::OpNotAllowed(msg)) => {
+                assert!(msg.contains("/foo/b.txt -> /foo/d.txt -> /foo/c.txt"))
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_verify_symlink_source_path_normalized() {
+        let t = PathBuf::from("/private/tmp/bar/current");
+        let same = |i: &str, a: &str| {
+            verify_symlink_source_path(Path::new(i), Path::new(a), &t, true).unwrap()
+        };
+        assert!(same("../foo/1.txt", "./../foo/1.txt"));
+        assert!(same("../foo/1.txt", "../baz/../foo/1.txt"));
+        assert!(same("../bar/1.txt", "1.txt"));
+        assert!(same(
+            "/private/tmp/foo/1.txt",
+            "/private/tmp/bar/../foo/1.txt"
+        ));
+        // Style of the paths must be the same
+        assert!(!same("/private/tmp/foo/1.txt", "../foo/1.txt"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_verify() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").unwrap();
+        let rootdir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        for (p, contents) in [("1.txt", "ONE"), ("2.txt", "ONE"), ("3.txt", "ONE")] {
+            fs::write(rootdir.join(p), contents).unwrap();
+        }
+        fs::write(rootdir.join("4.txt"), "TWO").unwrap();
+        fs::write(rootdir.join("6.txt"), "TWO").unwrap();
+        let one = Checksum::of_file(&rootdir.join("1.txt")).unwrap();
+        let two = Checksum::of_file(&rootdir.join("4.txt")).unwrap();
+        let snap_input = [
+            format!("#! Root Directory: {}", rootdir.display()),
+            "#! Generated at: Tue, 12 Dec 2023 16:00:44 +0530".to_owned(),
+            "#! Checksum Format: hex".to_owned(),
+            "".to_owned(),
+            format!("[{}]", one),
+            "keep 1.txt".to_owned(),
+            "delete 2.txt".to_owned(),
+            "delete 3.txt".to_owned(),
+            "keep 5.txt".to_owned(),
+            "".to_owned(),
+            format!("[{}]", two),
+            "delete 4.txt".to_owned(),
+            "delete 6.txt".to_owned(),
+        ];
+        // One of the files is modified and another one doesn't exist
+        fs::write(rootdir.join("3.txt"), "THREE").unwrap();
+        let snap = crate::snapshot::textformat::parse(snap_input.to_vec()).unwrap();
+        let problems = verify(&snap, &false, &true).unwrap();
+        let summary = problems
+            .iter()
+            .map(|(hash, path, e)| {
+                (
+                    hash.value(),
+                    path.map(|p| p.strip_prefix(&rootdir).unwrap().to_path_buf()),
+                    e.kind(),
+                )
+            })
+            .collect::<Vec<(u64, Option<PathBuf>, &str)>>();
+        let mut expected = vec![
+            (
+                one.value(),
+                Some(PathBuf::from("3.txt")),
+                "ChecksumMismatch",
+            ),
+            (one.value(), Some(PathBuf::from("5.txt")), "OpNotPossible"),
+            (two.value(), None, "OpNotAllowed"),
+        ];
+        expected.sort_by_key(|(hash, _, _)| *hash);
+        assert_eq!(expected, summary);
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_validate_rootdir() {
+        fs::remove_dir_all(".tmp-test-data").unwrap_or(());
+        fs::create_dir(".tmp-test-data").expect("Couldn't create .tmp-test-data");
+        let data_dir = Path::new(".tmp-test-data").canonicalize().unwrap();
+        fs::write(data_dir.join("1.txt"), "ONE").unwrap();
+
+        assert!(validate_rootdir(&data_dir).is_ok());
+        match validate_rootdir(&data_dir.join("1.txt")) {
+            Err(Error::RootDir(msg)) => assert!(msg.ends_with("is not a directory")),
+            _ => assert!(false),
+        }
+        match validate_rootdir(&data_dir.join("2")) {
+            Err(Error::RootDir(msg)) => assert!(msg.ends_with("doesn't exist")),
+            _ => assert!(false),
+        }
+
+        fs::remove_dir_all(".tmp-test-data").unwrap();
+    }
 }
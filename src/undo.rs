@@ -0,0 +1,257 @@
+use crate::error::AppError;
+use crate::executor::BACKUP_FILES_DIR;
+use crate::fileutil::{self, normalize_path};
+use log::info;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Operation for restoring a file from the backup, depending on the
+/// current state of the original path
+#[derive(Debug, PartialEq)]
+enum RestoreOp {
+    // The original file doesn't exist anymore (deleted or moved)
+    Create,
+    // The original file has been replaced with a symlink
+    ReplaceSymlink,
+    // The original file has been replaced with a hardlink
+    ReplaceHardlink,
+    // The original file has different contents than the backup
+    Overwrite,
+    // The original file is intact
+    NoOp,
+}
+
+#[derive(Debug)]
+struct Restore {
+    backup_path: PathBuf,
+    path: PathBuf,
+    op: RestoreOp,
+}
+
+impl Restore {
+    fn dry_run(&self, rootdir: &Path) {
+        // Use relative path in dry-run output
+        let rel_path = normalize_path(&self.path, true, rootdir).unwrap();
+        let msg = match self.op {
+            RestoreOp::Create => "File to be restored",
+            RestoreOp::ReplaceSymlink => "Symlink to be replaced with the restored file",
+            RestoreOp::ReplaceHardlink => "Hardlink to be replaced with the restored file",
+            RestoreOp::Overwrite => "File to be overwritten with the restored file",
+            RestoreOp::NoOp => "[NO-OP] File is intact",
+        };
+        eprintln!("[DRY RUN] {}: {}", msg, rel_path.display());
+    }
+
+    fn execute(&self, rootdir: &Path) -> Result<(), AppError> {
+        // Show relative path in log messages
+        let rel_path = normalize_path(&self.path, true, rootdir).unwrap();
+        match self.op {
+            RestoreOp::NoOp => {
+                info!("File is intact (no-op): {}", rel_path.display());
+                return Ok(());
+            }
+            RestoreOp::Create => {
+                if let Some(parent) = self.path.parent() {
+                    fs::create_dir_all(parent).map_err(AppError::Io)?;
+                }
+            }
+            RestoreOp::ReplaceSymlink | RestoreOp::ReplaceHardlink | RestoreOp::Overwrite => {
+                // The existing path is removed first so that the
+                // source of a symlink or hardlink isn't overwritten
+                fs::remove_file(&self.path).map_err(AppError::Io)?;
+            }
+        }
+        info!("Restoring file from backup: {}", rel_path.display());
+        fs::copy(&self.backup_path, &self.path).map_err(AppError::Io)?;
+        Ok(())
+    }
+}
+
+/// Recursively collects paths of all files under the `dir`
+fn backed_up_files(dir: &Path, result: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            backed_up_files(&path, result)?;
+        } else {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Determines how the backed up file needs to be restored to `path`
+fn restore_op(backup_path: &Path, path: &Path, force: bool) -> Result<RestoreOp, AppError> {
+    let metadata = match path.symlink_metadata() {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(RestoreOp::Create),
+        Err(e) => return Err(AppError::Io(e)),
+    };
+    if metadata.is_symlink() {
+        Ok(RestoreOp::ReplaceSymlink)
+    } else if !metadata.is_file() {
+        Err(AppError::Fs(format!(
+            "Can't restore file as the path is not a regular file: {}",
+            path.display()
+        )))
+    } else if fileutil::files_equal(path, backup_path).map_err(AppError::Io)? {
+        if fileutil::num_links(&metadata) > 1 {
+            Ok(RestoreOp::ReplaceHardlink)
+        } else {
+            Ok(RestoreOp::NoOp)
+        }
+    } else if force {
+        Ok(RestoreOp::Overwrite)
+    } else {
+        Err(AppError::Fs(format!(
+            "Current contents of the file differ from the backup (use --force to overwrite): {}",
+            path.display()
+        )))
+    }
+}
+
+/// Finds files in the `backup_dir` and determines how each of them
+/// needs to be restored under the `rootdir`
+///
+/// The files are expected under the `BACKUP_FILES_DIR` sub dir of
+/// the backup dir, preserving the directory structure relative to
+/// the rootdir (which is how the backups are taken by the `apply`
+/// command).
+fn plan(backup_dir: &Path, rootdir: &Path, force: bool) -> Result<Vec<Restore>, AppError> {
+    let files_dir = backup_dir.join(BACKUP_FILES_DIR);
+    let mut backup_paths = Vec::new();
+    if files_dir.is_dir() {
+        backed_up_files(&files_dir, &mut backup_paths).map_err(AppError::Io)?;
+    }
+    backup_paths.sort();
+    let mut restores = Vec::with_capacity(backup_paths.len());
+    for backup_path in backup_paths {
+        // Unwrap is fine as the path has been obtained by
+        // traversing the backup dir
+        let rel_path = backup_path.strip_prefix(&files_dir).unwrap();
+        let path = rootdir.join(rel_path);
+        let op = restore_op(&backup_path, &path, force)?;
+        restores.push(Restore {
+            backup_path,
+            path,
+            op,
+        });
+    }
+    Ok(restores)
+}
+
+/// Restores files from the `backup_dir` to their original locations
+/// under the `rootdir`
+///
+/// All files are checked before restoring any of them, so that the
+/// restoration doesn't fail midway if the current contents of some
+/// file differ from the backup (unless `force` is true).
+pub fn undo(
+    backup_dir: &Path,
+    rootdir: &Path,
+    dry_run: &bool,
+    force: &bool,
+    confirm: impl FnOnce() -> bool,
+) -> Result<(), AppError> {
+    if !backup_dir.is_dir() {
+        return Err(AppError::Cmd(format!(
+            "Backup dir doesn't exist: {}",
+            backup_dir.display()
+        )));
+    }
+    let restores = plan(backup_dir, rootdir, *force)?;
+    let num_pending = restores.iter().filter(|r| r.op != RestoreOp::NoOp).count();
+    info!("Restoring {} file(s) with dry_run={}", num_pending, dry_run);
+    if *dry_run {
+        for restore in restores.iter() {
+            restore.dry_run(rootdir);
+        }
+        eprintln!("[DRY RUN] {} file(s) will be restored", num_pending);
+    } else if num_pending == 0 {
+        eprintln!("No files to be restored");
+    } else if confirm() {
+        for restore in restores.iter() {
+            restore.execute(rootdir)?;
+        }
+        eprintln!("{} file(s) have been restored", num_pending);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::MANIFEST_FILE;
+    use serial_test::serial;
+
+    const TEST_DATA_DIR: &str = ".tmp-test-data";
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    fn test_undo() {
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap_or(());
+        fs::create_dir(TEST_DATA_DIR).expect("Couldn't create TEST_DATA_DIR");
+        let data_dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        let backup_dir = data_dir.join("backup");
+        let rootdir = data_dir.join("root");
+        for p in ["a/1.txt", "b/2.txt", "c/3.txt", "d/4.txt", "e/5.txt"] {
+            let path = backup_dir.join(BACKUP_FILES_DIR).join(p);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "ONE").unwrap();
+        }
+        fs::write(backup_dir.join(MANIFEST_FILE), "").unwrap();
+        fs::create_dir_all(rootdir.join("b")).unwrap();
+        fs::create_dir_all(rootdir.join("c")).unwrap();
+        fs::create_dir_all(rootdir.join("d")).unwrap();
+        fs::create_dir_all(rootdir.join("e")).unwrap();
+        // a/1.txt was deleted whereas b/2.txt was replaced with a
+        // symlink
+        fs::write(rootdir.join("keeper.txt"), "ONE").unwrap();
+        std::os::unix::fs::symlink("../keeper.txt", rootdir.join("b/2.txt")).unwrap();
+        // c/3.txt is intact
+        fs::write(rootdir.join("c/3.txt"), "ONE").unwrap();
+        // d/4.txt was modified after apply
+        fs::write(rootdir.join("d/4.txt"), "TWO").unwrap();
+        // e/5.txt was replaced with a hardlink
+        fs::hard_link(rootdir.join("keeper.txt"), rootdir.join("e/5.txt")).unwrap();
+
+        // Modified file is not overwritten without force
+        assert!(plan(&backup_dir, &rootdir, false).is_err());
+        let ops = plan(&backup_dir, &rootdir, true)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.op)
+            .collect::<Vec<RestoreOp>>();
+        assert_eq!(
+            vec![
+                RestoreOp::Create,
+                RestoreOp::ReplaceSymlink,
+                RestoreOp::NoOp,
+                RestoreOp::Overwrite,
+                RestoreOp::ReplaceHardlink,
+            ],
+            ops
+        );
+
+        // Nothing is restored if not confirmed
+        undo(&backup_dir, &rootdir, &false, &true, || false).unwrap();
+        assert!(!rootdir.join("a/1.txt").exists());
+
+        undo(&backup_dir, &rootdir, &false, &true, || true).unwrap();
+        for p in ["a/1.txt", "b/2.txt", "c/3.txt", "d/4.txt", "e/5.txt"] {
+            let path = rootdir.join(p);
+            assert!(!path.is_symlink());
+            assert_eq!(1, fileutil::num_links(&path.metadata().unwrap()));
+            assert_eq!("ONE", fs::read_to_string(&path).unwrap());
+        }
+        // Source of the symlink and hardlink is not affected
+        assert_eq!(
+            "ONE",
+            fs::read_to_string(rootdir.join("keeper.txt")).unwrap()
+        );
+
+        fs::remove_dir_all(TEST_DATA_DIR).unwrap();
+    }
+}
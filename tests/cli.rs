@@ -0,0 +1,28 @@
+use std::process::{Command, Output, Stdio};
+
+/// Runs the binary with the args, with stdin not connected to a
+/// terminal
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dupenukem"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("Couldn't run the binary")
+}
+
+#[test]
+fn test_cmd_error_exit_code() {
+    // Neither snapshot path nor '--stdin' is specified
+    let output = run(&["validate"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Command Error:"));
+}
+
+#[test]
+fn test_apply_without_terminal() {
+    // Confirmation can't be asked for, hence the changes are not
+    // applied unless '--yes' is specified
+    let output = run(&["apply", "snapshot.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Use '--yes'"));
+}